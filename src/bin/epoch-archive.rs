@@ -0,0 +1,161 @@
+//! Backs up EventStoreDB categories to newline delimited JSON archives and restores them
+//!
+//! ```text
+//! epoch-archive --esdb esdb://localhost:2113?tls=false export --category user --out users.ndjson
+//! epoch-archive --esdb esdb://localhost:2113?tls=false import --in users.ndjson
+//! ```
+
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, Subcommand};
+use epoch::repository::{
+    archive::{
+        export_category, export_stream, import_archive, list_categories, ArchiveReader,
+        ArchiveReport, ArchiveWriter, RawEvent,
+    },
+    esdb::ESDBEventRepository,
+};
+use eventstore::Client;
+
+#[derive(Parser)]
+#[command(name = "epoch-archive", version, about = "Event store backups")]
+struct Cli {
+    /// EventStoreDB connection string
+    #[arg(long, env = "ESDB_CONNECTION_STRING")]
+    esdb: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Writes a stream, a category or every category to an archive
+    Export {
+        /// Category to export - every category when left out
+        #[arg(long)]
+        category: Option<String>,
+        /// Single stream of the category to export
+        #[arg(long, requires = "category")]
+        stream: Option<String>,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Appends the streams of an archive - streams that already exist are refused
+    Import {
+        #[arg(long = "in")]
+        input: PathBuf,
+        /// Category of the archive to import - every category when left out
+        #[arg(long)]
+        category: Option<String>,
+    },
+}
+
+fn open_archive(path: &Path) -> Result<ArchiveReader<BufReader<File>>, Box<dyn Error>> {
+    Ok(ArchiveReader::new(BufReader::new(File::open(path)?))?)
+}
+
+async fn export(
+    client: &Client,
+    category: Option<String>,
+    stream: Option<String>,
+    out: &Path,
+) -> Result<ArchiveReport, Box<dyn Error>> {
+    let categories = match category {
+        Some(category) => vec![category],
+        None => list_categories(&ESDBEventRepository::<RawEvent>::new(client, "")).await?,
+    };
+
+    let mut archive = ArchiveWriter::new(BufWriter::new(File::create(out)?))?;
+    let mut report = ArchiveReport::default();
+
+    for category in categories {
+        let repository = ESDBEventRepository::<RawEvent>::new(client, &category);
+
+        match &stream {
+            Some(stream) => {
+                let events = export_stream(&repository, &category, stream, &mut archive).await?;
+                report.streams += usize::from(events > 0);
+                report.events += events;
+            }
+            None => {
+                let exported = export_category(&repository, &category, &mut archive).await?;
+                report.streams += exported.streams;
+                report.events += exported.events;
+            }
+        }
+    }
+
+    archive.finish()?;
+
+    Ok(report)
+}
+
+async fn import(
+    client: &Client,
+    category: Option<String>,
+    input: &Path,
+) -> Result<ArchiveReport, Box<dyn Error>> {
+    // Each category goes to its own repository, so the archive is read once per category
+    let mut categories = vec![];
+    let mut seen = HashSet::new();
+    for stream in open_archive(input)? {
+        let stream = stream?;
+        if (category.is_none() || category.as_ref() == Some(&stream.category))
+            && seen.insert(stream.category.to_owned())
+        {
+            categories.push(stream.category);
+        }
+    }
+
+    let mut report = ArchiveReport::default();
+
+    for category in categories {
+        let mut repository = ESDBEventRepository::<RawEvent>::new(client, &category);
+        let imported =
+            import_archive(open_archive(input)?, Some(&category), &mut repository).await?;
+
+        report.streams += imported.streams;
+        report.events += imported.events;
+    }
+
+    Ok(report)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let client = Client::new(cli.esdb.parse()?)?;
+
+    match cli.command {
+        Command::Export {
+            category,
+            stream,
+            out,
+        } => {
+            let report = export(&client, category, stream, &out).await?;
+            println!(
+                "Exported {} events from {} streams to {}",
+                report.events,
+                report.streams,
+                out.display()
+            );
+        }
+        Command::Import { input, category } => {
+            let report = import(&client, category, &input).await?;
+            println!(
+                "Imported {} events into {} streams from {}",
+                report.events,
+                report.streams,
+                input.display()
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use epoch::{
+    decider::Event,
+    repository::{
+        archive::{list_categories, to_millis, RawEvent},
+        esdb::ESDBEventRepository,
+        event::{
+            StreamInfoRepository, StreamListingRepository, StreamMetadataRepository,
+            StreamingEventRepository,
+        },
+        RepositoryVersion,
+    },
+};
+use eventstore::Client;
+use futures::TryStreamExt;
+
+use super::{CliResult, Inspector, Row};
+
+pub struct EsdbInspector {
+    client: Client,
+}
+
+impl EsdbInspector {
+    pub fn connect(connection_string: &str) -> CliResult<Self> {
+        Ok(Self {
+            client: Client::new(connection_string.parse()?)?,
+        })
+    }
+
+    fn repository(&self, category: &str) -> ESDBEventRepository<RawEvent> {
+        ESDBEventRepository::new(&self.client, category)
+    }
+}
+
+#[async_trait(?Send)]
+impl Inspector for EsdbInspector {
+    async fn categories(&self) -> CliResult<Vec<String>> {
+        Ok(list_categories(&self.repository("")).await?)
+    }
+
+    async fn streams(&self, category: &str) -> CliResult<Vec<String>> {
+        Ok(self.repository(category).list_streams().await?)
+    }
+
+    async fn events(
+        &self,
+        category: &str,
+        stream: Option<&str>,
+        after: Option<&str>,
+    ) -> CliResult<Vec<Row>> {
+        let from = match after {
+            Some(after) => RepositoryVersion::Exact(after.parse::<usize>()? + 1),
+            None => RepositoryVersion::Any,
+        };
+
+        let repository = self.repository(category);
+        let recorded: Vec<_> = repository
+            .stream_from_version(from, stream.map(str::to_owned))
+            .try_collect()
+            .await?;
+
+        Ok(recorded
+            .into_iter()
+            .map(|recorded| Row {
+                version: recorded.version.to_string(),
+                recorded_at: recorded.recorded_at.and_then(to_millis),
+                event_type: recorded.event.event_type(),
+                payload: recorded.event.0.to_string(),
+            })
+            .collect())
+    }
+
+    async fn info(&self, category: &str, stream: Option<&str>) -> CliResult<Vec<(String, String)>> {
+        let repository = self.repository(category);
+        let stream = stream.map(str::to_owned);
+        let info = repository.stream_info(stream.as_ref()).await?;
+
+        let mut fields = vec![
+            ("version".to_owned(), format!("{:?}", info.version)),
+            ("events".to_owned(), info.event_count.to_string()),
+            ("deleted".to_owned(), info.deleted.to_string()),
+        ];
+
+        for (field, recorded_at) in [
+            ("first recorded", info.first_recorded_at),
+            ("last recorded", info.last_recorded_at),
+        ] {
+            if let Some(millis) = recorded_at.and_then(to_millis) {
+                fields.push((field.to_owned(), super::format_millis(millis)));
+            }
+        }
+
+        if let Some(stream) = &stream {
+            let metadata = repository.get_metadata(stream).await?;
+            fields.push(("metadata".to_owned(), format!("{:?}", metadata)));
+        }
+
+        Ok(fields)
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::HashSet, fs::File, io::BufReader, path::Path};
+
+use async_trait::async_trait;
+use epoch::repository::archive::{ArchiveReader, ArchivedStream};
+use serde_json::Value;
+
+use super::{format_millis, CliResult, Inspector, Row};
+
+/// Reads an archive written by `epoch-archive export` - versions are those of the exported store
+pub struct FileInspector {
+    streams: Vec<ArchivedStream>,
+}
+
+impl FileInspector {
+    pub fn open(path: &Path) -> CliResult<Self> {
+        Ok(Self {
+            streams: ArchiveReader::new(BufReader::new(File::open(path)?))?
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn in_category<'s>(&'s self, category: &'s str) -> impl Iterator<Item = &'s ArchivedStream> {
+        self.streams
+            .iter()
+            .filter(move |stream| stream.category == category)
+    }
+}
+
+fn render(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_owned(),
+        other => other.to_string(),
+    }
+}
+
+#[async_trait(?Send)]
+impl Inspector for FileInspector {
+    async fn categories(&self) -> CliResult<Vec<String>> {
+        let mut seen = HashSet::new();
+
+        Ok(self
+            .streams
+            .iter()
+            .filter(|stream| seen.insert(stream.category.to_owned()))
+            .map(|stream| stream.category.to_owned())
+            .collect())
+    }
+
+    async fn streams(&self, category: &str) -> CliResult<Vec<String>> {
+        Ok(self
+            .in_category(category)
+            .map(|stream| render(&stream.stream))
+            .collect())
+    }
+
+    async fn events(
+        &self,
+        category: &str,
+        stream: Option<&str>,
+        after: Option<&str>,
+    ) -> CliResult<Vec<Row>> {
+        // Versions are per stream, so they can't place an event within a whole category
+        if after.is_some() && stream.is_none() {
+            return Err("--after needs --stream when reading an archive".into());
+        }
+
+        let rows = self
+            .in_category(category)
+            .filter(|archived| {
+                stream.is_none() || stream == Some(render(&archived.stream).as_str())
+            })
+            .flat_map(|archived| archived.events.iter())
+            .map(|event| Row {
+                version: render(&event.version),
+                recorded_at: event.recorded_at,
+                event_type: event.event_type.to_owned(),
+                payload: event.payload.to_string(),
+            });
+
+        Ok(match after {
+            Some(after) => rows
+                .skip_while(|row| row.version != after)
+                .skip(1)
+                .collect(),
+            None => rows.collect(),
+        })
+    }
+
+    async fn info(&self, category: &str, stream: Option<&str>) -> CliResult<Vec<(String, String)>> {
+        let streams: Vec<_> = self
+            .in_category(category)
+            .filter(|archived| {
+                stream.is_none() || stream == Some(render(&archived.stream).as_str())
+            })
+            .collect();
+
+        if streams.is_empty() {
+            return Err("no such stream in the archive".into());
+        }
+
+        let events: Vec<_> = streams.iter().flat_map(|s| s.events.iter()).collect();
+        let mut fields = vec![
+            ("streams".to_owned(), streams.len().to_string()),
+            ("events".to_owned(), events.len().to_string()),
+        ];
+
+        if let [archived] = streams.as_slice() {
+            fields.insert(0, ("version".to_owned(), render(&archived.version)));
+        }
+
+        let recorded: Vec<_> = events.iter().filter_map(|e| e.recorded_at).collect();
+        if let (Some(first), Some(last)) = (recorded.iter().min(), recorded.iter().max()) {
+            fields.push(("first recorded".to_owned(), format_millis(*first)));
+            fields.push(("last recorded".to_owned(), format_millis(*last)));
+        }
+
+        Ok(fields)
+    }
+
+    fn can_tail(&self) -> bool {
+        false
+    }
+}
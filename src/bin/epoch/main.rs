@@ -0,0 +1,221 @@
+//! Inspects an event store - lists categories and streams, prints and tails events and shows stream info
+//!
+//! ```text
+//! epoch --esdb esdb://localhost:2113?tls=false streams user
+//! epoch --esdb esdb://localhost:2113?tls=false events user --stream 42
+//! epoch --redis redis://localhost:6379 tail user_events
+//! epoch --redis redis://localhost:6379 --redis-stream-field user_id events user_events --stream 42
+//! epoch --file users.ndjson info user --stream 42
+//! ```
+
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use async_trait::async_trait;
+use clap::{ArgGroup, Parser, Subcommand};
+
+use self::{esdb::EsdbInspector, file::FileInspector, redis::RedisInspector};
+
+mod esdb;
+mod file;
+mod redis;
+
+pub type CliResult<T> = Result<T, Box<dyn Error>>;
+
+/// Events printed by `tail` before it starts following the stream
+const TAIL_BACKLOG: usize = 10;
+
+#[derive(Parser)]
+#[command(name = "epoch", version, about = "Event store inspector")]
+#[command(group(ArgGroup::new("backend").required(true).args(["esdb", "redis", "file"])))]
+struct Cli {
+    /// EventStoreDB connection string
+    #[arg(long, env = "EPOCH_ESDB")]
+    esdb: Option<String>,
+    /// Redis connection url
+    #[arg(long, env = "EPOCH_REDIS")]
+    redis: Option<String>,
+    /// Redis entry field holding the sub stream id, which `--stream` and `streams` match on
+    #[arg(long, env = "EPOCH_REDIS_STREAM_FIELD", requires = "redis")]
+    redis_stream_field: Option<String>,
+    /// Archive written by `epoch-archive export`
+    #[arg(long)]
+    file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists the categories holding events
+    Categories,
+    /// Lists the streams of a category
+    Streams { category: String },
+    /// Prints the events of a stream, or of the whole category, with their versions
+    Events {
+        category: String,
+        #[arg(long)]
+        stream: Option<String>,
+        /// Only prints events after this version - archives need `--stream` with it
+        #[arg(long)]
+        after: Option<String>,
+    },
+    /// Prints the latest events, then new events as they are appended
+    Tail {
+        category: String,
+        #[arg(long)]
+        stream: Option<String>,
+        /// How often to poll for new events
+        #[arg(long, default_value_t = 1000)]
+        interval_ms: u64,
+    },
+    /// Shows the version, event count and metadata of a stream, or of the whole category
+    Info {
+        category: String,
+        #[arg(long)]
+        stream: Option<String>,
+    },
+}
+
+/// An event as printed by the inspector
+pub struct Row {
+    pub version: String,
+    /// Milliseconds since the unix epoch
+    pub recorded_at: Option<u64>,
+    pub event_type: String,
+    pub payload: String,
+}
+
+/// What the inspector needs from a backend
+#[async_trait(?Send)]
+pub trait Inspector {
+    async fn categories(&self) -> CliResult<Vec<String>>;
+
+    async fn streams(&self, category: &str) -> CliResult<Vec<String>>;
+
+    /// Events after version `after` (exclusive), from the start when `None`
+    async fn events(
+        &self,
+        category: &str,
+        stream: Option<&str>,
+        after: Option<&str>,
+    ) -> CliResult<Vec<Row>>;
+
+    async fn info(&self, category: &str, stream: Option<&str>) -> CliResult<Vec<(String, String)>>;
+
+    fn can_tail(&self) -> bool {
+        true
+    }
+}
+
+/// `yyyy-mm-ddThh:mm:ss.sssZ` without pulling in a date time crate
+pub fn format_millis(millis: u64) -> String {
+    let (days, ms_of_day) = (millis / 86_400_000, millis % 86_400_000);
+
+    // Civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1_000 % 60,
+        ms_of_day % 1_000
+    )
+}
+
+fn print_row(row: &Row) {
+    println!(
+        "{:>10}  {:<24}  {:<24}  {}",
+        row.version,
+        row.recorded_at.map_or("-".to_owned(), format_millis),
+        row.event_type,
+        row.payload
+    );
+}
+
+async fn run(inspector: &dyn Inspector, command: Command) -> CliResult<()> {
+    match command {
+        Command::Categories => {
+            for category in inspector.categories().await? {
+                println!("{}", category);
+            }
+        }
+        Command::Streams { category } => {
+            for stream in inspector.streams(&category).await? {
+                println!("{}", stream);
+            }
+        }
+        Command::Events {
+            category,
+            stream,
+            after,
+        } => {
+            for row in inspector
+                .events(&category, stream.as_deref(), after.as_deref())
+                .await?
+            {
+                print_row(&row);
+            }
+        }
+        Command::Tail {
+            category,
+            stream,
+            interval_ms,
+        } => {
+            if !inspector.can_tail() {
+                return Err("this backend cannot be tailed".into());
+            }
+
+            let rows = inspector.events(&category, stream.as_deref(), None).await?;
+            rows.iter()
+                .skip(rows.len().saturating_sub(TAIL_BACKLOG))
+                .for_each(print_row);
+            let mut after = rows.last().map(|row| row.version.to_owned());
+
+            loop {
+                tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+
+                let rows = inspector
+                    .events(&category, stream.as_deref(), after.as_deref())
+                    .await?;
+                rows.iter().for_each(print_row);
+
+                if let Some(last) = rows.last() {
+                    after = Some(last.version.to_owned());
+                }
+            }
+        }
+        Command::Info { category, stream } => {
+            for (field, value) in inspector.info(&category, stream.as_deref()).await? {
+                println!("{:<16} {}", field, value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> CliResult<()> {
+    let cli = Cli::parse();
+
+    let inspector: Box<dyn Inspector> = match (cli.esdb, cli.redis, cli.file) {
+        (Some(esdb), _, _) => Box::new(EsdbInspector::connect(&esdb)?),
+        (_, Some(redis), _) => Box::new(RedisInspector::connect(&redis, cli.redis_stream_field)?),
+        (_, _, Some(file)) => Box::new(FileInspector::open(&file)?),
+        _ => unreachable!("clap requires a backend"),
+    };
+
+    run(inspector.as_ref(), cli.command).await
+}
@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use async_trait::async_trait;
+use epoch::repository::redis::{versioned_event::SOFT_DELETED_THROUGH, RedisVersion};
+use redis_om::redis::{
+    self,
+    aio::MultiplexedConnection,
+    streams::{StreamId, StreamInfoStreamReply, StreamRangeReply},
+};
+
+use super::{CliResult, Inspector, Row};
+
+/// Entries read per XRANGE call
+const PAGE_SIZE: usize = 1000;
+
+const SUB_STREAMS_UNKNOWN: &str =
+    "redis sub streams need the entry field holding their id, pass --redis-stream-field";
+
+/// Reads redis streams directly, so a category here is a whole redis stream and its entries are printed field
+/// by field
+///
+/// Sub streams are defined by the application's `StreamModel` DTO - with `stream_field` naming the DTO field that
+/// holds the sub stream id, entries are matched on it like `WithFineGrainedStreamId` does. Entries of hard deleted
+/// sub streams and the ones hidden by soft deletes are then left out, as `RedisStreamsEventRepository` reads do
+pub struct RedisInspector {
+    client: redis::Client,
+    stream_field: Option<String>,
+}
+
+impl RedisInspector {
+    pub fn connect(url: &str, stream_field: Option<String>) -> CliResult<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            stream_field,
+        })
+    }
+
+    async fn connection(&self) -> CliResult<MultiplexedConnection> {
+        Ok(self.client.get_multiplexed_async_connection().await?)
+    }
+
+    fn stream_field(&self) -> CliResult<&str> {
+        self.stream_field
+            .as_deref()
+            .ok_or_else(|| SUB_STREAMS_UNKNOWN.into())
+    }
+
+    /// Entries after id `after` (exclusive) of the redis stream, of the sub stream `stream` when given
+    ///
+    /// Without a stream field every entry is returned, since none can be told apart
+    async fn entries(
+        &self,
+        category: &str,
+        stream: Option<&str>,
+        after: Option<&str>,
+    ) -> CliResult<Vec<StreamId>> {
+        let field = match stream {
+            Some(_) => Some(self.stream_field()?),
+            None => self.stream_field.as_deref(),
+        };
+
+        let mut conn = self.connection().await?;
+
+        if let Some(stream) = stream {
+            if is_hard_deleted(&mut conn, category, stream).await? {
+                return Err(format!("sub stream {} was hard deleted", stream).into());
+            }
+        }
+
+        let mut after = after.map(str::to_owned);
+        let mut hidden_through = HashMap::new();
+        let mut entries = vec![];
+
+        loop {
+            // Exclusive range start - requires redis 6.2+
+            let start = after
+                .as_ref()
+                .map_or("-".to_owned(), |id| format!("({}", id));
+
+            let reply: StreamRangeReply = redis::cmd("XRANGE")
+                .arg(category)
+                .arg(start)
+                .arg("+")
+                .arg("COUNT")
+                .arg(PAGE_SIZE)
+                .query_async(&mut conn)
+                .await?;
+
+            let page = reply.ids.len();
+            after = reply.ids.last().map(|entry| entry.id.to_owned());
+
+            for entry in reply.ids {
+                let sub_stream = match field {
+                    Some(field) => match field_value(&entry, field) {
+                        Some(sub_stream) => sub_stream,
+                        None => continue,
+                    },
+                    None => {
+                        entries.push(entry);
+                        continue;
+                    }
+                };
+
+                if stream.is_some_and(|stream| stream != sub_stream) {
+                    continue;
+                }
+
+                if !hidden_through.contains_key(&sub_stream) {
+                    let through = hidden_through_of(&mut conn, category, &sub_stream).await?;
+                    hidden_through.insert(sub_stream.to_owned(), through);
+                }
+
+                if is_visible(&entry, &hidden_through[&sub_stream]) {
+                    entries.push(entry);
+                }
+            }
+
+            if page < PAGE_SIZE {
+                return Ok(entries);
+            }
+        }
+    }
+}
+
+/// Set of hard deleted sub stream ids, as `RedisStreamsEventRepository` keeps it next to the redis stream
+fn tombstones_key(category: &str) -> String {
+    format!("{}:tombstones", category)
+}
+
+/// Hash of a sub stream's metadata and soft delete marker, as `RedisStreamsEventRepository` keeps it
+fn metadata_key(category: &str, stream: &str) -> String {
+    format!("{}:metadata:{}", category, stream)
+}
+
+async fn is_hard_deleted(
+    conn: &mut MultiplexedConnection,
+    category: &str,
+    stream: &str,
+) -> CliResult<bool> {
+    Ok(redis::cmd("SISMEMBER")
+        .arg(tombstones_key(category))
+        .arg(stream)
+        .query_async(conn)
+        .await?)
+}
+
+/// What a sub stream's entries are hidden by - `Hidden::All` once it was hard deleted
+enum Hidden {
+    All,
+    Through(Option<RedisVersion>),
+}
+
+async fn hidden_through_of(
+    conn: &mut MultiplexedConnection,
+    category: &str,
+    stream: &str,
+) -> CliResult<Hidden> {
+    if is_hard_deleted(conn, category, stream).await? {
+        return Ok(Hidden::All);
+    }
+
+    let through: Option<String> = redis::cmd("HGET")
+        .arg(metadata_key(category, stream))
+        .arg(SOFT_DELETED_THROUGH)
+        .query_async(conn)
+        .await?;
+
+    Ok(Hidden::Through(
+        through
+            .map(|through| RedisVersion::try_from(through.as_str()))
+            .transpose()?,
+    ))
+}
+
+fn is_visible(entry: &StreamId, hidden: &Hidden) -> bool {
+    match hidden {
+        Hidden::All => false,
+        Hidden::Through(None) => true,
+        Hidden::Through(Some(through)) => {
+            RedisVersion::try_from(entry.id.as_str()).map_or(true, |version| version > *through)
+        }
+    }
+}
+
+fn field_value(entry: &StreamId, field: &str) -> Option<String> {
+    entry
+        .map
+        .get(field)
+        .and_then(|value| redis::from_redis_value::<String>(value).ok())
+}
+
+#[async_trait(?Send)]
+impl Inspector for RedisInspector {
+    async fn categories(&self) -> CliResult<Vec<String>> {
+        let mut conn = self.connection().await?;
+        let mut cursor = 0u64;
+        let mut keys = vec![];
+
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("TYPE")
+                .arg("stream")
+                .query_async(&mut conn)
+                .await?;
+            keys.extend(batch);
+
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Sub streams with entries left to read - hard deleted and fully soft deleted ones are left out
+    async fn streams(&self, category: &str) -> CliResult<Vec<String>> {
+        let field = self.stream_field()?;
+        let mut seen = HashSet::new();
+
+        Ok(self
+            .entries(category, None, None)
+            .await?
+            .iter()
+            .filter_map(|entry| field_value(entry, field))
+            .filter(|stream| seen.insert(stream.to_owned()))
+            .collect())
+    }
+
+    async fn events(
+        &self,
+        category: &str,
+        stream: Option<&str>,
+        after: Option<&str>,
+    ) -> CliResult<Vec<Row>> {
+        Ok(self
+            .entries(category, stream, after)
+            .await?
+            .into_iter()
+            .map(|entry| {
+                let fields: BTreeMap<_, _> = entry
+                    .map
+                    .iter()
+                    .map(|(field, value)| {
+                        let value = redis::from_redis_value::<String>(value)
+                            .unwrap_or_else(|_| format!("{:?}", value));
+                        (field.to_owned(), value)
+                    })
+                    .collect();
+
+                Row {
+                    // Entry ids start with the millisecond they were added at
+                    recorded_at: entry.id.split('-').next().and_then(|ms| ms.parse().ok()),
+                    version: entry.id,
+                    event_type: "-".to_owned(),
+                    payload: fields
+                        .into_iter()
+                        .map(|(field, value)| format!("{}={}", field, value))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                }
+            })
+            .collect())
+    }
+
+    async fn info(&self, category: &str, stream: Option<&str>) -> CliResult<Vec<(String, String)>> {
+        let mut conn = self.connection().await?;
+
+        if let Some(stream) = stream {
+            if is_hard_deleted(&mut conn, category, stream).await? {
+                return Ok(vec![("deleted".to_owned(), "hard".to_owned())]);
+            }
+
+            let metadata: BTreeMap<String, String> = redis::cmd("HGETALL")
+                .arg(metadata_key(category, stream))
+                .query_async(&mut conn)
+                .await?;
+            let entries = self.entries(category, Some(stream), None).await?;
+
+            let mut rows = match (entries.first(), entries.last()) {
+                (Some(first), Some(last)) => vec![
+                    ("version".to_owned(), last.id.to_owned()),
+                    ("events".to_owned(), entries.len().to_string()),
+                    ("first entry".to_owned(), first.id.to_owned()),
+                    ("last entry".to_owned(), last.id.to_owned()),
+                ],
+                _ if metadata.contains_key(SOFT_DELETED_THROUGH) => {
+                    vec![("events".to_owned(), "0".to_owned())]
+                }
+                _ => return Err("no such sub stream".into()),
+            };
+
+            rows.extend(
+                metadata
+                    .into_iter()
+                    .map(|(field, value)| (format!("metadata {}", field), value)),
+            );
+
+            return Ok(rows);
+        }
+
+        let info: StreamInfoStreamReply = redis::cmd("XINFO")
+            .arg("STREAM")
+            .arg(category)
+            .query_async(&mut conn)
+            .await?;
+        let hard_deleted: usize = redis::cmd("SCARD")
+            .arg(tombstones_key(category))
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(vec![
+            ("version".to_owned(), info.last_generated_id),
+            ("entries".to_owned(), info.length.to_string()),
+            ("first entry".to_owned(), info.first_entry.id),
+            ("last entry".to_owned(), info.last_entry.id),
+            ("groups".to_owned(), info.groups.to_string()),
+            (
+                "hard deleted sub streams".to_owned(),
+                hard_deleted.to_string(),
+            ),
+        ])
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt::Debug;
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("JSON error {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Could not encode payload: {0}")]
+    Encode(String),
+    #[error("Could not decode payload: {0}")]
+    Decode(String),
+    #[error("No codec for content type {0}")]
+    UnknownContentType(String),
+}
+
+/// Serialization format for stored events
+///
+/// Payloads are stored together with the codec's `content_type` so a reader can tell which codec wrote them
+///
+/// The ESDB repository takes a codec directly. Redis entries are hash fields written by `StreamModelDTO`
+/// conversions, so a Redis repository stores codec encoded events through `CompressedEventDTO` with a
+/// `CompressedEventRepository` around it, which tags each payload with its content type as well
+pub trait Codec: Debug + Clone + Send + Sync {
+    fn content_type(&self) -> &'static str;
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError>;
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, CodecError>;
+
+    /// Decodes a payload tagged with `content_type` - payloads written by another codec are decoded with the
+    /// built in codec the tag names, so switching codecs keeps older events readable
+    fn decode_tagged<T: DeserializeOwned>(
+        &self,
+        content_type: &str,
+        payload: &[u8],
+    ) -> Result<T, CodecError> {
+        if content_type == self.content_type() {
+            self.decode(payload)
+        } else {
+            decode_by_content_type(content_type, payload)
+        }
+    }
+}
+
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+pub const CBOR_CONTENT_TYPE: &str = "application/cbor";
+pub const BINCODE_CONTENT_TYPE: &str = "application/x-bincode";
+
+/// Decodes with the built in codec `content_type` names - codecs behind a disabled feature are unknown
+pub fn decode_by_content_type<T: DeserializeOwned>(
+    content_type: &str,
+    payload: &[u8],
+) -> Result<T, CodecError> {
+    match content_type {
+        JSON_CONTENT_TYPE => JsonCodec.decode(payload),
+        #[cfg(feature = "msgpack")]
+        MSGPACK_CONTENT_TYPE => MessagePackCodec.decode(payload),
+        #[cfg(feature = "cbor")]
+        CBOR_CONTENT_TYPE => CborCodec.decode(payload),
+        #[cfg(feature = "bincode")]
+        BINCODE_CONTENT_TYPE => BincodeCodec.decode(payload),
+        other => Err(CodecError::UnknownContentType(other.to_owned())),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn content_type(&self) -> &'static str {
+        JSON_CONTENT_TYPE
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, CodecError> {
+        Ok(serde_json::from_slice(payload)?)
+    }
+}
+
+/// MessagePack with field names kept, so payloads survive reordered struct fields
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePackCodec {
+    fn content_type(&self) -> &'static str {
+        MSGPACK_CONTENT_TYPE
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        rmp_serde::to_vec_named(value).map_err(|e| CodecError::Encode(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, CodecError> {
+        rmp_serde::from_slice(payload).map_err(|e| CodecError::Decode(e.to_string()))
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn content_type(&self) -> &'static str {
+        CBOR_CONTENT_TYPE
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        let mut payload = vec![];
+        ciborium::ser::into_writer(value, &mut payload)
+            .map_err(|e| CodecError::Encode(e.to_string()))?;
+
+        Ok(payload)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, CodecError> {
+        ciborium::de::from_reader(payload).map_err(|e| CodecError::Decode(e.to_string()))
+    }
+}
+
+/// Smallest payloads, but not self describing - events using `#[serde(untagged)]` or `#[serde(flatten)]` will
+/// not decode
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl Codec for BincodeCodec {
+    fn content_type(&self) -> &'static str {
+        BINCODE_CONTENT_TYPE
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        bincode::serialize(value).map_err(|e| CodecError::Encode(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, CodecError> {
+        bincode::deserialize(payload).map_err(|e| CodecError::Decode(e.to_string()))
+    }
+}
+
+/// Serde helper for byte fields - base64 strings in human readable formats such as JSON instead of arrays of
+/// numbers, raw bytes in binary formats
+///
+/// Use with `#[serde(with = "crate::codec::bytes")]`
+pub mod bytes {
+    use std::fmt;
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{
+        de::{Error, SeqAccess, Visitor},
+        Deserializer, Serializer,
+    };
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&STANDARD.encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(BytesVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    /// Also takes arrays of numbers, so payloads written before base64 still load
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("bytes or a base64 string")
+        }
+
+        fn visit_str<E: Error>(self, v: &str) -> Result<Vec<u8>, E> {
+            STANDARD.decode(v).map_err(E::custom)
+        }
+
+        fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+
+            Ok(bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::test_helpers::deciders::user::{User, UserEvent, UserName};
+
+    use super::{Codec, CodecError, JsonCodec};
+
+    fn assert_round_trip(codec: impl Codec) {
+        let event = UserEvent::UserAdded(User::new(1, UserName::try_from("Mike").unwrap()));
+
+        let payload = codec.encode(&event).unwrap();
+        let decoded: UserEvent = codec.decode_tagged(codec.content_type(), &payload).unwrap();
+
+        assert_eq!(decoded, event);
+    }
+
+    #[test]
+    fn codecs_round_trip() {
+        assert_round_trip(JsonCodec);
+        #[cfg(feature = "msgpack")]
+        assert_round_trip(super::MessagePackCodec);
+        #[cfg(feature = "cbor")]
+        assert_round_trip(super::CborCodec);
+        #[cfg(feature = "bincode")]
+        assert_round_trip(super::BincodeCodec);
+    }
+
+    #[test]
+    fn refuses_unknown_content_types() {
+        let payload = JsonCodec.encode(&1).unwrap();
+
+        assert_matches!(
+            JsonCodec.decode_tagged::<usize>("application/x-unknown", &payload),
+            Err(CodecError::UnknownContentType(_))
+        );
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn decodes_by_content_type_tag() {
+        let payload = super::CborCodec.encode(&1).unwrap();
+
+        assert_eq!(
+            JsonCodec
+                .decode_tagged::<usize>(super::CBOR_CONTENT_TYPE, &payload)
+                .unwrap(),
+            1
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    fmt::Debug,
+    sync::Arc,
+};
+
+use futures::future::BoxFuture;
+
+use crate::{
+    decider::{DeciderWithContext, Evolver},
+    repository::{event::StreamingEventRepository, StreamIdFromEvent},
+    strategies::{LoadDecideAppend, LoadDecideAppendError, StreamState},
+};
+
+/// Ties a command type to the decider that handles it so `CommandBus::dispatch` can route it
+pub trait Command: Send + Sync + Debug + Sized + 'static {
+    type Decide: DeciderWithContext<Cmd = Self> + 'static;
+}
+
+type BoxedAny = Box<dyn Any + Send>;
+type Handler = Box<dyn Fn(BoxedAny) -> BoxFuture<'static, BoxedAny> + Send + Sync>;
+
+pub type CommandBusResult<C> = Result<
+    Vec<<<C as Command>::Decide as Evolver>::Evt>,
+    CommandBusError<<<C as Command>::Decide as DeciderWithContext>::Err>,
+>;
+
+/// Routes commands to registered deciders and runs them through `LoadDecideAppend`
+///
+/// Each dispatch works on a clone of the registered repository, so the repository handle must share
+/// its underlying storage between clones (as the ESDB and Redis clients do)
+#[derive(Default)]
+pub struct CommandBus {
+    handlers: HashMap<TypeId, Handler>,
+    retrys: Option<u32>,
+}
+
+impl CommandBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_retrys(mut self, retrys: u32) -> Self {
+        self.retrys = Some(retrys);
+        self
+    }
+
+    /// Panics when a decider for the same command type is already registered
+    pub fn register<L, R, RepoErr, StreamId, V>(
+        mut self,
+        event_repository: R,
+        ctx: <L::Decide as DeciderWithContext>::Ctx,
+        route: impl Fn(&<L::Decide as DeciderWithContext>::Cmd) -> StreamState<StreamId>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self
+    where
+        L: LoadDecideAppend + Send + Sync + 'static,
+        L::Decide: 'static,
+        <L::Decide as Evolver>::State: Default + Send + Sync + Debug,
+        <L::Decide as Evolver>::Evt: Clone + Send + Sync + Debug + 'static,
+        <L::Decide as DeciderWithContext>::Ctx: Send + Sync + Debug + 'static,
+        <L::Decide as DeciderWithContext>::Cmd: Send + Sync + Debug + 'static,
+        <L::Decide as DeciderWithContext>::Err: Send + Sync + Debug + 'static,
+        R: StreamingEventRepository<
+                'static,
+                <L::Decide as Evolver>::Evt,
+                RepoErr,
+                StreamId = StreamId,
+                Version = V,
+            > + Clone
+            + Send
+            + Sync
+            + 'static,
+        RepoErr: Debug + Send + Sync + 'static,
+        StreamId: Send + Sync + Clone + StreamIdFromEvent<<L::Decide as Evolver>::Evt> + 'static,
+        V: Clone + Ord + Send + Sync + 'static,
+    {
+        let ctx = Arc::new(ctx);
+        let route = Arc::new(route);
+        let retrys = self.retrys;
+
+        let handler: Handler = Box::new(move |cmd: BoxedAny| {
+            let mut event_repository = event_repository.clone();
+            let ctx = ctx.clone();
+            let route = route.clone();
+
+            Box::pin(async move {
+                // Handlers are keyed by decider so the command type is guaranteed to match
+                let cmd = cmd
+                    .downcast::<<L::Decide as DeciderWithContext>::Cmd>()
+                    .unwrap_or_else(|_| unreachable!("Command routed to the wrong decider"));
+                let stream_state = route(&cmd);
+
+                let res = L::execute(
+                    Default::default(),
+                    &mut event_repository,
+                    &stream_state,
+                    &ctx,
+                    &cmd,
+                    retrys,
+                )
+                .await
+                .map_err(CommandBusError::from_lda_error);
+
+                Box::new(res) as BoxedAny
+            })
+        });
+
+        if self
+            .handlers
+            .insert(TypeId::of::<L::Decide>(), handler)
+            .is_some()
+        {
+            panic!(
+                "A decider for {} is already registered on this command bus",
+                type_name::<<L::Decide as DeciderWithContext>::Cmd>()
+            );
+        }
+
+        self
+    }
+
+    pub async fn dispatch<C: Command>(&self, cmd: C) -> CommandBusResult<C>
+    where
+        <C::Decide as Evolver>::Evt: 'static,
+        <C::Decide as DeciderWithContext>::Err: Send + Sync + 'static,
+    {
+        let handler = self
+            .handlers
+            .get(&TypeId::of::<C::Decide>())
+            .ok_or(CommandBusError::Unregistered(type_name::<C>()))?;
+
+        *handler(Box::new(cmd))
+            .await
+            .downcast::<CommandBusResult<C>>()
+            .unwrap_or_else(|_| unreachable!("Decider produced a mismatched response"))
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandBusError<DecideErr: Send + Sync> {
+    Unregistered(&'static str),
+    Execute(LoadDecideAppendError<DecideErr, Box<dyn Debug + Send + Sync>>),
+}
+
+impl<DecideErr: Send + Sync> CommandBusError<DecideErr> {
+    fn from_lda_error<RepoErr: Debug + Send + Sync + 'static>(
+        err: LoadDecideAppendError<DecideErr, RepoErr>,
+    ) -> Self {
+        Self::Execute(match err {
+            LoadDecideAppendError::OccMaxRetries => LoadDecideAppendError::OccMaxRetries,
+            LoadDecideAppendError::VersionError => LoadDecideAppendError::VersionError,
+            LoadDecideAppendError::DecideErr(e) => LoadDecideAppendError::DecideErr(e),
+            LoadDecideAppendError::RepositoryErr(e) => {
+                LoadDecideAppendError::RepositoryErr(Box::new(e))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::{
+        repository::in_memory::versioned_with_streams::InMemoryEventRepository,
+        test_helpers::{
+            deciders::user::{
+                UserCommand, UserDecider, UserDeciderCtx, UserDeciderError, UserEvent,
+                UserFieldError,
+            },
+            ValueType,
+        },
+    };
+
+    use super::*;
+
+    fn route(cmd: &UserCommand) -> StreamState<String> {
+        match cmd {
+            UserCommand::AddUser(_) => StreamState::New,
+            UserCommand::UpdateUserName(id, _) | UserCommand::AddGuitar(id, _) => {
+                StreamState::Existing(id.to_string())
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn dispatch_to_registered_decider() {
+        let bus = CommandBus::new().register::<UserDecider, _, _, _, _>(
+            InMemoryEventRepository::<UserEvent>::new("test"),
+            UserDeciderCtx::new(),
+            route,
+        );
+
+        let evts = bus
+            .dispatch(UserCommand::AddUser("Mike".to_string()))
+            .await
+            .expect("command succeeds");
+
+        assert_matches!(evts.first(), Some(UserEvent::UserAdded(user)) if user.name.value() == "Mike");
+
+        let res = bus
+            .dispatch(UserCommand::UpdateUserName(
+                1,
+                "DmitiryWayToLongToSucceed".to_string(),
+            ))
+            .await;
+
+        assert_matches!(
+            res,
+            Err(CommandBusError::Execute(LoadDecideAppendError::DecideErr(
+                UserDeciderError::UserField(UserFieldError::NameToLong(_))
+            )))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "is already registered on this command bus")]
+    fn register_same_command_twice() {
+        let _ = CommandBus::new()
+            .register::<UserDecider, _, _, _, _>(
+                InMemoryEventRepository::<UserEvent>::new("test"),
+                UserDeciderCtx::new(),
+                route,
+            )
+            .register::<UserDecider, _, _, _, _>(
+                InMemoryEventRepository::<UserEvent>::new("test"),
+                UserDeciderCtx::new(),
+                route,
+            );
+    }
+
+    #[actix_rt::test]
+    async fn dispatch_unregistered_command() {
+        let bus = CommandBus::new();
+
+        let res = bus.dispatch(UserCommand::AddUser("Mike".to_string())).await;
+
+        assert_matches!(res, Err(CommandBusError::Unregistered(_)));
+    }
+}
@@ -1,3 +1,5 @@
+use async_trait::async_trait;
+
 pub trait Event {
     type EntityId;
 
@@ -5,6 +7,10 @@ pub trait Event {
     fn get_id(&self) -> Self::EntityId;
 }
 
+pub trait IdempotentCommand {
+    fn idempotency_key(&self) -> Option<String>;
+}
+
 pub trait Decider: Evolver {
     type Cmd: Send + Sync;
     type Err;
@@ -24,6 +30,22 @@ pub trait DeciderWithContext: Evolver {
     ) -> Result<Vec<Self::Evt>, Self::Err>;
 }
 
+/// `DeciderWithContext` whose context can perform I/O (uniqueness checks, pricing lookups, ...) while deciding
+///
+/// Async strategies await `decide` on every OCC attempt so lookups never go stale between retries
+#[async_trait]
+pub trait AsyncDeciderWithContext: Evolver {
+    type Ctx: std::fmt::Debug;
+    type Cmd: Send + Sync + std::fmt::Debug;
+    type Err: std::fmt::Debug;
+
+    async fn decide(
+        ctx: &Self::Ctx,
+        state: &Self::State,
+        cmd: &Self::Cmd,
+    ) -> Result<Vec<Self::Evt>, Self::Err>;
+}
+
 pub trait Evolver {
     type State;
     type Evt: Event;
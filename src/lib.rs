@@ -1,6 +1,10 @@
+pub mod codec;
+pub mod command_bus;
 pub mod decider;
 pub mod repository;
 pub mod strategies;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 #[cfg(test)]
 mod test_helpers;
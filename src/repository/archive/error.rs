@@ -0,0 +1,38 @@
+use std::fmt::Debug;
+
+use thiserror::Error;
+
+use crate::repository::VersionedRepositoryError;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("Could not read or write archive {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse archive line {line}: {source}")]
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("Could not serialize archive record {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Archive does not start with a header")]
+    MissingHeader,
+    #[error("Unsupported archive format {0}")]
+    UnsupportedFormat(u32),
+    #[error("Corrupt archive at line {line}: {reason}")]
+    Corrupt { line: usize, reason: String },
+    #[error("Event type mismatch in stream {stream}, archived {archived} but decoded {decoded}")]
+    EventTypeMismatch {
+        stream: String,
+        archived: String,
+        decoded: String,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum ArchiveTransferError<RepoErr: Debug, V: Debug> {
+    #[error("Repository error {0:?}")]
+    Repository(VersionedRepositoryError<RepoErr, V>),
+    #[error("Archive error {0}")]
+    Archive(#[from] ArchiveError),
+}
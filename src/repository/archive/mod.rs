@@ -0,0 +1,769 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    io::{BufRead, Lines, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::TryStreamExt;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::decider::Event;
+
+use super::{
+    event::{
+        AllEventsFilter, AllEventsRepository, StreamListingRepository, StreamMetadata,
+        StreamMetadataRepository, StreamingEventRepository,
+    },
+    RepositoryVersion,
+};
+
+use self::error::{ArchiveError, ArchiveTransferError};
+
+pub mod error;
+
+/// Format written by `ArchiveWriter` - format 1 archives, which count a stream's events on its stream record
+/// instead of closing it with a `stream_end` record, are still read
+pub const ARCHIVE_FORMAT: u32 = 2;
+
+/// Events are imported in batches of this size, each appended against the version the previous one returned
+pub const IMPORT_BATCH_SIZE: usize = 500;
+
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// One line of an archive - a header, then each stream followed by its events and a record closing it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum ArchiveRecord {
+    Header {
+        format: u32,
+    },
+    Stream {
+        category: String,
+        stream: Value,
+        /// Format 1 only
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<Value>,
+        /// Format 1 only
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        event_count: Option<usize>,
+        #[serde(default)]
+        metadata: ArchivedStreamMetadata,
+    },
+    Event {
+        sequence: usize,
+        version: Value,
+        event_type: String,
+        recorded_at: Option<u64>,
+        #[serde(default)]
+        metadata: HashMap<String, Value>,
+        payload: Value,
+    },
+    StreamEnd {
+        event_count: usize,
+        version: Value,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedEvent {
+    pub version: Value,
+    pub event_type: String,
+    /// Milliseconds since the unix epoch, for backends that record it
+    pub recorded_at: Option<u64>,
+    /// What the source backend stored next to the event, such as ESDB event ids and custom metadata
+    pub metadata: HashMap<String, Value>,
+    pub payload: Value,
+}
+
+/// Stream metadata as it was exported - `truncate_before` is a version of the source backend
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedStreamMetadata {
+    pub max_age_ms: Option<u64>,
+    pub max_count: Option<usize>,
+    pub truncate_before: Option<Value>,
+    pub custom: HashMap<String, Value>,
+}
+
+impl ArchivedStreamMetadata {
+    fn from_metadata<V: Serialize>(metadata: StreamMetadata<V>) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            max_age_ms: metadata.max_age.map(|max_age| max_age.as_millis() as u64),
+            max_count: metadata.max_count,
+            truncate_before: metadata
+                .truncate_before
+                .map(|version| serde_json::to_value(&version))
+                .transpose()?,
+            custom: metadata.custom,
+        })
+    }
+
+    /// Metadata to set on an imported stream, `None` when there is nothing to set
+    ///
+    /// `truncate_before` is left out - it is a version of the source backend, and the events below it were not
+    /// exported in the first place
+    fn to_metadata<V>(&self) -> Option<StreamMetadata<V>> {
+        if self.max_age_ms.is_none() && self.max_count.is_none() && self.custom.is_empty() {
+            return None;
+        }
+
+        Some(StreamMetadata {
+            max_age: self.max_age_ms.map(Duration::from_millis),
+            max_count: self.max_count,
+            truncate_before: None,
+            custom: self.custom.to_owned(),
+        })
+    }
+}
+
+/// A stream as it was exported - versions are the source backend's and are kept for reference only
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedStream {
+    pub category: String,
+    pub stream: Value,
+    pub version: Value,
+    pub metadata: ArchivedStreamMetadata,
+    pub events: Vec<ArchivedEvent>,
+}
+
+/// Streams and events exported or imported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveReport {
+    pub streams: usize,
+    pub events: usize,
+}
+
+/// Event of any shape, for tooling that moves events around without knowing their types
+///
+/// The event type is the variant name of an externally tagged enum, which is how serde writes epoch events
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RawEvent(pub Value);
+
+impl Event for RawEvent {
+    type EntityId = ();
+
+    fn event_type(&self) -> String {
+        match &self.0 {
+            Value::String(variant) => variant.to_owned(),
+            Value::Object(fields) if fields.len() == 1 => fields.keys().next().unwrap().to_owned(),
+            _ => "Unknown".to_owned(),
+        }
+    }
+
+    fn get_id(&self) -> Self::EntityId {}
+}
+
+/// Writes a newline delimited JSON archive
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    /// Events written since the last `start_stream`
+    sequence: usize,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(writer: W) -> Result<Self, ArchiveError> {
+        let mut archive = Self {
+            writer,
+            sequence: 0,
+        };
+        archive.write_record(&ArchiveRecord::Header {
+            format: ARCHIVE_FORMAT,
+        })?;
+
+        Ok(archive)
+    }
+
+    pub fn write_stream(&mut self, stream: &ArchivedStream) -> Result<(), ArchiveError> {
+        self.start_stream(&stream.category, &stream.stream, &stream.metadata)?;
+
+        for event in &stream.events {
+            self.write_event(event)?;
+        }
+
+        self.end_stream(&stream.version)
+    }
+
+    /// Starts a stream whose events follow one at a time with `write_event` - readers refuse the archive
+    /// unless `end_stream` closes it
+    pub fn start_stream(
+        &mut self,
+        category: &str,
+        stream: &Value,
+        metadata: &ArchivedStreamMetadata,
+    ) -> Result<(), ArchiveError> {
+        self.sequence = 0;
+
+        self.write_record(&ArchiveRecord::Stream {
+            category: category.to_owned(),
+            stream: stream.to_owned(),
+            version: None,
+            event_count: None,
+            metadata: metadata.to_owned(),
+        })
+    }
+
+    pub fn write_event(&mut self, event: &ArchivedEvent) -> Result<(), ArchiveError> {
+        self.write_record(&ArchiveRecord::Event {
+            sequence: self.sequence,
+            version: event.version.to_owned(),
+            event_type: event.event_type.to_owned(),
+            recorded_at: event.recorded_at,
+            metadata: event.metadata.to_owned(),
+            payload: event.payload.to_owned(),
+        })?;
+        self.sequence += 1;
+
+        Ok(())
+    }
+
+    /// Closes the stream started last at `version`, recording how many events were written for it
+    pub fn end_stream(&mut self, version: &Value) -> Result<(), ArchiveError> {
+        self.write_record(&ArchiveRecord::StreamEnd {
+            event_count: self.sequence,
+            version: version.to_owned(),
+        })
+    }
+
+    /// Flushes the archive and hands back the writer
+    pub fn finish(mut self) -> Result<W, ArchiveError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_record(&mut self, record: &ArchiveRecord) -> Result<(), ArchiveError> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+}
+
+/// Reads a newline delimited JSON archive one stream at a time, checking each stream is complete
+pub struct ArchiveReader<R: BufRead> {
+    lines: Lines<R>,
+    line: usize,
+    pending: Option<ArchiveRecord>,
+}
+
+impl<R: BufRead> ArchiveReader<R> {
+    pub fn new(reader: R) -> Result<Self, ArchiveError> {
+        let mut archive = Self {
+            lines: reader.lines(),
+            line: 0,
+            pending: None,
+        };
+
+        match archive.next_record()? {
+            Some(ArchiveRecord::Header { format }) if (1..=ARCHIVE_FORMAT).contains(&format) => {
+                Ok(archive)
+            }
+            Some(ArchiveRecord::Header { format }) => Err(ArchiveError::UnsupportedFormat(format)),
+            _ => Err(ArchiveError::MissingHeader),
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<ArchiveRecord>, ArchiveError> {
+        if let Some(record) = self.pending.take() {
+            return Ok(Some(record));
+        }
+
+        for line in self.lines.by_ref() {
+            self.line += 1;
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            return serde_json::from_str(&line)
+                .map(Some)
+                .map_err(|source| ArchiveError::Parse {
+                    line: self.line,
+                    source,
+                });
+        }
+
+        Ok(None)
+    }
+
+    fn corrupt(&self, reason: impl Into<String>) -> ArchiveError {
+        ArchiveError::Corrupt {
+            line: self.line,
+            reason: reason.into(),
+        }
+    }
+
+    fn next_stream(&mut self) -> Result<Option<ArchivedStream>, ArchiveError> {
+        let (category, stream, version, event_count, metadata) = match self.next_record()? {
+            None => return Ok(None),
+            Some(ArchiveRecord::Stream {
+                category,
+                stream,
+                version,
+                event_count,
+                metadata,
+            }) => (category, stream, version, event_count, metadata),
+            Some(_) => return Err(self.corrupt("expected a stream record")),
+        };
+
+        let mut events = vec![];
+        let mut end = None;
+
+        while let Some(record) = self.next_record()? {
+            match record {
+                ArchiveRecord::Event {
+                    sequence,
+                    version,
+                    event_type,
+                    recorded_at,
+                    metadata,
+                    payload,
+                } => {
+                    if sequence != events.len() {
+                        return Err(self.corrupt(format!(
+                            "expected event {} of stream {}, found {}",
+                            events.len(),
+                            stream,
+                            sequence
+                        )));
+                    }
+
+                    events.push(ArchivedEvent {
+                        version,
+                        event_type,
+                        recorded_at,
+                        metadata,
+                        payload,
+                    });
+                }
+                ArchiveRecord::StreamEnd {
+                    event_count,
+                    version,
+                } => {
+                    end = Some((event_count, version));
+                    break;
+                }
+                record => {
+                    self.pending = Some(record);
+                    break;
+                }
+            }
+        }
+
+        let (event_count, version) = match (end, event_count, version) {
+            (Some(end), _, _) => end,
+            (None, Some(event_count), Some(version)) => (event_count, version),
+            _ => return Err(self.corrupt(format!("stream {} is not closed", stream))),
+        };
+
+        if events.len() != event_count {
+            return Err(self.corrupt(format!(
+                "stream {} holds {} events, expected {}",
+                stream,
+                events.len(),
+                event_count
+            )));
+        }
+
+        Ok(Some(ArchivedStream {
+            category,
+            stream,
+            version,
+            metadata,
+            events,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for ArchiveReader<R> {
+    type Item = Result<ArchivedStream, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_stream().transpose()
+    }
+}
+
+/// Categories found in the global log, in the order they first appear
+///
+/// Reads the whole log a page at a time - meant for tooling that exports or inspects every category
+pub async fn list_categories<E, Err>(
+    repository: &impl AllEventsRepository<E, Err>,
+) -> Result<Vec<String>, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    let filter = AllEventsFilter::new();
+    let mut seen = HashSet::new();
+    let mut categories = vec![];
+    let mut from = None;
+
+    loop {
+        let page = repository
+            .load_all(from.as_ref(), &filter, Some(LIST_PAGE_SIZE))
+            .await?;
+
+        let last = match page.last() {
+            Some(last) => last.position.to_owned(),
+            None => return Ok(categories),
+        };
+
+        for event in page {
+            if seen.insert(event.category.to_owned()) {
+                categories.push(event.category);
+            }
+        }
+
+        from = Some(last);
+    }
+}
+
+/// Milliseconds since the unix epoch, `None` for times before it
+pub fn to_millis(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|since| since.as_millis() as u64)
+}
+
+/// Writes one stream and its metadata to `archive` under `category` - empty streams are left out
+///
+/// Events are written as the repository streams them, so a stream is never held in memory. Fails on the first
+/// event the repository can't decode rather than leaving it out, which leaves the stream unclosed for readers to
+/// refuse. Returns the number of events written
+pub async fn export_stream<'a, R, E, Err, W>(
+    repository: &R,
+    category: &str,
+    stream: &R::StreamId,
+    archive: &mut ArchiveWriter<W>,
+) -> Result<usize, ArchiveTransferError<Err, R::Version>>
+where
+    R: StreamingEventRepository<'a, E, Err> + StreamMetadataRepository<'a, E, Err>,
+    R::StreamId: Serialize + Clone,
+    R::Version: Serialize + Debug,
+    E: Event + Serialize + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+    W: Write,
+{
+    let metadata = repository
+        .get_metadata(stream)
+        .await
+        .map_err(ArchiveTransferError::Repository)?;
+    let metadata = ArchivedStreamMetadata::from_metadata(metadata).map_err(ArchiveError::from)?;
+    let stream_value = serde_json::to_value(stream).map_err(ArchiveError::from)?;
+
+    let mut recorded_events = repository.stream(Some(stream.to_owned()));
+    let mut written = 0;
+    let mut version = None;
+
+    while let Some(recorded) = recorded_events
+        .try_next()
+        .await
+        .map_err(ArchiveTransferError::Repository)?
+    {
+        if written == 0 {
+            archive.start_stream(category, &stream_value, &metadata)?;
+        }
+
+        let event = ArchivedEvent {
+            version: serde_json::to_value(&recorded.version).map_err(ArchiveError::from)?,
+            event_type: recorded.event.event_type(),
+            recorded_at: recorded.recorded_at.and_then(to_millis),
+            metadata: recorded.metadata,
+            payload: serde_json::to_value(&recorded.event).map_err(ArchiveError::from)?,
+        };
+        archive.write_event(&event)?;
+
+        version = Some(event.version);
+        written += 1;
+    }
+
+    if let Some(version) = version {
+        archive.end_stream(&version)?;
+    }
+
+    Ok(written)
+}
+
+/// Writes every stream of the repository's category to `archive`
+pub async fn export_category<'a, R, E, Err, W>(
+    repository: &R,
+    category: &str,
+    archive: &mut ArchiveWriter<W>,
+) -> Result<ArchiveReport, ArchiveTransferError<Err, R::Version>>
+where
+    R: StreamingEventRepository<'a, E, Err>
+        + StreamListingRepository<'a, E, Err>
+        + StreamMetadataRepository<'a, E, Err>,
+    R::StreamId: Serialize + Clone,
+    R::Version: Serialize + Debug,
+    E: Event + Serialize + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+    W: Write,
+{
+    let mut report = ArchiveReport::default();
+
+    for stream in repository
+        .list_streams()
+        .await
+        .map_err(ArchiveTransferError::Repository)?
+    {
+        let events = export_stream(repository, category, &stream, archive).await?;
+
+        if events > 0 {
+            report.streams += 1;
+            report.events += events;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Appends the archived streams of `category` to `repository`, or every stream when `category` is `None`
+///
+/// Streams must not exist in the target yet - the first batch of a stream is appended with
+/// `RepositoryVersion::NoStream` and every later batch against the version the previous one returned, so an
+/// import never merges into or races with existing data. Stream metadata is set once the events are in, apart
+/// from `truncate_before`. Recorded times and the metadata of single events are not carried over, the target
+/// records its own
+pub async fn import_archive<'a, R, E, Err, Rd>(
+    archive: ArchiveReader<Rd>,
+    category: Option<&str>,
+    repository: &mut R,
+) -> Result<ArchiveReport, ArchiveTransferError<Err, R::Version>>
+where
+    R: StreamMetadataRepository<'a, E, Err>,
+    R::StreamId: DeserializeOwned,
+    R::Version: Debug,
+    E: Event + DeserializeOwned + Clone + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+    Rd: BufRead,
+{
+    let mut report = ArchiveReport::default();
+
+    for archived in archive {
+        let archived = archived?;
+
+        if category.is_some_and(|category| category != archived.category) {
+            continue;
+        }
+
+        let stream: R::StreamId =
+            serde_json::from_value(archived.stream.to_owned()).map_err(ArchiveError::from)?;
+
+        let events = archived
+            .events
+            .into_iter()
+            .map(|event| {
+                let decoded: E = serde_json::from_value(event.payload)?;
+
+                if decoded.event_type() != event.event_type {
+                    return Err(ArchiveError::EventTypeMismatch {
+                        stream: archived.stream.to_string(),
+                        archived: event.event_type,
+                        decoded: decoded.event_type(),
+                    });
+                }
+
+                Ok(decoded)
+            })
+            .collect::<Result<Vec<E>, ArchiveError>>()?;
+
+        let mut version = RepositoryVersion::NoStream;
+        for batch in events.chunks(IMPORT_BATCH_SIZE) {
+            (_, version) = repository
+                .append(&version, &stream, &batch.to_vec())
+                .await
+                .map_err(ArchiveTransferError::Repository)?;
+        }
+
+        if let Some(metadata) = archived.metadata.to_metadata() {
+            repository
+                .set_metadata(&stream, &metadata)
+                .await
+                .map_err(ArchiveTransferError::Repository)?;
+        }
+
+        report.streams += 1;
+        report.events += events.len();
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::{
+        repository::{
+            event::{
+                StreamMetadata, StreamMetadataRepository, VersionedEventRepositoryWithStreams,
+            },
+            in_memory::versioned_with_streams::InMemoryEventRepository,
+            RepositoryVersion, VersionedRepositoryError,
+        },
+        test_helpers::deciders::user::{Guitar, User, UserEvent, UserId, UserName},
+    };
+
+    use super::{
+        error::{ArchiveError, ArchiveTransferError},
+        export_category, import_archive, list_categories, ArchiveReader, ArchiveReport,
+        ArchiveWriter, RawEvent,
+    };
+
+    fn user_events(id: UserId) -> Vec<UserEvent> {
+        vec![
+            UserEvent::UserAdded(User::new(id, UserName::try_from("Mike").unwrap())),
+            UserEvent::UserGuitarAdded(
+                id,
+                Guitar {
+                    brand: "Gibson".to_string(),
+                },
+            ),
+        ]
+    }
+
+    async fn archive_of(event_repository: &InMemoryEventRepository<UserEvent>) -> Vec<u8> {
+        let mut archive = ArchiveWriter::new(vec![]).unwrap();
+        let report = export_category(event_repository, "test", &mut archive)
+            .await
+            .unwrap();
+        assert_eq!(
+            report,
+            ArchiveReport {
+                streams: 2,
+                events: 4
+            }
+        );
+
+        archive.finish().unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn export_then_import_round_trips() {
+        let mut source = InMemoryEventRepository::<UserEvent>::new("test");
+        for id in ["1", "2"] {
+            source
+                .append(
+                    &RepositoryVersion::NoStream,
+                    &id.to_string(),
+                    &user_events(id.parse().unwrap()),
+                )
+                .await
+                .unwrap();
+        }
+        source
+            .set_metadata(
+                &"1".to_string(),
+                &StreamMetadata::new()
+                    .with_max_count(10)
+                    .with_custom("owner", serde_json::json!("billing")),
+            )
+            .await
+            .unwrap();
+        let archive = archive_of(&source).await;
+        assert_eq!(list_categories(&source).await.unwrap(), vec!["test"]);
+
+        let archived = ArchiveReader::new(archive.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(archived[0].metadata.max_count, Some(10));
+        assert_eq!(archived[0].metadata.custom["owner"], "billing");
+        assert_eq!(archived[1].metadata, Default::default());
+
+        let mut target = InMemoryEventRepository::<RawEvent>::new("copy");
+        let report = import_archive(
+            ArchiveReader::new(archive.as_slice()).unwrap(),
+            Some("test"),
+            &mut target,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.events, 4);
+
+        let metadata = target.get_metadata(&"1".to_string()).await.unwrap();
+        assert_eq!(metadata.max_count, Some(10));
+        assert_eq!(metadata.custom["owner"], "billing");
+
+        let (events, version) = target.load(Some(&"2".to_string())).await.unwrap();
+        assert_eq!(version, RepositoryVersion::Exact(1));
+        assert_eq!(
+            events
+                .into_iter()
+                .map(|event| serde_json::from_value::<UserEvent>(event.0).unwrap())
+                .collect::<Vec<_>>(),
+            user_events(2)
+        );
+
+        // Importing again would merge into the copied streams
+        let res = import_archive(
+            ArchiveReader::new(archive.as_slice()).unwrap(),
+            None,
+            &mut target,
+        )
+        .await;
+        assert_matches!(
+            res,
+            Err(ArchiveTransferError::Repository(
+                VersionedRepositoryError::VersionConflict(_)
+            ))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn truncated_archives_are_refused() {
+        let mut source = InMemoryEventRepository::<UserEvent>::new("test");
+        for id in ["1", "2"] {
+            source
+                .append(
+                    &RepositoryVersion::NoStream,
+                    &id.to_string(),
+                    &user_events(id.parse().unwrap()),
+                )
+                .await
+                .unwrap();
+        }
+        let archive = String::from_utf8(archive_of(&source).await).unwrap();
+        let truncated = archive.lines().take(3).collect::<Vec<_>>().join("\n");
+
+        let res = ArchiveReader::new(truncated.as_bytes())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>();
+        assert_matches!(res, Err(ArchiveError::Corrupt { line: 3, .. }));
+
+        assert_matches!(
+            ArchiveReader::new(
+                archive
+                    .lines()
+                    .skip(1)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+                    .as_bytes()
+            )
+            .err(),
+            Some(ArchiveError::MissingHeader)
+        );
+    }
+
+    #[test]
+    fn reads_format_1_archives() {
+        let archive = [
+            r#"{"record":"header","format":1}"#,
+            r#"{"record":"stream","category":"test","stream":"1","version":0,"event_count":1}"#,
+            r#"{"record":"event","sequence":0,"version":0,"event_type":"UserAdded","recorded_at":null,"payload":{"UserAdded":{}}}"#,
+        ]
+        .join("\n");
+
+        let archived = ArchiveReader::new(archive.as_bytes())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].version, 0);
+        assert_eq!(archived[0].events[0].event_type, "UserAdded");
+    }
+}
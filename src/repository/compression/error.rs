@@ -0,0 +1,27 @@
+use std::fmt::Debug;
+
+use thiserror::Error;
+
+use crate::codec::CodecError;
+
+use super::CompressionAlgorithm;
+
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error("Codec error {0}")]
+    Codec(#[from] CodecError),
+    #[error("Could not compress or decompress payload {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0:?} compression is not enabled in this build")]
+    Unsupported(CompressionAlgorithm),
+    #[error("Unknown compression algorithm {0:?} in event metadata")]
+    UnknownAlgorithm(String),
+}
+
+#[derive(Debug, Error)]
+pub enum CompressedRepositoryError<RepoErr: Debug> {
+    #[error("Repository error {0:?}")]
+    Repository(RepoErr),
+    #[error(transparent)]
+    Compression(#[from] CompressionError),
+}
@@ -0,0 +1,468 @@
+use std::{collections::HashMap, fmt::Debug, marker::PhantomData};
+
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    codec::{Codec, JsonCodec},
+    decider::Event,
+};
+
+use super::{
+    event::{
+        EventStream, RecordedEvent, StreamingEventRepository, VersionedEventRepositoryWithStreams,
+    },
+    RepositoryVersion, VersionedRepositoryError,
+};
+
+use self::error::{CompressedRepositoryError, CompressionError};
+
+pub mod error;
+#[cfg(feature = "redis")]
+pub mod redis;
+
+/// Payloads up to this many bytes are stored as they are by default
+pub const DEFAULT_THRESHOLD: usize = 1024;
+
+/// Metadata key naming the algorithm a payload was compressed with - absent for payloads stored as they are
+pub const COMPRESSION_METADATA_KEY: &str = "compression";
+
+/// Metadata key holding the content type of the codec a payload was encoded with
+pub const CONTENT_TYPE_METADATA_KEY: &str = "content-type";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressionAlgorithm {
+    None,
+    Zstd,
+    Gzip,
+}
+
+impl CompressionAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::None => "none",
+            CompressionAlgorithm::Zstd => "zstd",
+            CompressionAlgorithm::Gzip => "gzip",
+        }
+    }
+
+    /// Algorithm recorded in an event's metadata
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Result<Self, CompressionError> {
+        match metadata.get(COMPRESSION_METADATA_KEY).map(String::as_str) {
+            None | Some("none") => Ok(CompressionAlgorithm::None),
+            Some("zstd") => Ok(CompressionAlgorithm::Zstd),
+            Some("gzip") => Ok(CompressionAlgorithm::Gzip),
+            Some(other) => Err(CompressionError::UnknownAlgorithm(other.to_owned())),
+        }
+    }
+
+    fn compress(&self, payload: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        match self {
+            CompressionAlgorithm::None => Ok(payload.to_vec()),
+            #[cfg(feature = "zstd")]
+            CompressionAlgorithm::Zstd => {
+                Ok(zstd::encode_all(payload, zstd::DEFAULT_COMPRESSION_LEVEL)?)
+            }
+            #[cfg(feature = "gzip")]
+            CompressionAlgorithm::Gzip => {
+                use std::io::Write;
+
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(payload)?;
+
+                Ok(encoder.finish()?)
+            }
+            #[allow(unreachable_patterns)]
+            algorithm => Err(CompressionError::Unsupported(*algorithm)),
+        }
+    }
+
+    fn decompress(&self, payload: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        match self {
+            CompressionAlgorithm::None => Ok(payload.to_vec()),
+            #[cfg(feature = "zstd")]
+            CompressionAlgorithm::Zstd => Ok(zstd::decode_all(payload)?),
+            #[cfg(feature = "gzip")]
+            CompressionAlgorithm::Gzip => {
+                use std::io::Read;
+
+                let mut decompressed = vec![];
+                flate2::read::GzDecoder::new(payload).read_to_end(&mut decompressed)?;
+
+                Ok(decompressed)
+            }
+            #[allow(unreachable_patterns)]
+            algorithm => Err(CompressionError::Unsupported(*algorithm)),
+        }
+    }
+}
+
+/// What a `CompressedEventRepository` stores in the wrapped repository
+///
+/// The algorithm the payload was compressed with is recorded under `COMPRESSION_METADATA_KEY` in `metadata`, and the
+/// codec it was encoded with under `CONTENT_TYPE_METADATA_KEY`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressedEvent {
+    pub stream_id: String,
+    pub event_type: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    #[serde(with = "crate::codec::bytes")]
+    pub payload: Vec<u8>,
+}
+
+impl CompressedEvent {
+    pub fn algorithm(&self) -> Result<CompressionAlgorithm, CompressionError> {
+        CompressionAlgorithm::from_metadata(&self.metadata)
+    }
+}
+
+impl Event for CompressedEvent {
+    type EntityId = String;
+
+    fn event_type(&self) -> String {
+        self.event_type.to_owned()
+    }
+
+    fn get_id(&self) -> Self::EntityId {
+        self.stream_id.to_owned()
+    }
+}
+
+/// Compresses event payloads larger than `threshold` bytes before they reach the wrapped repository
+///
+/// Each event records the algorithm and codec it was written with in its metadata, so the algorithm, threshold or codec
+/// can change without rewriting existing streams. A payload that does not get smaller is stored uncompressed
+///
+/// With `CompressionAlgorithm::None` this only encodes events with the codec, which is how a Redis repository over
+/// `CompressedEventDTO` stores events in any codec
+pub struct CompressedEventRepository<R, E, C = JsonCodec> {
+    inner: R,
+    algorithm: CompressionAlgorithm,
+    threshold: usize,
+    codec: C,
+    _hidden: PhantomData<E>,
+}
+
+impl<R, E> CompressedEventRepository<R, E> {
+    pub fn new(inner: R, algorithm: CompressionAlgorithm) -> Self {
+        Self::with_codec(inner, algorithm, JsonCodec)
+    }
+}
+
+impl<R, E, C> CompressedEventRepository<R, E, C> {
+    pub fn with_codec(inner: R, algorithm: CompressionAlgorithm, codec: C) -> Self {
+        Self {
+            inner,
+            algorithm,
+            threshold: DEFAULT_THRESHOLD,
+            codec,
+            _hidden: PhantomData,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R, E, C> CompressedEventRepository<R, E, C>
+where
+    E: Event + Serialize + DeserializeOwned,
+    C: Codec,
+{
+    fn compress(&self, stream: &str, event: &E) -> Result<CompressedEvent, CompressionError> {
+        let payload = self.codec.encode(event)?;
+
+        let (algorithm, payload) = if payload.len() > self.threshold {
+            let compressed = self.algorithm.compress(&payload)?;
+
+            if compressed.len() < payload.len() {
+                (self.algorithm, compressed)
+            } else {
+                (CompressionAlgorithm::None, payload)
+            }
+        } else {
+            (CompressionAlgorithm::None, payload)
+        };
+
+        let mut metadata = HashMap::from([(
+            CONTENT_TYPE_METADATA_KEY.to_owned(),
+            self.codec.content_type().to_owned(),
+        )]);
+        if algorithm != CompressionAlgorithm::None {
+            metadata.insert(
+                COMPRESSION_METADATA_KEY.to_owned(),
+                algorithm.name().to_owned(),
+            );
+        }
+
+        Ok(CompressedEvent {
+            stream_id: stream.to_owned(),
+            event_type: event.event_type(),
+            metadata,
+            payload,
+        })
+    }
+
+    fn decompress(&self, compressed: CompressedEvent) -> Result<E, CompressionError> {
+        let payload = compressed.algorithm()?.decompress(&compressed.payload)?;
+
+        // Events written before the content type was recorded are in this repository's codec
+        Ok(match compressed.metadata.get(CONTENT_TYPE_METADATA_KEY) {
+            Some(content_type) => self.codec.decode_tagged(content_type, &payload)?,
+            None => self.codec.decode(&payload)?,
+        })
+    }
+
+    fn decompress_all<Err: Debug, V>(
+        &self,
+        compressed: Vec<CompressedEvent>,
+    ) -> Result<Vec<E>, VersionedRepositoryError<CompressedRepositoryError<Err>, V>> {
+        compressed
+            .into_iter()
+            .map(|compressed| self.decompress(compressed))
+            .collect::<Result<_, _>>()
+            .map_err(|e| VersionedRepositoryError::RepoErr(e.into()))
+    }
+}
+
+#[async_trait]
+impl<'a, R, E, C, Err> VersionedEventRepositoryWithStreams<'a, E, CompressedRepositoryError<Err>>
+    for CompressedEventRepository<R, E, C>
+where
+    R: VersionedEventRepositoryWithStreams<'a, CompressedEvent, Err> + Send + Sync,
+    R::StreamId: ToString,
+    E: Event + Serialize + DeserializeOwned + Clone + Debug + Send + Sync,
+    C: Codec,
+    Err: Debug + Send + Sync,
+{
+    type StreamId = R::StreamId;
+    type Version = R::Version;
+
+    async fn load(
+        &self,
+        id: Option<&Self::StreamId>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<CompressedRepositoryError<Err>, Self::Version>,
+    > {
+        let (compressed, version) = self
+            .inner
+            .load(id)
+            .await
+            .map_err(|e| e.map_repo_err(CompressedRepositoryError::Repository))?;
+
+        Ok((self.decompress_all(compressed)?, version))
+    }
+
+    async fn load_from_version(
+        &self,
+        version: &RepositoryVersion<Self::Version>,
+        id: Option<&Self::StreamId>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<CompressedRepositoryError<Err>, Self::Version>,
+    > {
+        let (compressed, version) = self
+            .inner
+            .load_from_version(version, id)
+            .await
+            .map_err(|e| e.map_repo_err(CompressedRepositoryError::Repository))?;
+
+        Ok((self.decompress_all(compressed)?, version))
+    }
+
+    async fn append(
+        &mut self,
+        version: &RepositoryVersion<Self::Version>,
+        stream: &Self::StreamId,
+        events: &Vec<E>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<CompressedRepositoryError<Err>, Self::Version>,
+    >
+    where
+        'a: 'async_trait,
+        E: 'async_trait,
+    {
+        let compressed = events
+            .iter()
+            .map(|event| self.compress(&stream.to_string(), event))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| VersionedRepositoryError::RepoErr(e.into()))?;
+
+        let (_, version) = self
+            .inner
+            .append(version, stream, &compressed)
+            .await
+            .map_err(|e| e.map_repo_err(CompressedRepositoryError::Repository))?;
+
+        Ok((events.to_owned(), version))
+    }
+}
+
+impl<'a, R, E, C, Err> StreamingEventRepository<'a, E, CompressedRepositoryError<Err>>
+    for CompressedEventRepository<R, E, C>
+where
+    R: StreamingEventRepository<'a, CompressedEvent, Err> + Send + Sync,
+    R::StreamId: ToString,
+    R::Version: 'static,
+    E: Event + Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+    C: Codec,
+    Err: Debug + Send + Sync + 'static,
+{
+    fn stream_from_version(
+        &self,
+        version: RepositoryVersion<Self::Version>,
+        id: Option<Self::StreamId>,
+    ) -> EventStream<'_, E, Self::Version, CompressedRepositoryError<Err>> {
+        self.inner
+            .stream_from_version(version, id)
+            .map_err(|e| e.map_repo_err(CompressedRepositoryError::Repository))
+            .and_then(move |recorded| async move {
+                Ok(RecordedEvent {
+                    event: self
+                        .decompress(recorded.event)
+                        .map_err(|e| VersionedRepositoryError::RepoErr(e.into()))?,
+                    version: recorded.version,
+                    recorded_at: recorded.recorded_at,
+                    metadata: recorded.metadata,
+                })
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        repository::{
+            event::VersionedEventRepositoryWithStreams,
+            in_memory::versioned_with_streams::InMemoryEventRepository, RepositoryVersion,
+        },
+        test_helpers::deciders::user::{Guitar, User, UserEvent, UserName},
+    };
+
+    use super::{
+        CompressedEvent, CompressedEventRepository, CompressionAlgorithm, COMPRESSION_METADATA_KEY,
+        CONTENT_TYPE_METADATA_KEY,
+    };
+
+    async fn assert_compresses_large_events(algorithm: CompressionAlgorithm) {
+        let mut event_repository = CompressedEventRepository::new(
+            InMemoryEventRepository::<CompressedEvent>::new("test"),
+            algorithm,
+        );
+        let id = "1".to_string();
+
+        let events = vec![
+            UserEvent::UserAdded(User::new(1, UserName::try_from("Mike").unwrap())),
+            UserEvent::UserGuitarAdded(
+                1,
+                Guitar {
+                    brand: "Gibson".repeat(1000),
+                },
+            ),
+        ];
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            event_repository.load(Some(&id)).await.unwrap(),
+            (events, RepositoryVersion::Exact(1))
+        );
+
+        let (stored, _) = event_repository.inner().load(Some(&id)).await.unwrap();
+        assert!(!stored[0].metadata.contains_key(COMPRESSION_METADATA_KEY));
+        assert_eq!(
+            stored[1].metadata.get(COMPRESSION_METADATA_KEY),
+            Some(&algorithm.name().to_owned())
+        );
+        assert_eq!(stored[1].algorithm().unwrap(), algorithm);
+        assert_eq!(stored[1].stream_id, id);
+        assert!(stored[1].payload.len() < 1000);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[actix_rt::test]
+    async fn zstd_compresses_large_events() {
+        assert_compresses_large_events(CompressionAlgorithm::Zstd).await;
+    }
+
+    #[cfg(feature = "gzip")]
+    #[actix_rt::test]
+    async fn gzip_compresses_large_events() {
+        assert_compresses_large_events(CompressionAlgorithm::Gzip).await;
+    }
+
+    #[cfg(feature = "cbor")]
+    #[actix_rt::test]
+    async fn reads_events_written_with_another_codec() {
+        let id = "1".to_string();
+        let events = vec![UserEvent::UserAdded(User::new(
+            1,
+            UserName::try_from("Mike").unwrap(),
+        ))];
+
+        let mut cbor = CompressedEventRepository::with_codec(
+            InMemoryEventRepository::<CompressedEvent>::new("test"),
+            CompressionAlgorithm::None,
+            crate::codec::CborCodec,
+        );
+        cbor.append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+
+        let (stored, _) = cbor.inner().load(Some(&id)).await.unwrap();
+        assert_eq!(
+            stored[0].metadata.get(CONTENT_TYPE_METADATA_KEY),
+            Some(&crate::codec::CBOR_CONTENT_TYPE.to_owned())
+        );
+
+        let json = CompressedEventRepository::<_, UserEvent>::new(
+            cbor.inner().to_owned(),
+            CompressionAlgorithm::None,
+        );
+        assert_eq!(json.load(Some(&id)).await.unwrap().0, events);
+    }
+
+    #[test]
+    fn payload_is_base64_and_algorithm_is_metadata_in_json() {
+        let event = CompressedEvent {
+            stream_id: "1".to_string(),
+            event_type: "UserAdded".to_string(),
+            metadata: [(COMPRESSION_METADATA_KEY.to_owned(), "zstd".to_owned())].into(),
+            payload: b"{}".to_vec(),
+        };
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["payload"], "e30=");
+        assert_eq!(json["metadata"][COMPRESSION_METADATA_KEY], "zstd");
+        assert_eq!(
+            serde_json::from_value::<CompressedEvent>(json).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn refuses_unknown_algorithms() {
+        let event = CompressedEvent {
+            stream_id: "1".to_string(),
+            event_type: "UserAdded".to_string(),
+            metadata: [(COMPRESSION_METADATA_KEY.to_owned(), "lz4".to_owned())].into(),
+            payload: vec![],
+        };
+
+        assert!(event.algorithm().is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use redis_om::StreamModel;
+use thiserror::Error;
+
+use crate::repository::{
+    redis::versioned_event::StreamModelDTO, WithEventType, WithFineGrainedStreamId,
+};
+
+use super::CompressedEvent;
+
+/// Redis stream entry of a `CompressedEvent`, so a `CompressedEventRepository` can wrap a
+/// `RedisStreamsEventRepository<CompressedEventDTOManager, CompressedEventDTO>`
+///
+/// The payload is kept as raw bytes and the metadata as a JSON object
+#[derive(StreamModel, Debug, Clone)]
+pub struct CompressedEventDTO {
+    stream_id: String,
+    event_type: String,
+    metadata: String,
+    payload: Vec<u8>,
+}
+
+impl WithFineGrainedStreamId for CompressedEventDTO {
+    fn to_fine_grained_id(&self) -> String {
+        self.stream_id.to_owned()
+    }
+}
+
+impl WithEventType for CompressedEventDTO {
+    fn event_type(&self) -> String {
+        self.event_type.to_owned()
+    }
+}
+
+#[derive(Error, Debug, Clone)]
+#[error("Compressed event DTO invalid: {0}")]
+pub struct CompressedEventDTOError(String);
+
+impl StreamModelDTO<CompressedEventDTOManager, CompressedEventDTOError> for CompressedEvent {
+    fn into_dto(self) -> CompressedEventDTO {
+        CompressedEventDTO {
+            stream_id: self.stream_id,
+            event_type: self.event_type,
+            metadata: serde_json::to_string(&self.metadata)
+                .expect("string maps always serialize to JSON"),
+            payload: self.payload,
+        }
+    }
+
+    fn try_from_dto(model: CompressedEventDTO) -> Result<Self, CompressedEventDTOError> {
+        let metadata = serde_json::from_str::<HashMap<String, String>>(&model.metadata)
+            .map_err(|e| CompressedEventDTOError(format!("metadata: {}", e)))?;
+
+        Ok(CompressedEvent {
+            stream_id: model.stream_id,
+            event_type: model.event_type,
+            metadata,
+            payload: model.payload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redis_om::redis::{FromRedisValue, ToRedisArgs, Value};
+
+    use crate::repository::{
+        compression::{CompressedEvent, COMPRESSION_METADATA_KEY},
+        redis::versioned_event::StreamModelDTO,
+    };
+
+    use super::{CompressedEventDTO, CompressedEventDTOError, CompressedEventDTOManager};
+
+    #[test]
+    fn round_trips_through_redis_fields() {
+        let event = CompressedEvent {
+            stream_id: "1".to_string(),
+            event_type: "UserAdded".to_string(),
+            metadata: [(COMPRESSION_METADATA_KEY.to_owned(), "gzip".to_owned())].into(),
+            payload: vec![0, 159, 146, 150],
+        };
+
+        let dto = StreamModelDTO::<CompressedEventDTOManager, CompressedEventDTOError>::into_dto(
+            event.clone(),
+        );
+        let fields = Value::Bulk(dto.to_redis_args().into_iter().map(Value::Data).collect());
+
+        assert_eq!(
+            <CompressedEvent as StreamModelDTO<CompressedEventDTOManager, _>>::try_from_dto(
+                CompressedEventDTO::from_redis_value(&fields).unwrap()
+            )
+            .unwrap(),
+            event
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt::Debug;
+
+use thiserror::Error;
+
+use crate::codec::CodecError;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError<RepoErr: Debug, KeyErr: Debug> {
+    #[error("Repository error {0:?}")]
+    Repository(RepoErr),
+    #[error("Key store error {0:?}")]
+    KeyStore(KeyErr),
+    #[error("Codec error {0}")]
+    Codec(#[from] CodecError),
+    #[error("Could not encrypt event for subject {0}")]
+    Encrypt(String),
+    #[error("Could not decrypt event for subject {0}, the key does not match or the event was tampered with")]
+    Decrypt(String),
+    #[error("Subject {0} has been forgotten")]
+    SubjectForgotten(String),
+}
+
+#[derive(Debug, Error)]
+pub enum FileKeyStoreError {
+    #[error("Could not access key file {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse key file {0}")]
+    Parse(#[from] serde_json::Error),
+}
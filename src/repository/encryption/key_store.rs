@@ -0,0 +1,181 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    fmt::Debug,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use aes_gcm::{aead::OsRng, Aes256Gcm, KeyInit};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::error::FileKeyStoreError;
+
+pub type SubjectKey = [u8; 32];
+
+/// Per subject encryption keys
+///
+/// Forgetting a subject deletes its key for good, `get_or_create` then refuses to hand out a new one so no new
+/// events can be written for the subject
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    type Error: Debug + Send + Sync;
+
+    /// Key of `subject`, created on first use - `None` once the subject has been forgotten
+    async fn get_or_create(&self, subject: &str) -> Result<Option<SubjectKey>, Self::Error>;
+
+    /// `None` for forgotten subjects and subjects that never had a key
+    async fn get(&self, subject: &str) -> Result<Option<SubjectKey>, Self::Error>;
+
+    async fn forget(&self, subject: &str) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct KeyRing {
+    keys: HashMap<String, SubjectKey>,
+    forgotten: HashSet<String>,
+}
+
+impl KeyRing {
+    fn get_or_create(&mut self, subject: &str) -> Option<SubjectKey> {
+        if self.forgotten.contains(subject) {
+            return None;
+        }
+
+        Some(
+            *self
+                .keys
+                .entry(subject.to_owned())
+                .or_insert_with(|| Aes256Gcm::generate_key(OsRng).into()),
+        )
+    }
+
+    fn forget(&mut self, subject: &str) {
+        self.keys.remove(subject);
+        self.forgotten.insert(subject.to_owned());
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryKeyStore {
+    ring: Arc<Mutex<KeyRing>>,
+}
+
+impl InMemoryKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl KeyStore for InMemoryKeyStore {
+    type Error = Infallible;
+
+    async fn get_or_create(&self, subject: &str) -> Result<Option<SubjectKey>, Infallible> {
+        Ok(self.ring.lock().unwrap().get_or_create(subject))
+    }
+
+    async fn get(&self, subject: &str) -> Result<Option<SubjectKey>, Infallible> {
+        Ok(self.ring.lock().unwrap().keys.get(subject).copied())
+    }
+
+    async fn forget(&self, subject: &str) -> Result<(), Infallible> {
+        self.ring.lock().unwrap().forget(subject);
+        Ok(())
+    }
+}
+
+/// Keys kept in a single JSON file, for local development and single node deployments
+///
+/// Every change rewrites the whole file through a temporary file and a rename, so a crash never leaves a
+/// half written key file behind and a forgotten key is gone from disk once `forget` returns. Changes only reach
+/// the keys in memory once the file is written, and writers queue on an async lock while one of them writes
+#[derive(Debug)]
+pub struct FileKeyStore {
+    path: PathBuf,
+    ring: futures::lock::Mutex<KeyRing>,
+}
+
+impl FileKeyStore {
+    /// Opens the key file at `path`, starting with no keys if it does not exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FileKeyStoreError> {
+        let path = path.as_ref().to_owned();
+
+        let ring = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            KeyRing::default()
+        };
+
+        Ok(Self {
+            path,
+            ring: futures::lock::Mutex::new(ring),
+        })
+    }
+
+    fn save(&self, ring: &KeyRing) -> Result<(), FileKeyStoreError> {
+        let tmp = self.path.with_extension("tmp");
+
+        // Left behind by a crash between writing and renaming
+        match fs::remove_file(&tmp) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+
+        let mut options = OpenOptions::new();
+        options.create_new(true).write(true);
+
+        // Owner only from the moment the file exists, before any key is written to it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&tmp)?;
+        file.write_all(&serde_json::to_vec(ring)?)?;
+        file.sync_all()?;
+
+        fs::rename(tmp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl KeyStore for FileKeyStore {
+    type Error = FileKeyStoreError;
+
+    async fn get_or_create(&self, subject: &str) -> Result<Option<SubjectKey>, FileKeyStoreError> {
+        let mut ring = self.ring.lock().await;
+
+        if ring.keys.contains_key(subject) || ring.forgotten.contains(subject) {
+            return Ok(ring.keys.get(subject).copied());
+        }
+
+        let mut next = ring.clone();
+        let key = next.get_or_create(subject);
+        self.save(&next)?;
+        *ring = next;
+
+        Ok(key)
+    }
+
+    async fn get(&self, subject: &str) -> Result<Option<SubjectKey>, FileKeyStoreError> {
+        Ok(self.ring.lock().await.keys.get(subject).copied())
+    }
+
+    async fn forget(&self, subject: &str) -> Result<(), FileKeyStoreError> {
+        let mut ring = self.ring.lock().await;
+
+        let mut next = ring.clone();
+        next.forget(subject);
+        self.save(&next)?;
+        *ring = next;
+
+        Ok(())
+    }
+}
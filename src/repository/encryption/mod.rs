@@ -0,0 +1,480 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use aes_gcm::{
+    aead::{Aead, OsRng, Payload},
+    AeadCore, Aes256Gcm, Key, KeyInit, Nonce,
+};
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    codec::{Codec, JsonCodec},
+    decider::Event,
+};
+
+use super::{
+    event::{
+        EventStream, RecordedEvent, StreamingEventRepository, VersionedEventRepositoryWithStreams,
+    },
+    RepositoryVersion, VersionedRepositoryError,
+};
+
+use self::error::EncryptionError;
+use self::key_store::KeyStore;
+
+pub mod error;
+pub mod key_store;
+
+const NONCE_LEN: usize = 12;
+
+/// Events carrying personal data name the subject the data belongs to
+pub trait PersonalData: Sized {
+    /// Subject whose key encrypts the event - `None` stores the event unencrypted
+    fn data_subject(&self) -> Option<String>;
+
+    /// Stand in loaded for events of a forgotten subject - `None` leaves them out of loads
+    fn forgotten(_event_type: &str, _subject: &str) -> Option<Self> {
+        None
+    }
+}
+
+/// What an `EncryptedEventRepository` stores in the wrapped repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SealedEvent {
+    Plain {
+        event_type: String,
+        #[serde(with = "crate::codec::bytes")]
+        payload: Vec<u8>,
+    },
+    Encrypted {
+        event_type: String,
+        subject: String,
+        #[serde(with = "crate::codec::bytes")]
+        nonce: Vec<u8>,
+        #[serde(with = "crate::codec::bytes")]
+        ciphertext: Vec<u8>,
+    },
+}
+
+impl Event for SealedEvent {
+    type EntityId = Option<String>;
+
+    fn event_type(&self) -> String {
+        match self {
+            SealedEvent::Plain { event_type, .. } | SealedEvent::Encrypted { event_type, .. } => {
+                event_type.to_owned()
+            }
+        }
+    }
+
+    fn get_id(&self) -> Self::EntityId {
+        match self {
+            SealedEvent::Plain { .. } => None,
+            SealedEvent::Encrypted { subject, .. } => Some(subject.to_owned()),
+        }
+    }
+}
+
+/// Encrypts events with a key per data subject before they reach the wrapped repository
+///
+/// Forgetting a subject deletes its key, after which its events can no longer be read - they are left out of
+/// loads, or replaced by `PersonalData::forgotten`, while the stream and its versions stay intact. This is how
+/// erasure requests are honoured against an append only log
+pub struct EncryptedEventRepository<R, E, K, C = JsonCodec> {
+    inner: R,
+    key_store: K,
+    codec: C,
+    _hidden: PhantomData<E>,
+}
+
+impl<R, E, K> EncryptedEventRepository<R, E, K> {
+    pub fn new(inner: R, key_store: K) -> Self {
+        Self::with_codec(inner, key_store, JsonCodec)
+    }
+}
+
+impl<R, E, K, C> EncryptedEventRepository<R, E, K, C> {
+    pub fn with_codec(inner: R, key_store: K, codec: C) -> Self {
+        Self {
+            inner,
+            key_store,
+            codec,
+            _hidden: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn key_store(&self) -> &K {
+        &self.key_store
+    }
+}
+
+impl<R, E, K, C> EncryptedEventRepository<R, E, K, C>
+where
+    E: Event + PersonalData + Serialize + DeserializeOwned,
+    K: KeyStore,
+    C: Codec,
+{
+    /// Deletes the subject's key, making every event encrypted for it unreadable
+    pub async fn forget(&self, subject: &str) -> Result<(), K::Error> {
+        self.key_store.forget(subject).await
+    }
+
+    async fn seal<Err: Debug>(
+        &self,
+        event: &E,
+    ) -> Result<SealedEvent, EncryptionError<Err, K::Error>> {
+        let event_type = event.event_type();
+        let payload = self.codec.encode(event)?;
+
+        let subject = match event.data_subject() {
+            Some(subject) => subject,
+            None => {
+                return Ok(SealedEvent::Plain {
+                    event_type,
+                    payload,
+                })
+            }
+        };
+
+        let key = self
+            .key_store
+            .get_or_create(&subject)
+            .await
+            .map_err(EncryptionError::KeyStore)?
+            .ok_or_else(|| EncryptionError::SubjectForgotten(subject.to_owned()))?;
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        // The subject is authenticated with the payload so a ciphertext cannot be moved to another subject
+        let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &payload,
+                    aad: subject.as_bytes(),
+                },
+            )
+            .map_err(|_| EncryptionError::Encrypt(subject.to_owned()))?;
+
+        Ok(SealedEvent::Encrypted {
+            event_type,
+            subject,
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+
+    async fn unseal<Err: Debug>(
+        &self,
+        sealed: SealedEvent,
+    ) -> Result<Option<E>, EncryptionError<Err, K::Error>> {
+        let (event_type, subject, nonce, ciphertext) = match sealed {
+            SealedEvent::Plain { payload, .. } => return Ok(Some(self.codec.decode(&payload)?)),
+            SealedEvent::Encrypted {
+                event_type,
+                subject,
+                nonce,
+                ciphertext,
+            } => (event_type, subject, nonce, ciphertext),
+        };
+
+        let key = match self
+            .key_store
+            .get(&subject)
+            .await
+            .map_err(EncryptionError::KeyStore)?
+        {
+            Some(key) => key,
+            None => return Ok(E::forgotten(&event_type, &subject)),
+        };
+
+        if nonce.len() != NONCE_LEN {
+            return Err(EncryptionError::Decrypt(subject));
+        }
+
+        let payload = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: subject.as_bytes(),
+                },
+            )
+            .map_err(|_| EncryptionError::Decrypt(subject.to_owned()))?;
+
+        Ok(Some(self.codec.decode(&payload)?))
+    }
+
+    async fn unseal_all<Err: Debug, V>(
+        &self,
+        sealed: Vec<SealedEvent>,
+    ) -> Result<Vec<E>, VersionedRepositoryError<EncryptionError<Err, K::Error>, V>> {
+        let mut events = vec![];
+
+        for sealed in sealed {
+            events.extend(
+                self.unseal(sealed)
+                    .await
+                    .map_err(VersionedRepositoryError::RepoErr)?,
+            );
+        }
+
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl<'a, R, E, K, C, Err> VersionedEventRepositoryWithStreams<'a, E, EncryptionError<Err, K::Error>>
+    for EncryptedEventRepository<R, E, K, C>
+where
+    R: VersionedEventRepositoryWithStreams<'a, SealedEvent, Err> + Send + Sync,
+    E: Event + PersonalData + Serialize + DeserializeOwned + Clone + Debug + Send + Sync,
+    K: KeyStore,
+    C: Codec,
+    Err: Debug + Send + Sync,
+{
+    type StreamId = R::StreamId;
+    type Version = R::Version;
+
+    async fn load(
+        &self,
+        id: Option<&Self::StreamId>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<EncryptionError<Err, K::Error>, Self::Version>,
+    > {
+        let (sealed, version) = self
+            .inner
+            .load(id)
+            .await
+            .map_err(|e| e.map_repo_err(EncryptionError::Repository))?;
+
+        Ok((self.unseal_all(sealed).await?, version))
+    }
+
+    async fn load_from_version(
+        &self,
+        version: &RepositoryVersion<Self::Version>,
+        id: Option<&Self::StreamId>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<EncryptionError<Err, K::Error>, Self::Version>,
+    > {
+        let (sealed, version) = self
+            .inner
+            .load_from_version(version, id)
+            .await
+            .map_err(|e| e.map_repo_err(EncryptionError::Repository))?;
+
+        Ok((self.unseal_all(sealed).await?, version))
+    }
+
+    async fn append(
+        &mut self,
+        version: &RepositoryVersion<Self::Version>,
+        stream: &Self::StreamId,
+        events: &Vec<E>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<EncryptionError<Err, K::Error>, Self::Version>,
+    >
+    where
+        'a: 'async_trait,
+        E: 'async_trait,
+    {
+        let mut sealed = vec![];
+        for event in events {
+            sealed.push(
+                self.seal(event)
+                    .await
+                    .map_err(VersionedRepositoryError::RepoErr)?,
+            );
+        }
+
+        let (_, version) = self
+            .inner
+            .append(version, stream, &sealed)
+            .await
+            .map_err(|e| e.map_repo_err(EncryptionError::Repository))?;
+
+        Ok((events.to_owned(), version))
+    }
+}
+
+/// Events of forgotten subjects that have no replacement are skipped, as in `load`
+impl<'a, R, E, K, C, Err> StreamingEventRepository<'a, E, EncryptionError<Err, K::Error>>
+    for EncryptedEventRepository<R, E, K, C>
+where
+    R: StreamingEventRepository<'a, SealedEvent, Err> + Send + Sync,
+    R::Version: 'static,
+    E: Event + PersonalData + Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+    K: KeyStore,
+    C: Codec,
+    Err: Debug + Send + Sync + 'static,
+{
+    fn stream_from_version(
+        &self,
+        version: RepositoryVersion<Self::Version>,
+        id: Option<Self::StreamId>,
+    ) -> EventStream<'_, E, Self::Version, EncryptionError<Err, K::Error>> {
+        self.inner
+            .stream_from_version(version, id)
+            .map_err(|e| e.map_repo_err(EncryptionError::Repository))
+            .try_filter_map(move |recorded| async move {
+                Ok(self
+                    .unseal(recorded.event)
+                    .await
+                    .map_err(VersionedRepositoryError::RepoErr)?
+                    .map(|event| RecordedEvent {
+                        event,
+                        version: recorded.version,
+                        recorded_at: recorded.recorded_at,
+                        metadata: recorded.metadata,
+                    }))
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::{
+        decider::Event,
+        repository::{
+            event::VersionedEventRepositoryWithStreams,
+            in_memory::versioned_with_streams::InMemoryEventRepository, RepositoryVersion,
+            VersionedRepositoryError,
+        },
+        test_helpers::deciders::user::{Guitar, User, UserEvent, UserName},
+    };
+
+    use super::{
+        error::EncryptionError,
+        key_store::{FileKeyStore, InMemoryKeyStore, KeyStore},
+        EncryptedEventRepository, PersonalData, SealedEvent,
+    };
+
+    impl PersonalData for UserEvent {
+        fn data_subject(&self) -> Option<String> {
+            match self {
+                UserEvent::UserGuitarAdded(_, _) => None,
+                _ => Some(self.get_id().to_string()),
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn forgotten_subjects_cannot_be_read() {
+        let mut event_repository = EncryptedEventRepository::new(
+            InMemoryEventRepository::<SealedEvent>::new("test"),
+            InMemoryKeyStore::new(),
+        );
+        let id = "1".to_string();
+
+        let events = vec![
+            UserEvent::UserAdded(User::new(1, UserName::try_from("Mike").unwrap())),
+            UserEvent::UserGuitarAdded(
+                1,
+                Guitar {
+                    brand: "Gibson".to_string(),
+                },
+            ),
+        ];
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            event_repository.load(Some(&id)).await.unwrap(),
+            (events.to_owned(), RepositoryVersion::Exact(1))
+        );
+
+        let (stored, _) = event_repository.inner().load(Some(&id)).await.unwrap();
+        assert_matches!(stored[0], SealedEvent::Encrypted { .. });
+        assert_matches!(stored[1], SealedEvent::Plain { .. });
+
+        event_repository.forget("1").await.unwrap();
+
+        assert_eq!(
+            event_repository.load(Some(&id)).await.unwrap(),
+            (events[1..].to_vec(), RepositoryVersion::Exact(1))
+        );
+
+        let res = event_repository
+            .append(&RepositoryVersion::Exact(1), &id, &events[..1].to_vec())
+            .await;
+        assert_matches!(
+            res,
+            Err(VersionedRepositoryError::RepoErr(
+                EncryptionError::SubjectForgotten(_)
+            ))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn file_key_store_persists_keys() {
+        let path = std::env::temp_dir().join(format!("epoch-keys-{}.json", std::process::id()));
+
+        let key = FileKeyStore::open(&path)
+            .unwrap()
+            .get_or_create("1")
+            .await
+            .unwrap();
+        assert!(key.is_some());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let key_store = FileKeyStore::open(&path).unwrap();
+        assert_eq!(key_store.get("1").await.unwrap(), key);
+
+        key_store.forget("1").await.unwrap();
+        let key_store = FileKeyStore::open(&path).unwrap();
+        assert_eq!(key_store.get("1").await.unwrap(), None);
+        assert_eq!(key_store.get_or_create("1").await.unwrap(), None);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn file_key_store_keeps_keys_in_memory_only_once_saved() {
+        let dir = std::env::temp_dir().join(format!("epoch-keys-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let key_store = FileKeyStore::open(dir.join("keys.json")).unwrap();
+        let key = key_store.get_or_create("1").await.unwrap();
+
+        // Nowhere left to write the key file to
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(key_store.get_or_create("2").await.is_err());
+        assert_eq!(key_store.get("2").await.unwrap(), None);
+
+        assert!(key_store.forget("1").await.is_err());
+        assert_eq!(key_store.get("1").await.unwrap(), key);
+    }
+
+    #[test]
+    fn sealed_bytes_are_base64_in_json() {
+        let sealed = SealedEvent::Plain {
+            event_type: "UserAdded".to_owned(),
+            payload: b"{}".to_vec(),
+        };
+
+        let json = serde_json::to_value(&sealed).unwrap();
+        assert_eq!(json["Plain"]["payload"], "e30=");
+        assert_eq!(serde_json::from_value::<SealedEvent>(json).unwrap(), sealed);
+    }
+}
@@ -1,15 +1,23 @@
 use thiserror::Error;
 
+use crate::codec::CodecError;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("ESDB Error {0}")]
     ESDBGeneral(eventstore::Error),
     #[error("Error reading stream: {0}")]
     ReadStream(eventstore::Error),
+    #[error("Error reading $all: {0}")]
+    ReadAll(eventstore::Error),
     #[error("Could not deserialize event {0}")]
-    DeserializeEvent(serde_json::Error),
+    DeserializeEvent(CodecError),
     #[error("Could not serialize event {0}")]
-    SerializeEventDataPayload(serde_json::Error),
+    SerializeEventDataPayload(CodecError),
     #[error("Could not write to stream {0}: {1}")]
     WriteStream(String, eventstore::Error),
+    #[error("Stream {0} has been deleted")]
+    StreamDeleted(String),
+    #[error("ESDB cannot append to several streams in one transaction")]
+    MultiStreamAppendUnsupported,
 }
@@ -0,0 +1,178 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use async_trait::async_trait;
+use eventstore::{AppendToStreamOptions, ReadStreamOptions, StreamPosition};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    codec::Codec,
+    decider::Event,
+    repository::{
+        idempotency::IdempotentEventRepository, RepositoryVersion, VersionedRepositoryError,
+    },
+};
+
+use super::{error::Error, ESDBEventRepository, IDEMPOTENCY_KEY};
+
+impl<E, C: Codec> ESDBEventRepository<E, C> {
+    /// Ids are derived from the stream, key and position in the append, so ESDB itself drops a repeated append
+    fn idempotent_event_id(stream: &str, key: &str, index: usize) -> Uuid {
+        Uuid::new_v5(
+            &Uuid::NAMESPACE_OID,
+            format!("{}/{}/{}", stream, key, index).as_bytes(),
+        )
+    }
+
+    fn idempotency_key_of(recorded: &eventstore::RecordedEvent) -> Option<String> {
+        serde_json::from_slice::<HashMap<String, serde_json::Value>>(&recorded.custom_metadata)
+            .ok()
+            .and_then(|metadata| {
+                metadata
+                    .get(IDEMPOTENCY_KEY)
+                    .and_then(|key| key.as_str().map(str::to_owned))
+            })
+    }
+
+    /// Events appended under `key` with the stream's current version, reading the stream newest first
+    ///
+    /// Events of one append are contiguous, so reading stops at the first event before them. A key that is not
+    /// there costs a read of the whole window
+    async fn find_keyed(
+        &self,
+        stream: &str,
+        key: &str,
+    ) -> Result<Option<(Vec<E>, RepositoryVersion<usize>)>, VersionedRepositoryError<Error, usize>>
+    where
+        E: DeserializeOwned,
+    {
+        let mut read = self
+            .client
+            .read_stream(
+                stream.to_owned(),
+                &ReadStreamOptions::default()
+                    .backwards()
+                    .position(StreamPosition::End)
+                    .max_count(self.idempotency_window),
+            )
+            .await
+            .map_err(Error::ESDBGeneral)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let mut current = None;
+        let mut keyed = vec![];
+
+        loop {
+            match read.next().await {
+                Ok(Some(ev)) => {
+                    let recorded = ev.get_original_event();
+                    current.get_or_insert(recorded.revision);
+
+                    if Self::idempotency_key_of(recorded).as_deref() == Some(key) {
+                        keyed.push(
+                            self.decode(recorded)
+                                .map_err(Error::DeserializeEvent)
+                                .map_err(VersionedRepositoryError::RepoErr)?,
+                        );
+                    } else if !keyed.is_empty() {
+                        break;
+                    }
+                }
+                Ok(None) | Err(eventstore::Error::ResourceNotFound) => break,
+                Err(eventstore::Error::ResourceDeleted) => {
+                    return Err(VersionedRepositoryError::RepoErr(Error::StreamDeleted(
+                        stream.to_owned(),
+                    )))
+                }
+                Err(e) => return Err(VersionedRepositoryError::RepoErr(Error::ReadStream(e))),
+            }
+        }
+
+        if keyed.is_empty() {
+            return Ok(None);
+        }
+
+        keyed.reverse();
+
+        Ok(current.map(|revision| {
+            (
+                keyed,
+                RepositoryVersion::Exact(revision.try_into().unwrap()),
+            )
+        }))
+    }
+}
+
+/// Keys are stored in the metadata of the events appended under them and looked up by reading the stream
+/// newest first, at most `with_idempotency_window` events deep. A first attempt, whose key is not there yet,
+/// therefore reads the whole window, and a key appended more than a window of events ago is no longer found -
+/// size the window to the events a retry can race with
+///
+/// An append repeated at the same version is dropped by ESDB through the key's deterministic event ids. One
+/// repeated at a later version conflicts, and the events already appended under the key are returned instead
+#[async_trait]
+impl<'a, E, C> IdempotentEventRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    async fn processed(
+        &self,
+        stream: &String,
+        key: &str,
+    ) -> Result<Option<Vec<E>>, VersionedRepositoryError<Error, usize>> {
+        Ok(self
+            .find_keyed(&self.get_stream(Some(stream)), key)
+            .await?
+            .map(|(events, _)| events))
+    }
+
+    async fn append_idempotent(
+        &mut self,
+        version: &RepositoryVersion<usize>,
+        stream: &String,
+        events: &[E],
+        key: &str,
+    ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>>
+    where
+        'a: 'async_trait,
+        E: 'async_trait,
+    {
+        let stream_name = self.get_stream(Some(stream));
+        let mut prepared_events = vec![];
+
+        for (index, e) in events.iter().enumerate() {
+            let ed = self
+                .to_event_data(e, Some(key))
+                .map(|ed| ed.id(Self::idempotent_event_id(&stream_name, key, index)))
+                .map_err(Error::SerializeEventDataPayload)
+                .map_err(VersionedRepositoryError::RepoErr)?;
+
+            prepared_events.push(ed);
+        }
+
+        let res = self
+            .client
+            .append_to_stream(
+                stream_name.to_owned(),
+                &AppendToStreamOptions::default()
+                    .expected_revision(Self::version_to_expected_revision(version)),
+                prepared_events,
+            )
+            .await;
+
+        match res {
+            Ok(res) => Ok((
+                events.to_owned(),
+                RepositoryVersion::Exact(res.next_expected_version.try_into().unwrap()),
+            )),
+            Err(e @ eventstore::Error::WrongExpectedVersion { .. }) => {
+                match self.find_keyed(&stream_name, key).await? {
+                    Some(appended) => Ok(appended),
+                    None => Err(Self::write_error(version, &stream_name, e)),
+                }
+            }
+            Err(e) => Err(Self::write_error(version, &stream_name, e)),
+        }
+    }
+}
@@ -1,42 +1,85 @@
 pub use eventstore;
 
-use std::{fmt::Debug, marker::PhantomData};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    marker::PhantomData,
+    time::SystemTime,
+};
 
 use async_trait::async_trait;
 use eventstore::{
-    AppendToStreamOptions, Client, CurrentRevision, EventData, ExpectedRevision, ReadStreamOptions,
-    ResolvedEvent, StreamPosition,
+    AppendToStreamOptions, Client, CurrentRevision, DeleteStreamOptions, EventData,
+    ExpectedRevision, Position, ReadAllOptions, ReadStream, ReadStreamOptions, ResolvedEvent,
+    StreamMetadataResult, StreamPosition, TombstoneStreamOptions,
 };
+use futures::{stream, StreamExt, TryStreamExt};
 use serde::{de::DeserializeOwned, Serialize};
 use uuid::Uuid;
 
-use crate::decider::Event;
+use crate::{
+    codec::{Codec, CodecError, JsonCodec, JSON_CONTENT_TYPE},
+    decider::Event,
+};
 
 use self::error::Error;
 
 use super::{
-    event::VersionedEventRepositoryWithStreams, RepositoryVersion, VersionDiff,
-    VersionedRepositoryError,
+    event::{
+        AllEventsFilter, AllEventsRepository, BackwardEventRepository, DeletableEventRepository,
+        EventStream, FilteredEventRepository, GlobalEvent, MultiStreamEventRepository,
+        RecordedEvent, StreamAppend, StreamInfo, StreamInfoRepository, StreamListingRepository,
+        StreamMetadata, StreamMetadataRepository, StreamingEventRepository,
+        VersionedEventRepositoryWithStreams,
+    },
+    RepositoryVersion, VersionDiff, VersionedRepositoryError,
 };
 
 pub mod error;
+pub mod idempotency;
+
+const CONTENT_TYPE_KEY: &str = "content-type";
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+/// Newest events of a stream searched for an idempotency key by default
+pub const DEFAULT_IDEMPOTENCY_WINDOW: usize = 1000;
+/// Recorded event metadata key holding the ESDB event id
+pub const EVENT_ID_KEY: &str = "event-id";
 
 #[derive(Clone)]
-pub struct ESDBEventRepository<E> {
+pub struct ESDBEventRepository<E, C = JsonCodec> {
     client: Client,
     stream_name: String,
+    codec: C,
+    idempotency_window: usize,
     _hidden: PhantomData<E>,
 }
 
 impl<E> ESDBEventRepository<E> {
     pub fn new(client: &Client, stream_name: &str) -> Self {
+        Self::with_codec(client, stream_name, JsonCodec)
+    }
+}
+
+impl<E, C: Codec> ESDBEventRepository<E, C> {
+    /// Events are written with `codec` and tagged with its content type - events tagged with another content
+    /// type are decoded with the codec the tag names
+    pub fn with_codec(client: &Client, stream_name: &str, codec: C) -> Self {
         Self {
             client: client.to_owned(),
             stream_name: stream_name.to_owned(),
+            codec,
+            idempotency_window: DEFAULT_IDEMPOTENCY_WINDOW,
             _hidden: PhantomData::default(),
         }
     }
 
+    /// How many of a stream's newest events are searched for an idempotency key - see
+    /// `IdempotentEventRepository`
+    pub fn with_idempotency_window(mut self, window: usize) -> Self {
+        self.idempotency_window = window.max(1);
+        self
+    }
+
     fn get_stream(&self, stream_id: Option<&String>) -> String {
         if let Some(id) = stream_id {
             format!("{}-{}", self.stream_name, id)
@@ -45,6 +88,277 @@ impl<E> ESDBEventRepository<E> {
         }
     }
 
+    /// JSON events are stored as ESDB JSON so server side projections can still read them, other codecs are
+    /// stored as binary with their content type in the event's metadata
+    fn to_event_data(
+        &self,
+        event: &E,
+        idempotency_key: Option<&str>,
+    ) -> Result<EventData, CodecError>
+    where
+        E: Event + Serialize,
+    {
+        let mut metadata = HashMap::new();
+
+        if let Some(key) = idempotency_key {
+            metadata.insert(IDEMPOTENCY_KEY, key);
+        }
+
+        let event_data = if self.codec.content_type() == JSON_CONTENT_TYPE {
+            EventData::json(event.event_type(), event)?
+        } else {
+            metadata.insert(CONTENT_TYPE_KEY, self.codec.content_type());
+            EventData::binary(event.event_type(), self.codec.encode(event)?.into())
+        };
+
+        if metadata.is_empty() {
+            return Ok(event_data);
+        }
+
+        Ok(event_data.metadata_as_json(metadata)?)
+    }
+
+    fn content_type_of(recorded: &eventstore::RecordedEvent) -> String {
+        if recorded.is_json {
+            return JSON_CONTENT_TYPE.to_owned();
+        }
+
+        serde_json::from_slice::<HashMap<String, serde_json::Value>>(&recorded.custom_metadata)
+            .ok()
+            .and_then(|metadata| {
+                metadata
+                    .get(CONTENT_TYPE_KEY)
+                    .and_then(|content_type| content_type.as_str().map(str::to_owned))
+            })
+            .unwrap_or_else(|| "application/octet-stream".to_owned())
+    }
+
+    fn decode(&self, recorded: &eventstore::RecordedEvent) -> Result<E, CodecError>
+    where
+        E: DeserializeOwned,
+    {
+        self.codec
+            .decode_tagged(&Self::content_type_of(recorded), &recorded.data)
+    }
+
+    /// `None` for system events (`$` prefixed types such as deletes) and links whose event is gone
+    fn to_recorded_event(&self, ev: ResolvedEvent) -> Result<Option<RecordedEvent<E, usize>>, Error>
+    where
+        E: DeserializeOwned,
+    {
+        let version = ev.get_original_event().revision.try_into().unwrap();
+        let event_data = match ev.event {
+            Some(event_data) if !event_data.event_type.starts_with('$') => event_data,
+            _ => return Ok(None),
+        };
+
+        let mut metadata = serde_json::from_slice::<HashMap<String, serde_json::Value>>(
+            &event_data.custom_metadata,
+        )
+        .unwrap_or_default();
+        metadata.insert(
+            EVENT_ID_KEY.to_owned(),
+            serde_json::Value::String(event_data.id.to_string()),
+        );
+
+        Ok(Some(RecordedEvent {
+            event: self.decode(&event_data).map_err(Error::DeserializeEvent)?,
+            version,
+            recorded_at: Some(SystemTime::from(event_data.created)),
+            metadata,
+        }))
+    }
+
+    /// Reads a stream from `version`, deserializing only the events whose type passes `keep`
+    async fn read_events(
+        &self,
+        version: &RepositoryVersion<usize>,
+        id: Option<&String>,
+        keep: impl Fn(&str) -> bool + Send,
+    ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>>
+    where
+        E: DeserializeOwned + Send,
+    {
+        let mut stream = self
+            .client
+            .read_stream(
+                self.get_stream(id),
+                &ReadStreamOptions::default()
+                    .resolve_link_tos()
+                    .position(Self::version_to_esdb_position(version)),
+            )
+            .await
+            .map_err(Error::ESDBGeneral)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let mut rv = vec![];
+        let mut pos = RepositoryVersion::StreamExists;
+
+        loop {
+            match stream.next().await {
+                Ok(Some(ev)) => {
+                    pos = RepositoryVersion::Exact(
+                        ev.get_original_event().revision.try_into().unwrap(),
+                    );
+
+                    // Checked on the recorded type so skipped and system events are never deserialized
+                    if let Some(event_data) = ev
+                        .event
+                        .filter(|e| !e.event_type.starts_with('$') && keep(&e.event_type))
+                    {
+                        rv.push(
+                            self.decode(&event_data)
+                                .map_err(Error::DeserializeEvent)
+                                .map_err(VersionedRepositoryError::RepoErr)?,
+                        );
+                    }
+                }
+                Ok(None) => break,
+                Err(eventstore::Error::ResourceNotFound) => {
+                    return Ok((vec![], RepositoryVersion::NoStream))
+                }
+                Err(eventstore::Error::ResourceDeleted) => {
+                    return Err(VersionedRepositoryError::RepoErr(Error::StreamDeleted(
+                        self.get_stream(id),
+                    )))
+                }
+                Err(e) => return Err(VersionedRepositoryError::RepoErr(Error::ReadStream(e))),
+            }
+        }
+
+        Ok((rv, pos))
+    }
+
+    /// Revision of the last event, the number of events a read returns and when the first and last of those
+    /// were recorded - `None` when the stream does not exist
+    ///
+    /// Reads the whole stream without deserializing it, since the server hides events below `$tb` (truncation
+    /// and soft deletes) and past `$maxCount` or `$maxAge` only on reads, and the category's links to deleted
+    /// events only resolve to nothing
+    async fn count_events(
+        &self,
+        stream: &str,
+    ) -> Result<Option<(u64, usize, Option<(SystemTime, SystemTime)>)>, eventstore::Error> {
+        let mut read = self
+            .client
+            .read_stream(
+                stream.to_owned(),
+                &ReadStreamOptions::default().resolve_link_tos(),
+            )
+            .await?;
+
+        let mut revision = None;
+        let mut count = 0;
+        let mut recorded: Option<(SystemTime, SystemTime)> = None;
+
+        loop {
+            match read.next().await {
+                Ok(Some(ev)) => {
+                    revision = Some(ev.get_original_event().revision);
+
+                    if let Some(event) = ev.event.filter(|e| !e.event_type.starts_with('$')) {
+                        let at = SystemTime::from(event.created);
+                        count += 1;
+                        recorded = Some(recorded.map_or((at, at), |(first, _)| (first, at)));
+                    }
+                }
+                Ok(None) => break,
+                Err(eventstore::Error::ResourceNotFound) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(revision.map(|revision| (revision, count, recorded)))
+    }
+
+    fn write_error(
+        version: &RepositoryVersion<usize>,
+        stream: &str,
+        e: eventstore::Error,
+    ) -> VersionedRepositoryError<Error, usize> {
+        match e {
+            eventstore::Error::WrongExpectedVersion { current, .. } => {
+                VersionedRepositoryError::VersionConflict(VersionDiff::new(
+                    *version,
+                    Self::current_revision_to_version(&current),
+                ))
+            }
+            eventstore::Error::ResourceDeleted => {
+                VersionedRepositoryError::RepoErr(Error::StreamDeleted(stream.to_owned()))
+            }
+            e => VersionedRepositoryError::RepoErr(Error::WriteStream(stream.to_owned(), e)),
+        }
+    }
+
+    /// Metadata of a stream and the revision of its `$$` metadata stream to write the next version against
+    async fn read_metadata(
+        &self,
+        stream: &str,
+    ) -> Result<
+        (eventstore::StreamMetadata, ExpectedRevision),
+        VersionedRepositoryError<Error, usize>,
+    > {
+        let res = self
+            .client
+            .get_stream_metadata(stream.to_owned(), &ReadStreamOptions::default())
+            .await
+            .map_err(Error::ReadStream)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        match res {
+            StreamMetadataResult::Success(versioned) => Ok((
+                versioned.metadata().to_owned(),
+                ExpectedRevision::Exact(versioned.version()),
+            )),
+            StreamMetadataResult::NotFound { .. } => Ok((
+                eventstore::StreamMetadata::builder().build(),
+                ExpectedRevision::NoStream,
+            )),
+            StreamMetadataResult::Deleted { .. } => Err(VersionedRepositoryError::RepoErr(
+                Error::StreamDeleted(stream.to_owned()),
+            )),
+        }
+    }
+
+    /// Applies `update` to the stream's metadata and writes it against the revision it was read at, reading it
+    /// again when another writer got in between
+    ///
+    /// `$tb` only ever moves forward - ESDB's soft delete is itself a `$tb`, so lowering it would bring deleted
+    /// events back
+    async fn update_metadata(
+        &self,
+        stream: &str,
+        update: impl Fn(&mut eventstore::StreamMetadata) + Send,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        loop {
+            let (mut metadata, revision) = self.read_metadata(stream).await?;
+            let truncate_before = metadata.truncate_before;
+
+            update(&mut metadata);
+            metadata.truncate_before = metadata.truncate_before.max(truncate_before);
+
+            match self
+                .client
+                .set_stream_metadata(
+                    stream.to_owned(),
+                    &AppendToStreamOptions::default().expected_revision(revision),
+                    metadata,
+                )
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(eventstore::Error::WrongExpectedVersion { .. }) => continue,
+                Err(e) => return Err(Self::write_error(&RepositoryVersion::Any, stream, e)),
+            }
+        }
+    }
+
+    fn category_of(stream: &str) -> &str {
+        stream
+            .split_once('-')
+            .map_or(stream, |(category, _)| category)
+    }
+
     fn version_to_esdb_position(version: &RepositoryVersion<usize>) -> StreamPosition<u64> {
         if let RepositoryVersion::Exact(u) = version {
             StreamPosition::Position(u.to_owned().try_into().unwrap())
@@ -71,9 +385,10 @@ impl<E> ESDBEventRepository<E> {
 }
 
 #[async_trait]
-impl<'a, E> VersionedEventRepositoryWithStreams<'a, E, Error> for ESDBEventRepository<E>
+impl<'a, E, C> VersionedEventRepositoryWithStreams<'a, E, Error> for ESDBEventRepository<E, C>
 where
     E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
 {
     type StreamId = String;
     type Version = usize;
@@ -90,46 +405,7 @@ where
         version: &RepositoryVersion<usize>,
         id: Option<&Self::StreamId>,
     ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>> {
-        let mut stream = self
-            .client
-            .read_stream(
-                self.get_stream(id),
-                &ReadStreamOptions::default()
-                    .resolve_link_tos()
-                    .position(Self::version_to_esdb_position(version)),
-            )
-            .await
-            .map_err(Error::ESDBGeneral)
-            .map_err(VersionedRepositoryError::RepoErr)?;
-
-        let mut evts: Vec<ResolvedEvent> = vec![];
-
-        loop {
-            match stream.next().await {
-                Ok(Some(event)) => evts.push(event),
-                Ok(None) => break,
-                Err(eventstore::Error::ResourceNotFound) => {
-                    return Ok((vec![], RepositoryVersion::NoStream))
-                }
-                Err(e) => return Err(VersionedRepositoryError::RepoErr(Error::ReadStream(e))),
-            }
-        }
-
-        let mut rv = vec![];
-        let mut pos = RepositoryVersion::StreamExists;
-
-        for ev in evts {
-            pos = RepositoryVersion::Exact(ev.get_original_event().revision.try_into().unwrap());
-
-            if let Some(event_data) = ev.event {
-                // Continue on deser failure - occasionally you'll get delete and other system types in the stream
-                if let Ok(event) = event_data.as_json::<E>().map_err(Error::DeserializeEvent) {
-                    rv.push(event);
-                }
-            }
-        }
-
-        Ok((rv, pos))
+        self.read_events(version, id, |_| true).await
     }
 
     async fn append(
@@ -145,7 +421,8 @@ where
         let mut perpared_events = vec![];
 
         for e in events {
-            let ed = EventData::json(e.event_type(), e)
+            let ed = self
+                .to_event_data(e, None)
                 .map(|ed| ed.id(Uuid::new_v4()))
                 .map_err(Error::SerializeEventDataPayload)
                 .map_err(VersionedRepositoryError::RepoErr)?;
@@ -162,16 +439,7 @@ where
                 perpared_events,
             )
             .await
-            .map_err(|e| {
-                if let eventstore::Error::WrongExpectedVersion { current, .. } = e {
-                    VersionedRepositoryError::VersionConflict(VersionDiff::new(
-                        *version,
-                        Self::current_revision_to_version(&current),
-                    ))
-                } else {
-                    VersionedRepositoryError::RepoErr(Error::WriteStream(stream.to_owned(), e))
-                }
-            })?;
+            .map_err(|e| Self::write_error(version, stream, e))?;
 
         Ok((
             events.to_owned(),
@@ -180,8 +448,391 @@ where
     }
 }
 
+/// ESDB transactions only ever cover a single stream, so multi stream appends always fail with
+/// `Error::MultiStreamAppendUnsupported` and nothing is written
+#[async_trait]
+impl<'a, E, C> MultiStreamEventRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    async fn append_multi(
+        &mut self,
+        _appends: &[StreamAppend<E, String, usize>],
+    ) -> Result<Vec<(Vec<E>, RepositoryVersion<usize>)>, VersionedRepositoryError<Error, usize>>
+    where
+        'a: 'async_trait,
+        E: 'async_trait,
+    {
+        Err(VersionedRepositoryError::RepoErr(
+            Error::MultiStreamAppendUnsupported,
+        ))
+    }
+}
+
+#[async_trait]
+impl<'a, E, C> FilteredEventRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    async fn load_types_from_version(
+        &self,
+        version: &RepositoryVersion<usize>,
+        id: Option<&Self::StreamId>,
+        event_types: &[&str],
+    ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>> {
+        self.read_events(version, id, |event_type| event_types.contains(&event_type))
+            .await
+    }
+}
+
+impl<'a, E, C> StreamingEventRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    fn stream_from_version(
+        &self,
+        version: RepositoryVersion<usize>,
+        id: Option<String>,
+    ) -> EventStream<'_, E, usize, Error> {
+        let stream_name = self.get_stream(id.as_ref());
+        let options = ReadStreamOptions::default()
+            .resolve_link_tos()
+            .position(Self::version_to_esdb_position(&version));
+
+        // The read is opened on first poll and pulled one event at a time from then on
+        stream::try_unfold(None, move |read: Option<ReadStream>| {
+            let client = self.client.clone();
+            let stream_name = stream_name.clone();
+            let options = options.clone();
+
+            async move {
+                let mut read = match read {
+                    Some(read) => read,
+                    None => client
+                        .read_stream(stream_name.to_owned(), &options)
+                        .await
+                        .map_err(Error::ESDBGeneral)?,
+                };
+
+                loop {
+                    match read.next().await {
+                        Ok(Some(ev)) => {
+                            if let Some(recorded) = self.to_recorded_event(ev)? {
+                                return Ok(Some((recorded, Some(read))));
+                            }
+                        }
+                        Ok(None) | Err(eventstore::Error::ResourceNotFound) => return Ok(None),
+                        Err(eventstore::Error::ResourceDeleted) => {
+                            return Err(Error::StreamDeleted(stream_name))
+                        }
+                        Err(e) => return Err(Error::ReadStream(e)),
+                    }
+                }
+            }
+        })
+        .map_err(VersionedRepositoryError::RepoErr)
+        .boxed()
+    }
+}
+
+#[async_trait]
+impl<'a, E, C> BackwardEventRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    async fn load_backward(
+        &self,
+        id: Option<&String>,
+        from: &RepositoryVersion<usize>,
+        max_count: usize,
+    ) -> Result<Vec<RecordedEvent<E, usize>>, VersionedRepositoryError<Error, usize>> {
+        let position = match from {
+            RepositoryVersion::Exact(_) => Self::version_to_esdb_position(from),
+            _ => StreamPosition::End,
+        };
+
+        let mut stream = self
+            .client
+            .read_stream(
+                self.get_stream(id),
+                &ReadStreamOptions::default()
+                    .resolve_link_tos()
+                    .backwards()
+                    .position(position),
+            )
+            .await
+            .map_err(Error::ESDBGeneral)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let mut rv = vec![];
+
+        // Counted here rather than with the read's max_count so skipped events do not use up the budget
+        while rv.len() < max_count {
+            match stream.next().await {
+                Ok(Some(ev)) => rv.extend(
+                    self.to_recorded_event(ev)
+                        .map_err(VersionedRepositoryError::RepoErr)?,
+                ),
+                Ok(None) | Err(eventstore::Error::ResourceNotFound) => break,
+                Err(eventstore::Error::ResourceDeleted) => {
+                    return Err(VersionedRepositoryError::RepoErr(Error::StreamDeleted(
+                        self.get_stream(id),
+                    )))
+                }
+                Err(e) => return Err(VersionedRepositoryError::RepoErr(Error::ReadStream(e))),
+            }
+        }
+
+        Ok(rv)
+    }
+}
+
+#[async_trait]
+impl<'a, E, C> DeletableEventRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    async fn soft_delete(
+        &mut self,
+        version: &RepositoryVersion<usize>,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        let stream = self.get_stream(Some(stream));
+
+        self.client
+            .delete_stream(
+                stream.to_owned(),
+                &DeleteStreamOptions::default()
+                    .expected_revision(Self::version_to_expected_revision(version)),
+            )
+            .await
+            .map_err(|e| Self::write_error(version, &stream, e))?;
+
+        Ok(())
+    }
+
+    async fn hard_delete(
+        &mut self,
+        version: &RepositoryVersion<usize>,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        let stream = self.get_stream(Some(stream));
+
+        self.client
+            .tombstone_stream(
+                stream.to_owned(),
+                &TombstoneStreamOptions::default()
+                    .expected_revision(Self::version_to_expected_revision(version)),
+            )
+            .await
+            .map_err(|e| Self::write_error(version, &stream, e))?;
+
+        Ok(())
+    }
+
+    /// Raises `$tb` on the stream, events below it are removed on the next scavenge
+    async fn truncate_before(
+        &mut self,
+        version: &usize,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        self.update_metadata(&self.get_stream(Some(stream)), |metadata| {
+            metadata.truncate_before = Some(*version as u64)
+        })
+        .await
+    }
+}
+
+/// Maps onto ESDB's own stream metadata (`$maxAge`, `$maxCount`, `$tb` and custom properties), which the server
+/// enforces on reads and scavenges. ACLs and cache control set outside this repository are left untouched, and a
+/// `truncate_before` lower than the stored `$tb` (which includes the point of a soft delete) is ignored
+#[async_trait]
+impl<'a, E, C> StreamMetadataRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    async fn get_metadata(
+        &self,
+        stream: &String,
+    ) -> Result<StreamMetadata<usize>, VersionedRepositoryError<Error, usize>> {
+        let (metadata, _) = self.read_metadata(&self.get_stream(Some(stream))).await?;
+
+        Ok(StreamMetadata {
+            max_age: metadata.max_age,
+            max_count: metadata.max_count.map(|c| c as usize),
+            truncate_before: metadata.truncate_before.map(|v| v as usize),
+            custom: metadata.custom_properties,
+        })
+    }
+
+    async fn set_metadata(
+        &mut self,
+        stream: &String,
+        metadata: &StreamMetadata<usize>,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        self.update_metadata(&self.get_stream(Some(stream)), |esdb_metadata| {
+            esdb_metadata.max_age = metadata.max_age;
+            esdb_metadata.max_count = metadata.max_count.map(|c| c as u64);
+            esdb_metadata.truncate_before = metadata.truncate_before.map(|v| v as u64);
+            esdb_metadata.custom_properties = metadata.custom.to_owned();
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<'a, E, C> StreamInfoRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    async fn stream_info(
+        &self,
+        id: Option<&String>,
+    ) -> Result<StreamInfo<usize>, VersionedRepositoryError<Error, usize>> {
+        let (revision, event_count, recorded) = match self.count_events(&self.get_stream(id)).await
+        {
+            Ok(Some(counted)) => counted,
+            Ok(None) => return Ok(StreamInfo::no_stream()),
+            Err(eventstore::Error::ResourceDeleted) => {
+                return Ok(StreamInfo {
+                    deleted: true,
+                    ..StreamInfo::no_stream()
+                })
+            }
+            Err(e) => return Err(VersionedRepositoryError::RepoErr(Error::ReadStream(e))),
+        };
+
+        Ok(StreamInfo {
+            version: RepositoryVersion::Exact(revision.try_into().unwrap()),
+            event_count,
+            first_recorded_at: recorded.map(|(first, _)| first),
+            last_recorded_at: recorded.map(|(_, last)| last),
+            deleted: false,
+        })
+    }
+}
+
+/// Reads the links of the category's `$ce-` stream without resolving them, so the cost grows with the
+/// category rather than the whole store and needs the by category projection. Deleted streams are listed as
+/// long as their links are kept
+#[async_trait]
+impl<'a, E, C> StreamListingRepository<'a, E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    async fn list_streams(&self) -> Result<Vec<String>, VersionedRepositoryError<Error, usize>> {
+        let mut stream = self
+            .client
+            .read_stream(self.get_stream(None), &ReadStreamOptions::default())
+            .await
+            .map_err(Error::ESDBGeneral)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let prefix = format!("{}-", self.stream_name);
+        let mut seen = HashSet::new();
+        let mut ids = vec![];
+
+        loop {
+            match stream.next().await {
+                Ok(Some(ev)) => {
+                    // Link events hold `<revision>@<stream>` of the event they point to
+                    let link = ev.get_original_event();
+                    let target = std::str::from_utf8(&link.data)
+                        .ok()
+                        .and_then(|data| data.split_once('@'))
+                        .map(|(_, target)| target);
+
+                    if let Some(id) = target.and_then(|target| target.strip_prefix(&prefix)) {
+                        if seen.insert(id.to_owned()) {
+                            ids.push(id.to_owned());
+                        }
+                    }
+                }
+                Ok(None) | Err(eventstore::Error::ResourceNotFound) => break,
+                Err(e) => return Err(VersionedRepositoryError::RepoErr(Error::ReadStream(e))),
+            }
+        }
+
+        Ok(ids)
+    }
+}
+
+#[async_trait]
+impl<E, C> AllEventsRepository<E, Error> for ESDBEventRepository<E, C>
+where
+    E: Event + Sync + Send + Serialize + DeserializeOwned + Clone + Debug,
+    C: Codec,
+{
+    type Position = Position;
+
+    async fn load_all(
+        &self,
+        from: Option<&Position>,
+        filter: &AllEventsFilter,
+        max_count: Option<usize>,
+    ) -> Result<Vec<GlobalEvent<E, Position>>, Error> {
+        let mut stream = self
+            .client
+            .read_all(
+                &ReadAllOptions::default()
+                    .position(from.map_or(StreamPosition::Start, |p| StreamPosition::Position(*p))),
+            )
+            .await
+            .map_err(Error::ESDBGeneral)?;
+
+        let max_count = max_count.unwrap_or(usize::MAX);
+        let mut rv = vec![];
+
+        while rv.len() < max_count {
+            let ev = match stream.next().await.map_err(Error::ReadAll)? {
+                Some(ev) => ev,
+                None => break,
+            };
+            let recorded = ev.get_original_event();
+
+            // Reading $all from a position includes the event at that position
+            if Some(&recorded.position) == from {
+                continue;
+            }
+
+            // System streams and events carry no domain payload
+            if recorded.stream_id.starts_with('$') || recorded.event_type.starts_with('$') {
+                continue;
+            }
+
+            let category = Self::category_of(&recorded.stream_id);
+
+            // Filter before deserializing so unrelated categories cost nothing more than the read
+            if !filter.matches_category(category)
+                || !filter.matches_event_type(&recorded.event_type)
+            {
+                continue;
+            }
+
+            // Everything the filter lets through has to be an `E` - filter on categories when $all holds others
+            rv.push(GlobalEvent {
+                position: recorded.position,
+                stream: recorded.stream_id.to_owned(),
+                category: category.to_owned(),
+                event: self.decode(recorded).map_err(Error::DeserializeEvent)?,
+            });
+        }
+
+        Ok(rv)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
     use const_random::const_random;
 
     use eventstore::DeleteStreamOptions;
@@ -189,7 +840,7 @@ mod tests {
     use super::*;
 
     use crate::test_helpers::{
-        deciders::user::UserEvent,
+        deciders::user::{Guitar, User, UserEvent, UserName},
         repository::{
             versioned_event_repository_with_streams_occ_spec,
             versioned_event_repository_with_streams_spec,
@@ -219,6 +870,19 @@ mod tests {
         client
     }
 
+    fn user_events(id: usize) -> Vec<UserEvent> {
+        vec![
+            UserEvent::UserAdded(User::new(id, UserName::try_from("Mike").unwrap())),
+            UserEvent::UserNameUpdated(id, UserName::try_from("Mike2").unwrap()),
+            UserEvent::UserGuitarAdded(
+                id,
+                Guitar {
+                    brand: "Gibson".to_string(),
+                },
+            ),
+        ]
+    }
+
     #[actix_rt::test]
     async fn repository_spec_tests() {
         let base_stream = BASE_STREAM;
@@ -238,4 +902,300 @@ mod tests {
 
         let _ = versioned_event_repository_with_streams_occ_spec(event_repository).await;
     }
+
+    #[actix_rt::test]
+    async fn load_all_across_categories() {
+        let users_stream = format!("{}_all_users", BASE_STREAM);
+        let admins_stream = format!("{}_all_admins", BASE_STREAM);
+        let client = store_from_environment(&users_stream, vec![1]).await;
+        let mut users = ESDBEventRepository::<UserEvent>::new(&client, &users_stream);
+        let mut admins = ESDBEventRepository::<UserEvent>::new(&client, &admins_stream);
+        let id = "1".to_string();
+        let events = user_events(1);
+
+        users
+            .append(&RepositoryVersion::NoStream, &id, &events[..1].to_vec())
+            .await
+            .unwrap();
+        admins
+            .append(&RepositoryVersion::NoStream, &id, &events[..1].to_vec())
+            .await
+            .unwrap();
+        users
+            .append(&RepositoryVersion::Exact(0), &id, &events[1..2].to_vec())
+            .await
+            .unwrap();
+
+        let filter = AllEventsFilter::new().with_categories(&[&users_stream, &admins_stream]);
+        let all = users.load_all(None, &filter, None).await.unwrap();
+        assert_eq!(
+            all.iter()
+                .map(|e| (e.category.as_str(), e.event.to_owned()))
+                .collect::<Vec<_>>(),
+            vec![
+                (users_stream.as_str(), events[0].to_owned()),
+                (admins_stream.as_str(), events[0].to_owned()),
+                (users_stream.as_str(), events[1].to_owned()),
+            ]
+        );
+        assert!(all.windows(2).all(|w| w[0].position < w[1].position));
+
+        let after_first = admins
+            .load_all(Some(&all[0].position), &filter, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(after_first.len(), 1);
+        assert_eq!(after_first[0].stream, format!("{}-1", admins_stream));
+
+        let renamed = users
+            .load_all(
+                None,
+                &AllEventsFilter::new()
+                    .with_categories(&[&users_stream])
+                    .with_event_types(&["UserNameUpdated"]),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0].event, events[1]);
+    }
+
+    #[actix_rt::test]
+    async fn load_backward_newest_first() {
+        let base_stream = format!("{}_backward", BASE_STREAM);
+        let client = store_from_environment(&base_stream, vec![1]).await;
+        let mut event_repository = ESDBEventRepository::<UserEvent>::new(&client, &base_stream);
+        let id = "1".to_string();
+        let events = user_events(1);
+
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+
+        let last_two = event_repository
+            .load_backward(Some(&id), &RepositoryVersion::Any, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            last_two
+                .iter()
+                .map(|e| (e.version, e.event.to_owned()))
+                .collect::<Vec<_>>(),
+            vec![(2, events[2].to_owned()), (1, events[1].to_owned())]
+        );
+        assert!(last_two[0].metadata.contains_key(EVENT_ID_KEY));
+
+        let from_first = event_repository
+            .load_backward(Some(&id), &RepositoryVersion::Exact(1), 5)
+            .await
+            .unwrap();
+        assert_eq!(
+            from_first.iter().map(|e| e.version).collect::<Vec<_>>(),
+            vec![1, 0]
+        );
+
+        assert!(event_repository
+            .load_backward(Some(&"2".to_string()), &RepositoryVersion::Any, 5)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn soft_delete_then_hard_delete() {
+        let base_stream = format!("{}_delete", BASE_STREAM);
+        let client = store_from_environment(&base_stream, vec![1]).await;
+        let mut event_repository = ESDBEventRepository::<UserEvent>::new(&client, &base_stream);
+        let id = "1".to_string();
+        let events = user_events(1);
+
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events[..2].to_vec())
+            .await
+            .unwrap();
+
+        assert_matches!(
+            event_repository
+                .soft_delete(&RepositoryVersion::Exact(0), &id)
+                .await,
+            Err(VersionedRepositoryError::VersionConflict(_))
+        );
+
+        event_repository
+            .soft_delete(&RepositoryVersion::Exact(1), &id)
+            .await
+            .unwrap();
+        assert_eq!(
+            event_repository.load(Some(&id)).await.unwrap(),
+            (vec![], RepositoryVersion::NoStream)
+        );
+
+        // A soft deleted stream carries on from its last revision
+        event_repository
+            .append(&RepositoryVersion::Any, &id, &events[2..].to_vec())
+            .await
+            .unwrap();
+        assert_eq!(
+            event_repository.load(Some(&id)).await.unwrap(),
+            (events[2..].to_vec(), RepositoryVersion::Exact(2))
+        );
+
+        event_repository
+            .hard_delete(&RepositoryVersion::Exact(2), &id)
+            .await
+            .unwrap();
+
+        assert_matches!(
+            event_repository.load(Some(&id)).await,
+            Err(VersionedRepositoryError::RepoErr(Error::StreamDeleted(_)))
+        );
+        assert_matches!(
+            event_repository
+                .append(&RepositoryVersion::Any, &id, &events[..1].to_vec())
+                .await,
+            Err(VersionedRepositoryError::RepoErr(Error::StreamDeleted(_)))
+        );
+        assert!(
+            event_repository
+                .stream_info(Some(&id))
+                .await
+                .unwrap()
+                .deleted
+        );
+    }
+
+    #[actix_rt::test]
+    async fn truncate_before_hides_older_events() {
+        let base_stream = format!("{}_truncate", BASE_STREAM);
+        let client = store_from_environment(&base_stream, vec![1]).await;
+        let mut event_repository = ESDBEventRepository::<UserEvent>::new(&client, &base_stream);
+        let id = "1".to_string();
+        let events = user_events(1);
+
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+        event_repository.truncate_before(&1, &id).await.unwrap();
+
+        assert_eq!(
+            event_repository.load(Some(&id)).await.unwrap(),
+            (events[1..].to_vec(), RepositoryVersion::Exact(2))
+        );
+        assert_eq!(
+            event_repository
+                .load_backward(Some(&id), &RepositoryVersion::Any, 5)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[actix_rt::test]
+    async fn metadata_never_lowers_truncate_before() {
+        let base_stream = format!("{}_metadata", BASE_STREAM);
+        let client = store_from_environment(&base_stream, vec![1, 2]).await;
+        let mut event_repository = ESDBEventRepository::<UserEvent>::new(&client, &base_stream);
+        let (truncated, soft_deleted) = ("1".to_string(), "2".to_string());
+        let events = user_events(1);
+
+        event_repository
+            .append(&RepositoryVersion::NoStream, &truncated, &events)
+            .await
+            .unwrap();
+        event_repository
+            .truncate_before(&2, &truncated)
+            .await
+            .unwrap();
+        event_repository
+            .truncate_before(&1, &truncated)
+            .await
+            .unwrap();
+
+        let metadata = StreamMetadata {
+            max_count: Some(10),
+            truncate_before: Some(1),
+            custom: [("owner".to_string(), serde_json::json!("billing"))].into(),
+            ..StreamMetadata::default()
+        };
+        event_repository
+            .set_metadata(&truncated, &metadata)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            event_repository.get_metadata(&truncated).await.unwrap(),
+            StreamMetadata {
+                truncate_before: Some(2),
+                ..metadata.to_owned()
+            }
+        );
+        assert_eq!(
+            event_repository.load(Some(&truncated)).await.unwrap(),
+            (events[2..].to_vec(), RepositoryVersion::Exact(2))
+        );
+
+        event_repository
+            .append(&RepositoryVersion::NoStream, &soft_deleted, &events)
+            .await
+            .unwrap();
+        event_repository
+            .soft_delete(&RepositoryVersion::Exact(2), &soft_deleted)
+            .await
+            .unwrap();
+        event_repository
+            .set_metadata(&soft_deleted, &StreamMetadata::default())
+            .await
+            .unwrap();
+
+        // Soft deleted events stay hidden
+        assert_eq!(
+            event_repository.load(Some(&soft_deleted)).await.unwrap(),
+            (vec![], RepositoryVersion::NoStream)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn stream_info_counts_the_events_a_read_returns() {
+        let base_stream = format!("{}_info", BASE_STREAM);
+        let client = store_from_environment(&base_stream, vec![1]).await;
+        let mut event_repository = ESDBEventRepository::<UserEvent>::new(&client, &base_stream);
+        let id = "1".to_string();
+
+        assert_eq!(
+            event_repository.stream_info(Some(&id)).await.unwrap(),
+            StreamInfo::no_stream()
+        );
+
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &user_events(1))
+            .await
+            .unwrap();
+
+        let info = event_repository.stream_info(Some(&id)).await.unwrap();
+        assert_eq!(info.version, RepositoryVersion::Exact(2));
+        assert_eq!(info.event_count, 3);
+        assert!(info.first_recorded_at <= info.last_recorded_at);
+
+        event_repository.truncate_before(&2, &id).await.unwrap();
+
+        let info = event_repository.stream_info(Some(&id)).await.unwrap();
+        assert_eq!(info.version, RepositoryVersion::Exact(2));
+        assert_eq!(info.event_count, 1);
+        assert_eq!(info.first_recorded_at, info.last_recorded_at);
+
+        event_repository
+            .soft_delete(&RepositoryVersion::Exact(2), &id)
+            .await
+            .unwrap();
+
+        assert!(!event_repository
+            .stream_info(Some(&id))
+            .await
+            .unwrap()
+            .exists());
+    }
 }
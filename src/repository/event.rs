@@ -1,6 +1,11 @@
-use std::fmt::Debug;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    time::{Duration, SystemTime},
+};
 
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 
 use super::{RepositoryVersion, VersionedRepositoryError};
 use crate::decider::Event;
@@ -74,3 +79,341 @@ where
         'a: 'async_trait,
         E: 'async_trait;
 }
+
+/// Event read from the global log, tagged with the stream and category it was appended to
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalEvent<E, P> {
+    pub position: P,
+    pub stream: String,
+    pub category: String,
+    pub event: E,
+}
+
+/// Narrows a global read to some categories and/or event types - `None` matches everything
+#[derive(Debug, Clone, Default)]
+pub struct AllEventsFilter {
+    pub categories: Option<Vec<String>>,
+    pub event_types: Option<Vec<String>>,
+}
+
+impl AllEventsFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_categories(mut self, categories: &[&str]) -> Self {
+        self.categories = Some(categories.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    pub fn with_event_types(mut self, event_types: &[&str]) -> Self {
+        self.event_types = Some(event_types.iter().map(|t| t.to_string()).collect());
+        self
+    }
+
+    pub fn matches_category(&self, category: &str) -> bool {
+        self.categories
+            .as_ref()
+            .is_none_or(|categories| categories.iter().any(|c| c == category))
+    }
+
+    pub fn matches_event_type(&self, event_type: &str) -> bool {
+        self.event_types
+            .as_ref()
+            .is_none_or(|types| types.iter().any(|t| t == event_type))
+    }
+}
+
+/// Reads across every stream and category in commit order
+///
+/// Positions increase monotonically over the whole store so a projection can checkpoint the last position it
+/// handled and resume from it
+#[async_trait]
+pub trait AllEventsRepository<E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    type Position: Send + Sync + Eq + Ord + Clone;
+
+    /// Loads events positioned after `from` (exclusive), or from the start of the log when `from` is `None`
+    async fn load_all(
+        &self,
+        from: Option<&Self::Position>,
+        filter: &AllEventsFilter,
+        max_count: Option<usize>,
+    ) -> Result<Vec<GlobalEvent<E, Self::Position>>, Err>;
+}
+
+/// Event read through a `StreamingEventRepository` with the stream version it was written at
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedEvent<E, V> {
+    pub event: E,
+    pub version: V,
+    pub recorded_at: Option<SystemTime>,
+    /// What the backend stores next to the event, such as ESDB event ids and custom metadata - empty for
+    /// backends that store nothing
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+pub type EventStream<'s, E, V, Err> =
+    BoxStream<'s, Result<RecordedEvent<E, V>, VersionedRepositoryError<Err, V>>>;
+
+/// Reads events lazily instead of collecting a whole stream into a `Vec`
+pub trait StreamingEventRepository<'a, E, Err>:
+    VersionedEventRepositoryWithStreams<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    fn stream(&self, id: Option<Self::StreamId>) -> EventStream<'_, E, Self::Version, Err> {
+        self.stream_from_version(RepositoryVersion::Any, id)
+    }
+
+    /// Same starting point as `load_from_version` - the event at `version` is included
+    fn stream_from_version(
+        &self,
+        version: RepositoryVersion<Self::Version>,
+        id: Option<Self::StreamId>,
+    ) -> EventStream<'_, E, Self::Version, Err>;
+}
+
+/// Reads a stream newest first - recent activity views or finding the latest marker event
+#[async_trait]
+pub trait BackwardEventRepository<'a, E, Err>:
+    VersionedEventRepositoryWithStreams<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    /// Loads up to `max_count` events from `from` (inclusive) towards the start of the stream
+    ///
+    /// Any version other than `Exact` reads from the end of the stream
+    async fn load_backward(
+        &self,
+        id: Option<&Self::StreamId>,
+        from: &RepositoryVersion<Self::Version>,
+        max_count: usize,
+    ) -> Result<Vec<RecordedEvent<E, Self::Version>>, VersionedRepositoryError<Err, Self::Version>>;
+}
+
+/// Summary of a stream read without loading its events
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo<V> {
+    pub version: RepositoryVersion<V>,
+    pub event_count: usize,
+    pub first_recorded_at: Option<SystemTime>,
+    pub last_recorded_at: Option<SystemTime>,
+    pub deleted: bool,
+}
+
+impl<V> StreamInfo<V> {
+    pub fn no_stream() -> Self {
+        Self {
+            version: RepositoryVersion::NoStream,
+            event_count: 0,
+            first_recorded_at: None,
+            last_recorded_at: None,
+            deleted: false,
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.event_count > 0 && !self.deleted
+    }
+}
+
+/// Version and existence checks for OCC flows and ETags that do not need the events themselves
+#[async_trait]
+pub trait StreamInfoRepository<'a, E, Err>:
+    VersionedEventRepositoryWithStreams<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    async fn stream_info(
+        &self,
+        id: Option<&Self::StreamId>,
+    ) -> Result<StreamInfo<Self::Version>, VersionedRepositoryError<Err, Self::Version>>;
+}
+
+/// Removes events from streams
+///
+/// - `soft_delete` hides every event in the stream: reads return no events with `RepositoryVersion::NoStream`
+///   and appending again revives the stream, versions carrying on from where they were
+/// - `hard_delete` tombstones the stream: reads and appends fail with the backend's `StreamDeleted` error for good
+/// - `truncate_before` hides events below `version`, the remaining events keep their versions
+///
+/// Only the stream itself is affected - category and `$all` reads may still return removed events
+#[async_trait]
+pub trait DeletableEventRepository<'a, E, Err>:
+    VersionedEventRepositoryWithStreams<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    async fn soft_delete(
+        &mut self,
+        version: &RepositoryVersion<Self::Version>,
+        stream: &Self::StreamId,
+    ) -> Result<(), VersionedRepositoryError<Err, Self::Version>>;
+
+    async fn hard_delete(
+        &mut self,
+        version: &RepositoryVersion<Self::Version>,
+        stream: &Self::StreamId,
+    ) -> Result<(), VersionedRepositoryError<Err, Self::Version>>;
+
+    async fn truncate_before(
+        &mut self,
+        version: &Self::Version,
+        stream: &Self::StreamId,
+    ) -> Result<(), VersionedRepositoryError<Err, Self::Version>>;
+}
+
+/// Retention settings and user defined properties of a single stream
+///
+/// Events older than `max_age`, beyond the newest `max_count` or below `truncate_before` are no longer returned
+/// by reads
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamMetadata<V> {
+    pub max_age: Option<Duration>,
+    pub max_count: Option<usize>,
+    pub truncate_before: Option<V>,
+    pub custom: HashMap<String, serde_json::Value>,
+}
+
+impl<V> Default for StreamMetadata<V> {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            max_count: None,
+            truncate_before: None,
+            custom: HashMap::new(),
+        }
+    }
+}
+
+impl<V> StreamMetadata<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_max_count(mut self, max_count: usize) -> Self {
+        self.max_count = Some(max_count);
+        self
+    }
+
+    pub fn with_truncate_before(mut self, version: V) -> Self {
+        self.truncate_before = Some(version);
+        self
+    }
+
+    pub fn with_custom(mut self, key: &str, value: serde_json::Value) -> Self {
+        self.custom.insert(key.to_owned(), value);
+        self
+    }
+}
+
+#[async_trait]
+pub trait StreamMetadataRepository<'a, E, Err>:
+    VersionedEventRepositoryWithStreams<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    /// Default metadata for streams that never had any set
+    async fn get_metadata(
+        &self,
+        stream: &Self::StreamId,
+    ) -> Result<StreamMetadata<Self::Version>, VersionedRepositoryError<Err, Self::Version>>;
+
+    /// Replaces the stream's metadata as a whole
+    async fn set_metadata(
+        &mut self,
+        stream: &Self::StreamId,
+        metadata: &StreamMetadata<Self::Version>,
+    ) -> Result<(), VersionedRepositoryError<Err, Self::Version>>;
+}
+
+/// Reads only the events of the given types - projections usually care about a few types of a category
+///
+/// The returned version is the stream version reached by the read, skipped events included, so it can still
+/// be used for OCC. Backends drop unwanted events before deserializing them wherever they can
+#[async_trait]
+pub trait FilteredEventRepository<'a, E, Err>:
+    VersionedEventRepositoryWithStreams<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    async fn load_types_from_version(
+        &self,
+        version: &RepositoryVersion<Self::Version>,
+        id: Option<&Self::StreamId>,
+        event_types: &[&str],
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<Err, Self::Version>,
+    >;
+}
+
+/// One stream's part of a multi stream append
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamAppend<E, S, V> {
+    pub stream: S,
+    pub version: RepositoryVersion<V>,
+    pub events: Vec<E>,
+}
+
+impl<E, S, V> StreamAppend<E, S, V> {
+    pub fn new(stream: S, version: RepositoryVersion<V>, events: Vec<E>) -> Self {
+        Self {
+            stream,
+            version,
+            events,
+        }
+    }
+}
+
+/// Appends events for several streams in one transaction - commands such as a transfer between two accounts
+///
+/// Every stream is checked against its own expected version and either all appends are written or none are.
+/// Results are returned in the order of `appends`
+#[async_trait]
+pub trait MultiStreamEventRepository<'a, E, Err>:
+    VersionedEventRepositoryWithStreams<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    async fn append_multi(
+        &mut self,
+        appends: &[StreamAppend<E, Self::StreamId, Self::Version>],
+    ) -> Result<
+        Vec<(Vec<E>, RepositoryVersion<Self::Version>)>,
+        VersionedRepositoryError<Err, Self::Version>,
+    >
+    where
+        'a: 'async_trait,
+        E: 'async_trait;
+}
+
+/// Enumerates the streams of the repository's category - backups, migrations and tooling
+#[async_trait]
+pub trait StreamListingRepository<'a, E, Err>:
+    VersionedEventRepositoryWithStreams<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    /// Ids of the streams holding events, in the order they were first appended to
+    async fn list_streams(
+        &self,
+    ) -> Result<Vec<Self::StreamId>, VersionedRepositoryError<Err, Self::Version>>;
+}
@@ -0,0 +1,41 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+
+use crate::decider::Event;
+
+use super::{event::StreamingEventRepository, RepositoryVersion, VersionedRepositoryError};
+
+/// Event repositories that remember the idempotency key events were appended under
+///
+/// The key is checked in the same step as the append, so a retried command is never appended twice - not
+/// even when the retries race each other. Keys are scoped per stream
+#[async_trait]
+pub trait IdempotentEventRepository<'a, E, Err>: StreamingEventRepository<'a, E, Err>
+where
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    /// Events appended to `stream` under `key` - `None` if the key was not used on the stream yet
+    async fn processed(
+        &self,
+        stream: &Self::StreamId,
+        key: &str,
+    ) -> Result<Option<Vec<E>>, VersionedRepositoryError<Err, Self::Version>>;
+
+    /// Same as `append` unless `key` was already used on `stream`, in which case nothing is appended and the
+    /// events appended under the key are returned instead, whatever `version` is
+    async fn append_idempotent(
+        &mut self,
+        version: &RepositoryVersion<Self::Version>,
+        stream: &Self::StreamId,
+        events: &[E],
+        key: &str,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<Err, Self::Version>,
+    >
+    where
+        'a: 'async_trait,
+        E: 'async_trait;
+}
@@ -1,4 +1,6 @@
-use std::fmt::Debug;
+use std::{collections::HashMap, fmt::Debug, ops::Range, time::SystemTime};
+
+use super::event::StreamMetadata;
 
 pub mod simple;
 pub mod state;
@@ -7,5 +9,12 @@ pub mod versioned_with_streams;
 #[derive(Debug, Default)]
 pub(crate) struct InMemoryEventRepositoryState<E> {
     events: Vec<E>,
+    recorded_at: Vec<SystemTime>,
     position: usize,
+    metadata: StreamMetadata<usize>,
+    deleted: bool,
+    /// Events below this index were hidden by a soft delete, apart from the retention in `metadata`
+    soft_deleted_before: usize,
+    /// Indexes of the events appended under each idempotency key
+    idempotency_keys: HashMap<String, Range<usize>>,
 }
@@ -1,13 +1,18 @@
 use std::{
+    collections::HashMap,
     fmt::Debug,
     sync::{Arc, Mutex},
+    time::SystemTime,
 };
 
 use async_trait::async_trait;
 
 use crate::{
     decider::Event,
-    repository::{event::EventRepository, state::StateRepository},
+    repository::{
+        event::{EventRepository, StreamMetadata},
+        state::StateRepository,
+    },
 };
 
 use super::InMemoryEventRepositoryState;
@@ -44,6 +49,8 @@ where
     async fn append(&mut self, events: &Vec<E>) -> Result<Vec<E>, ()> {
         let mut lock = self.state.lock().unwrap();
         lock.events.extend(events.to_owned());
+        let now = SystemTime::now();
+        lock.recorded_at.extend(events.iter().map(|_| now));
         lock.position = lock.events.len();
 
         Ok(events.clone())
@@ -54,7 +61,12 @@ impl<E> InMemoryEventRepositoryState<E> {
     pub fn new() -> Self {
         InMemoryEventRepositoryState {
             events: vec![],
+            recorded_at: vec![],
             position: 0,
+            metadata: StreamMetadata::default(),
+            deleted: false,
+            soft_deleted_before: 0,
+            idempotency_keys: HashMap::new(),
         }
     }
 }
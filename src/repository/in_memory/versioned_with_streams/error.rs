@@ -6,4 +6,8 @@ use crate::repository::VersionDiff;
 pub enum Error {
     #[error("Cannot append, event version is out of date")]
     VersionConflict(VersionDiff<usize>),
+    #[error("Stream {0} has been deleted")]
+    StreamDeleted(String),
+    #[error("Stream {0} appears more than once in a multi stream append")]
+    DuplicateStream(String),
 }
@@ -1,16 +1,28 @@
 use async_trait::async_trait;
+use futures::{future::ready, stream, StreamExt};
 
 use std::{
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     fmt::Debug,
-    sync::{Arc, Mutex},
+    hash::{Hash, Hasher},
+    ops::Range,
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
 };
 
 use crate::{
     decider::Event,
     repository::{
-        event::VersionedEventRepositoryWithStreams, RepositoryVersion, VersionDiff,
-        VersionedRepositoryError,
+        event::{
+            AllEventsFilter, AllEventsRepository, BackwardEventRepository,
+            DeletableEventRepository, EventStream, FilteredEventRepository, GlobalEvent,
+            MultiStreamEventRepository, RecordedEvent, StreamAppend, StreamInfo,
+            StreamInfoRepository, StreamListingRepository, StreamMetadata,
+            StreamMetadataRepository, StreamingEventRepository,
+            VersionedEventRepositoryWithStreams,
+        },
+        idempotency::IdempotentEventRepository,
+        RepositoryVersion, VersionDiff, VersionedRepositoryError,
     },
 };
 
@@ -19,13 +31,66 @@ use error::Error;
 
 pub mod error;
 
-#[derive(Clone, Debug)]
+const SHARDS: usize = 16;
+
+type Streams<E> = HashMap<String, InMemoryEventRepositoryState<E>>;
+
+/// Storage shared by every clone of an `InMemoryEventRepository`
+///
+/// Streams and categories (base streams) are spread over sharded locks so appends to different streams
+/// do not contend. A category shard is always taken after the stream shards, and the global log lock after
+/// that, which keeps stream + category + global appends atomic without risking deadlocks
+#[derive(Debug)]
+struct InMemoryEventStore<E> {
+    streams: Vec<Mutex<Streams<E>>>,
+    categories: Vec<Mutex<Streams<E>>>,
+    all: Mutex<Vec<GlobalEvent<E, usize>>>,
+}
+
+impl<E> InMemoryEventStore<E> {
+    fn new() -> Self {
+        Self {
+            streams: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            categories: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            all: Mutex::new(vec![]),
+        }
+    }
+
+    fn shard_index(key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        hasher.finish() as usize % SHARDS
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<Streams<E>> {
+        &self.streams[Self::shard_index(key)]
+    }
+
+    fn category(&self, category: &str) -> &Mutex<Streams<E>> {
+        &self.categories[Self::shard_index(category)]
+    }
+}
+
+#[derive(Debug)]
 pub struct InMemoryEventRepository<E>
 where
     E: Event + Sync + Send + Debug,
 {
     stream_name: String,
-    state: HashMap<String, Arc<Mutex<InMemoryEventRepositoryState<E>>>>,
+    store: Arc<InMemoryEventStore<E>>,
+}
+
+impl<E> Clone for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Debug,
+{
+    fn clone(&self) -> Self {
+        Self {
+            stream_name: self.stream_name.to_owned(),
+            store: self.store.clone(),
+        }
+    }
 }
 
 impl<E> InMemoryEventRepository<E>
@@ -35,31 +100,20 @@ where
     pub fn new(stream_name: &str) -> Self {
         Self {
             stream_name: stream_name.to_owned(),
-            state: HashMap::default(),
+            store: Arc::new(InMemoryEventStore::new()),
         }
     }
 
-    fn get_base_stream_key(&self) -> String {
-        self.stream_name.to_owned()
-    }
-
-    fn get_stream_key(&self, stream_id: Option<&String>) -> String {
-        if let Some(id) = stream_id {
-            format!("{}/{}", self.stream_name, id)
-        } else {
-            self.stream_name.to_string()
+    /// Repository for another category backed by the same store, so `load_all` sees both
+    pub fn for_category(&self, stream_name: &str) -> Self {
+        Self {
+            stream_name: stream_name.to_owned(),
+            store: self.store.clone(),
         }
     }
 
-    fn get_stream_or_new(&mut self, key: &str) -> &Arc<Mutex<InMemoryEventRepositoryState<E>>> {
-        if self.state.get(key).is_none() {
-            self.state.insert(
-                key.to_owned(),
-                Arc::new(Mutex::new(InMemoryEventRepositoryState::new())),
-            );
-        }
-
-        self.state.get(key).unwrap()
+    fn get_stream_key(&self, stream_id: &String) -> String {
+        format!("{}/{}", self.stream_name, stream_id)
     }
 
     fn index_from_version(version: &RepositoryVersion<usize>) -> usize {
@@ -72,6 +126,215 @@ where
     fn version_from_index(index: &usize) -> RepositoryVersion<usize> {
         RepositoryVersion::Exact(*index)
     }
+
+    fn with_stream<T>(
+        &self,
+        id: Option<&String>,
+        f: impl FnOnce(&str, Option<&InMemoryEventRepositoryState<E>>) -> T,
+    ) -> T {
+        match id {
+            Some(id) => {
+                let stream_key = self.get_stream_key(id);
+                let streams = self.store.shard(&stream_key).lock().unwrap();
+
+                f(&stream_key, streams.get(&stream_key))
+            }
+            None => {
+                let categories = self.store.category(&self.stream_name).lock().unwrap();
+
+                f(&self.stream_name, categories.get(&self.stream_name))
+            }
+        }
+    }
+
+    /// Indexes left after retention, truncation and soft deletes
+    fn visible(
+        stream_key: &str,
+        stream_state: &InMemoryEventRepositoryState<E>,
+    ) -> Result<Range<usize>, Error> {
+        if stream_state.deleted {
+            return Err(Error::StreamDeleted(stream_key.to_owned()));
+        }
+
+        let metadata = &stream_state.metadata;
+        let len = stream_state.events.len();
+        let mut start = metadata
+            .truncate_before
+            .unwrap_or(0)
+            .max(stream_state.soft_deleted_before);
+
+        if let Some(max_count) = metadata.max_count {
+            start = start.max(len.saturating_sub(max_count));
+        }
+
+        if let Some(cutoff) = metadata
+            .max_age
+            .and_then(|max_age| SystemTime::now().checked_sub(max_age))
+        {
+            start = start.max(stream_state.recorded_at.partition_point(|t| *t < cutoff));
+        }
+
+        Ok(start.min(len)..len)
+    }
+
+    fn read(
+        stream_key: &str,
+        stream_state: Option<&InMemoryEventRepositoryState<E>>,
+        version: &RepositoryVersion<usize>,
+    ) -> Result<(Vec<E>, RepositoryVersion<usize>), Error>
+    where
+        E: Clone,
+    {
+        match stream_state {
+            Some(stream_state) => {
+                let visible = Self::visible(stream_key, stream_state)?;
+
+                if visible.is_empty() && !stream_state.events.is_empty() {
+                    return Ok((vec![], RepositoryVersion::NoStream));
+                }
+
+                let start = Self::index_from_version(version)
+                    .max(visible.start)
+                    .min(visible.end);
+
+                Ok((
+                    stream_state.events[start..visible.end].to_vec(),
+                    RepositoryVersion::Exact(stream_state.position),
+                ))
+            }
+            None => Ok((vec![], RepositoryVersion::Exact(0))),
+        }
+    }
+
+    /// Clones the first visible event at or after `index` out of the store, holding the lock only for that read
+    fn read_at(
+        &self,
+        id: Option<&String>,
+        index: usize,
+    ) -> Result<Option<RecordedEvent<E, usize>>, Error>
+    where
+        E: Clone,
+    {
+        self.with_stream(id, |stream_key, stream_state| {
+            let stream_state = match stream_state {
+                Some(stream_state) => stream_state,
+                None => return Ok(None),
+            };
+
+            let visible = Self::visible(stream_key, stream_state)?;
+            let index = index.max(visible.start);
+
+            Ok(visible.contains(&index).then(|| RecordedEvent {
+                event: stream_state.events[index].to_owned(),
+                version: index,
+                recorded_at: stream_state.recorded_at.get(index).copied(),
+                metadata: HashMap::new(),
+            }))
+        })
+    }
+
+    /// Applies a delete or truncation once the stream matches `version` - `Any` skips the check
+    fn update_stream(
+        &self,
+        version: &RepositoryVersion<usize>,
+        stream: &String,
+        update: impl FnOnce(&mut InMemoryEventRepositoryState<E>),
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        let stream_key = self.get_stream_key(stream);
+
+        let mut streams = self.store.shard(&stream_key).lock().unwrap();
+        let stream_state = streams
+            .entry(stream_key.to_owned())
+            .or_insert_with(InMemoryEventRepositoryState::new);
+
+        if stream_state.deleted {
+            return Err(Error::StreamDeleted(stream_key).into());
+        }
+
+        if *version != RepositoryVersion::Any
+            && stream_state.position != Self::index_from_version(version)
+        {
+            return Err(Error::VersionConflict(VersionDiff::new(
+                *version,
+                Self::version_from_index(&stream_state.position),
+            ))
+            .into());
+        }
+
+        update(stream_state);
+
+        Ok(())
+    }
+
+    /// Checks an append at `version` against the stream before anything is written
+    fn check_append(
+        stream_key: &str,
+        stream_state: &InMemoryEventRepositoryState<E>,
+        version: &RepositoryVersion<usize>,
+    ) -> Result<(), Error> {
+        if stream_state.deleted {
+            return Err(Error::StreamDeleted(stream_key.to_owned()));
+        }
+
+        // A soft deleted stream reads as `NoStream`, so it can be revived with the version it was loaded at
+        let soft_deleted =
+            !stream_state.events.is_empty() && Self::visible(stream_key, stream_state)?.is_empty();
+
+        if stream_state.position == Self::index_from_version(version)
+            || (soft_deleted
+                && matches!(
+                    version,
+                    RepositoryVersion::NoStream | RepositoryVersion::Any
+                ))
+        {
+            Ok(())
+        } else {
+            Err(Error::VersionConflict(VersionDiff::new(
+                *version,
+                Self::version_from_index(&stream_state.position),
+            )))
+        }
+    }
+
+    /// Adds events just appended to a stream to its category and the global log - the caller still holds the
+    /// stream's shard
+    fn record(&self, stream_key: &str, events: &[E])
+    where
+        E: Clone,
+    {
+        let mut categories = self.store.category(&self.stream_name).lock().unwrap();
+        Self::extend(
+            categories
+                .entry(self.stream_name.to_owned())
+                .or_insert_with(InMemoryEventRepositoryState::new),
+            events,
+        );
+
+        let mut all = self.store.all.lock().unwrap();
+        for event in events {
+            let position = all.len();
+            all.push(GlobalEvent {
+                position,
+                stream: stream_key.to_owned(),
+                category: self.stream_name.to_owned(),
+                event: event.to_owned(),
+            });
+        }
+    }
+
+    fn extend(stream_state: &mut InMemoryEventRepositoryState<E>, events: &[E]) -> usize
+    where
+        E: Clone,
+    {
+        stream_state.events.extend(events.iter().cloned());
+        let now = SystemTime::now();
+        stream_state.recorded_at.extend(events.iter().map(|_| now));
+        // An empty batch leaves the position where it was, even on a stream that has no events yet
+        if let Some(last) = stream_state.events.len().checked_sub(1) {
+            stream_state.position = last;
+        }
+        stream_state.position
+    }
 }
 
 #[async_trait]
@@ -93,77 +356,474 @@ where
         version: &RepositoryVersion<usize>,
         id: Option<&Self::StreamId>,
     ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>> {
-        let stream_key = self.get_stream_key(id);
+        Ok(self.with_stream(id, |stream_key, stream_state| {
+            Self::read(stream_key, stream_state, version)
+        })?)
+    }
+    async fn append(
+        &mut self,
+        version: &RepositoryVersion<usize>,
+        stream: &Self::StreamId,
+        events: &Vec<E>,
+    ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>>
+    where
+        'a: 'async_trait,
+        E: 'async_trait,
+    {
+        let stream_key = self.get_stream_key(stream);
+
+        let mut streams = self.store.shard(&stream_key).lock().unwrap();
+        let stream = streams
+            .entry(stream_key.to_owned())
+            .or_insert_with(InMemoryEventRepositoryState::new);
+
+        Self::check_append(&stream_key, stream, version)?;
+        let position = Self::extend(stream, events);
+
+        // Category is written while the stream shard is still held so both appends land together
+        self.record(&stream_key, events);
+
+        Ok((events.to_owned(), RepositoryVersion::Exact(position)))
+    }
+}
+
+/// Keys are kept with the stream and checked under the shard lock the append takes
+#[async_trait]
+impl<'a, E> IdempotentEventRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    async fn processed(
+        &self,
+        stream: &String,
+        key: &str,
+    ) -> Result<Option<Vec<E>>, VersionedRepositoryError<Error, usize>> {
+        Ok(self.with_stream(Some(stream), |_, stream_state| {
+            stream_state.and_then(|stream_state| {
+                stream_state
+                    .idempotency_keys
+                    .get(key)
+                    .map(|appended| stream_state.events[appended.to_owned()].to_vec())
+            })
+        }))
+    }
+
+    async fn append_idempotent(
+        &mut self,
+        version: &RepositoryVersion<usize>,
+        stream: &String,
+        events: &[E],
+        key: &str,
+    ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>>
+    where
+        'a: 'async_trait,
+        E: 'async_trait,
+    {
+        let stream_key = self.get_stream_key(stream);
 
-        if let Some(m) = self.state.get(&stream_key) {
-            let stream_state = m.lock().unwrap();
+        let mut streams = self.store.shard(&stream_key).lock().unwrap();
+        let stream = streams
+            .entry(stream_key.to_owned())
+            .or_insert_with(InMemoryEventRepositoryState::new);
 
-            let start = Self::index_from_version(version);
-            let end = stream_state.position + 1;
+        // Keys of a hard deleted stream went with its events
+        if stream.deleted {
+            return Err(Error::StreamDeleted(stream_key).into());
+        }
 
+        if let Some(appended) = stream.idempotency_keys.get(key) {
             return Ok((
-                stream_state.events[start..end].to_vec(),
-                RepositoryVersion::Exact(stream_state.position),
+                stream.events[appended.to_owned()].to_vec(),
+                RepositoryVersion::Exact(stream.position),
             ));
-        } else {
-            return Ok((vec![], RepositoryVersion::Exact(0)));
         }
+
+        Self::check_append(&stream_key, stream, version)?;
+        let start = stream.events.len();
+        let position = Self::extend(stream, events);
+        stream
+            .idempotency_keys
+            .insert(key.to_owned(), start..stream.events.len());
+
+        self.record(&stream_key, events);
+
+        Ok((events.to_owned(), RepositoryVersion::Exact(position)))
     }
-    async fn append(
+}
+
+#[async_trait]
+impl<'a, E> MultiStreamEventRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    async fn append_multi(
         &mut self,
-        version: &RepositoryVersion<usize>,
-        stream: &Self::StreamId,
-        events: &Vec<E>,
-    ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>>
+        appends: &[StreamAppend<E, String, usize>],
+    ) -> Result<Vec<(Vec<E>, RepositoryVersion<usize>)>, VersionedRepositoryError<Error, usize>>
     where
         'a: 'async_trait,
         E: 'async_trait,
     {
-        let stream_key = self.get_stream_key(Some(stream));
+        let stream_keys = appends
+            .iter()
+            .map(|append| self.get_stream_key(&append.stream))
+            .collect::<Vec<_>>();
 
-        let mut stream = self.get_stream_or_new(&stream_key).lock().unwrap();
+        if let Some((_, stream_key)) = stream_keys
+            .iter()
+            .enumerate()
+            .find(|(i, stream_key)| stream_keys[..*i].contains(stream_key))
+        {
+            return Err(Error::DuplicateStream(stream_key.to_owned()).into());
+        }
 
-        if stream.position == Self::index_from_version(version) {
-            stream.events.extend(events.clone());
-            let position = stream.events.len() - 1;
-            stream.position = position;
+        // Shards are always locked in index order so concurrent multi stream appends cannot deadlock
+        let mut shard_indexes = stream_keys
+            .iter()
+            .map(|stream_key| InMemoryEventStore::<E>::shard_index(stream_key))
+            .collect::<Vec<_>>();
+        shard_indexes.sort_unstable();
+        shard_indexes.dedup();
 
-            drop(stream); // Drop mutable reference so we can pull another and write to sub_stream
+        let mut shards: HashMap<usize, MutexGuard<Streams<E>>> = shard_indexes
+            .into_iter()
+            .map(|i| (i, self.store.streams[i].lock().unwrap()))
+            .collect();
 
-            let mut sub_stream = self
-                .get_stream_or_new(&self.get_base_stream_key())
-                .lock()
-                .unwrap();
-            sub_stream.events.extend(events.clone());
-            let sub_position = sub_stream.events.len() - 1;
-            sub_stream.position = sub_position;
+        for (stream_key, append) in stream_keys.iter().zip(appends) {
+            let streams = &shards[&InMemoryEventStore::<E>::shard_index(stream_key)];
 
-            Ok((events.to_owned(), RepositoryVersion::Exact(position)))
-        } else {
-            Err(Error::VersionConflict(VersionDiff::new(
-                *version,
-                Self::version_from_index(&stream.position),
-            ))
-            .into())
+            match streams.get(stream_key) {
+                Some(stream_state) => {
+                    Self::check_append(stream_key, stream_state, &append.version)?
+                }
+                None => Self::check_append(
+                    stream_key,
+                    &InMemoryEventRepositoryState::new(),
+                    &append.version,
+                )?,
+            }
+        }
+
+        let mut results = vec![];
+
+        for (stream_key, append) in stream_keys.iter().zip(appends) {
+            let stream_state = shards
+                .get_mut(&InMemoryEventStore::<E>::shard_index(stream_key))
+                .unwrap()
+                .entry(stream_key.to_owned())
+                .or_insert_with(InMemoryEventRepositoryState::new);
+
+            let position = Self::extend(stream_state, &append.events);
+            self.record(stream_key, &append.events);
+
+            results.push((append.events.to_owned(), RepositoryVersion::Exact(position)));
+        }
+
+        Ok(results)
+    }
+}
+
+impl<'a, E> StreamingEventRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    fn stream_from_version(
+        &self,
+        version: RepositoryVersion<usize>,
+        id: Option<String>,
+    ) -> EventStream<'_, E, usize, Error> {
+        let start = Self::index_from_version(&version);
+
+        stream::unfold(Some(start), move |index| {
+            ready(
+                index.and_then(|index| match self.read_at(id.as_ref(), index) {
+                    Ok(Some(recorded)) => {
+                        let next = recorded.version + 1;
+                        Some((Ok(recorded), Some(next)))
+                    }
+                    Ok(None) => None,
+                    Err(e) => Some((Err(e.into()), None)),
+                }),
+            )
+        })
+        .boxed()
+    }
+}
+
+#[async_trait]
+impl<'a, E> BackwardEventRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    async fn load_backward(
+        &self,
+        id: Option<&String>,
+        from: &RepositoryVersion<usize>,
+        max_count: usize,
+    ) -> Result<Vec<RecordedEvent<E, usize>>, VersionedRepositoryError<Error, usize>> {
+        Ok(self.with_stream(id, |stream_key, stream_state| {
+            let stream_state = match stream_state {
+                Some(stream_state) => stream_state,
+                None => return Ok(vec![]),
+            };
+
+            let visible = Self::visible(stream_key, stream_state)?;
+            let end = match from {
+                RepositoryVersion::Exact(v) => (v + 1).clamp(visible.start, visible.end),
+                _ => visible.end,
+            };
+
+            Ok::<_, Error>(
+                (visible.start..end)
+                    .rev()
+                    .take(max_count)
+                    .map(|index| RecordedEvent {
+                        event: stream_state.events[index].to_owned(),
+                        version: index,
+                        recorded_at: stream_state.recorded_at.get(index).copied(),
+                        metadata: HashMap::new(),
+                    })
+                    .collect(),
+            )
+        })?)
+    }
+}
+
+#[async_trait]
+impl<'a, E> StreamInfoRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    async fn stream_info(
+        &self,
+        id: Option<&String>,
+    ) -> Result<StreamInfo<usize>, VersionedRepositoryError<Error, usize>> {
+        Ok(
+            self.with_stream(id, |stream_key, stream_state| match stream_state {
+                Some(stream_state) if stream_state.deleted => StreamInfo {
+                    deleted: true,
+                    ..StreamInfo::no_stream()
+                },
+                Some(stream_state) => {
+                    let visible = Self::visible(stream_key, stream_state).unwrap_or_default();
+
+                    if visible.is_empty() {
+                        return StreamInfo::no_stream();
+                    }
+
+                    StreamInfo {
+                        version: RepositoryVersion::Exact(stream_state.position),
+                        event_count: visible.len(),
+                        first_recorded_at: stream_state.recorded_at.get(visible.start).copied(),
+                        last_recorded_at: stream_state.recorded_at.last().copied(),
+                        deleted: false,
+                    }
+                }
+                None => StreamInfo::no_stream(),
+            }),
+        )
+    }
+}
+
+#[async_trait]
+impl<'a, E> DeletableEventRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    async fn soft_delete(
+        &mut self,
+        version: &RepositoryVersion<usize>,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        self.update_stream(version, stream, |stream_state| {
+            stream_state.soft_deleted_before = stream_state.events.len()
+        })
+    }
+
+    async fn hard_delete(
+        &mut self,
+        version: &RepositoryVersion<usize>,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        self.update_stream(version, stream, |stream_state| {
+            stream_state.deleted = true;
+            stream_state.events.clear();
+            stream_state.recorded_at.clear();
+            stream_state.idempotency_keys.clear();
+        })
+    }
+
+    async fn truncate_before(
+        &mut self,
+        version: &usize,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        self.update_stream(&RepositoryVersion::Any, stream, |stream_state| {
+            let truncate_before = (*version).min(stream_state.events.len());
+
+            stream_state.metadata.truncate_before = Some(
+                stream_state
+                    .metadata
+                    .truncate_before
+                    .map_or(truncate_before, |current| current.max(truncate_before)),
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl<'a, E> StreamMetadataRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    async fn get_metadata(
+        &self,
+        stream: &String,
+    ) -> Result<StreamMetadata<usize>, VersionedRepositoryError<Error, usize>> {
+        Ok(self.with_stream(
+            Some(stream),
+            |stream_key, stream_state| match stream_state {
+                Some(stream_state) if stream_state.deleted => {
+                    Err(Error::StreamDeleted(stream_key.to_owned()))
+                }
+                Some(stream_state) => Ok(stream_state.metadata.to_owned()),
+                None => Ok(StreamMetadata::default()),
+            },
+        )?)
+    }
+
+    async fn set_metadata(
+        &mut self,
+        stream: &String,
+        metadata: &StreamMetadata<usize>,
+    ) -> Result<(), VersionedRepositoryError<Error, usize>> {
+        self.update_stream(&RepositoryVersion::Any, stream, |stream_state| {
+            stream_state.metadata = metadata.to_owned()
+        })
+    }
+}
+
+#[async_trait]
+impl<'a, E> FilteredEventRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    async fn load_types_from_version(
+        &self,
+        version: &RepositoryVersion<usize>,
+        id: Option<&Self::StreamId>,
+        event_types: &[&str],
+    ) -> Result<(Vec<E>, RepositoryVersion<usize>), VersionedRepositoryError<Error, usize>> {
+        let (events, version) = self.load_from_version(version, id).await?;
+
+        Ok((
+            events
+                .into_iter()
+                .filter(|e| event_types.contains(&e.event_type().as_str()))
+                .collect(),
+            version,
+        ))
+    }
+}
+
+#[async_trait]
+impl<'a, E> StreamListingRepository<'a, E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    async fn list_streams(&self) -> Result<Vec<String>, VersionedRepositoryError<Error, usize>> {
+        let prefix = format!("{}/", self.stream_name);
+        let mut seen = HashSet::new();
+        let mut ids = vec![];
+
+        for e in self.store.all.lock().unwrap().iter() {
+            if let Some(id) = e.stream.strip_prefix(&prefix) {
+                if seen.insert(id.to_owned()) {
+                    ids.push(id.to_owned());
+                }
+            }
         }
+
+        // Deleted and fully truncated streams hold no events anymore
+        Ok(ids
+            .into_iter()
+            .filter(|id| {
+                self.with_stream(Some(id), |stream_key, stream_state| {
+                    stream_state.is_some_and(|stream_state| {
+                        Self::visible(stream_key, stream_state).is_ok_and(|v| !v.is_empty())
+                    })
+                })
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl<E> AllEventsRepository<E, Error> for InMemoryEventRepository<E>
+where
+    E: Event + Sync + Send + Clone + Debug,
+{
+    type Position = usize;
+
+    async fn load_all(
+        &self,
+        from: Option<&usize>,
+        filter: &AllEventsFilter,
+        max_count: Option<usize>,
+    ) -> Result<Vec<GlobalEvent<E, usize>>, Error> {
+        let all = self.store.all.lock().unwrap();
+        let start = from.map_or(0, |position| position + 1);
+
+        Ok(all
+            .iter()
+            .skip(start)
+            .filter(|e| {
+                filter.matches_category(&e.category)
+                    && filter.matches_event_type(&e.event.event_type())
+            })
+            .take(max_count.unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
     }
 }
 
 impl From<Error> for VersionedRepositoryError<Error, usize> {
     fn from(value: Error) -> Self {
-        let Error::VersionConflict(diff) = value;
-
-        VersionedRepositoryError::VersionConflict(diff)
+        match value {
+            Error::VersionConflict(diff) => VersionedRepositoryError::VersionConflict(diff),
+            e => VersionedRepositoryError::RepoErr(e),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::test_helpers::{
-        deciders::user::UserEvent, repository::versioned_event_repository_with_streams_spec,
+    use std::time::Duration;
+
+    use assert_matches::assert_matches;
+
+    use crate::{
+        repository::{
+            event::{
+                AllEventsFilter, AllEventsRepository, BackwardEventRepository,
+                DeletableEventRepository, FilteredEventRepository, MultiStreamEventRepository,
+                StreamAppend, StreamInfoRepository, StreamMetadata, StreamMetadataRepository,
+                VersionedEventRepositoryWithStreams,
+            },
+            idempotency::IdempotentEventRepository,
+            RepositoryVersion, VersionedRepositoryError,
+        },
+        test_helpers::{
+            deciders::user::{User, UserEvent, UserName},
+            repository::{
+                versioned_event_repository_with_streams_occ_spec,
+                versioned_event_repository_with_streams_spec,
+            },
+        },
     };
 
-    use super::InMemoryEventRepository;
+    use super::{error::Error, InMemoryEventRepository};
 
     const BASE_STREAM: &str = "test";
 
@@ -172,4 +832,505 @@ mod tests {
         let event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
         let _ = versioned_event_repository_with_streams_spec(event_repository).await;
     }
+
+    #[actix_rt::test]
+    async fn repository_with_occ_spec_test() {
+        let event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let _ = versioned_event_repository_with_streams_occ_spec(event_repository).await;
+    }
+
+    #[actix_rt::test]
+    async fn clones_share_streams_created_after_clone() {
+        let event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let mut clone = event_repository.clone();
+
+        let events = vec![UserEvent::UserAdded(User::new(
+            1,
+            UserName::try_from("Mike").unwrap(),
+        ))];
+
+        clone
+            .append(&RepositoryVersion::NoStream, &"1".to_string(), &events)
+            .await
+            .expect("Successful append");
+
+        let (stream, _) = event_repository.load(Some(&"1".to_string())).await.unwrap();
+        let (category, _) = event_repository.load(None).await.unwrap();
+
+        assert_eq!(stream, events);
+        assert_eq!(category, events);
+    }
+
+    #[actix_rt::test]
+    async fn append_empty_batch_to_new_stream() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+
+        let (appended, _) = event_repository
+            .append(&RepositoryVersion::NoStream, &"1".to_string(), &vec![])
+            .await
+            .expect("Empty append succeeds");
+        assert!(appended.is_empty());
+
+        let events = vec![UserEvent::UserAdded(User::new(
+            1,
+            UserName::try_from("Mike").unwrap(),
+        ))];
+        let (_, version) = event_repository
+            .append(&RepositoryVersion::NoStream, &"1".to_string(), &events)
+            .await
+            .expect("Stream is still new");
+
+        assert_eq!(version, RepositoryVersion::Exact(0));
+    }
+
+    #[actix_rt::test]
+    async fn load_all_across_categories() {
+        let mut users = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let mut admins = users.for_category("admins");
+
+        let mike = User::new(1, UserName::try_from("Mike").unwrap());
+        let added = UserEvent::UserAdded(mike.clone());
+        let renamed = UserEvent::UserNameUpdated(1, UserName::try_from("Mike2").unwrap());
+
+        users
+            .append(
+                &RepositoryVersion::NoStream,
+                &"1".to_string(),
+                &vec![added.clone()],
+            )
+            .await
+            .unwrap();
+        admins
+            .append(
+                &RepositoryVersion::NoStream,
+                &"1".to_string(),
+                &vec![added.clone()],
+            )
+            .await
+            .unwrap();
+        users
+            .append(
+                &RepositoryVersion::Exact(0),
+                &"1".to_string(),
+                &vec![renamed.clone()],
+            )
+            .await
+            .unwrap();
+
+        let all = users
+            .load_all(None, &AllEventsFilter::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            all.iter()
+                .map(|e| (e.position, e.category.as_str(), e.stream.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, "test", "test/1"),
+                (1, "admins", "admins/1"),
+                (2, "test", "test/1")
+            ]
+        );
+
+        let after_first = admins
+            .load_all(Some(&0), &AllEventsFilter::new(), Some(1))
+            .await
+            .unwrap();
+        assert_eq!(after_first.len(), 1);
+        assert_eq!(after_first[0].category, "admins");
+
+        let filtered = users
+            .load_all(
+                None,
+                &AllEventsFilter::new()
+                    .with_categories(&["test"])
+                    .with_event_types(&["UserNameUpdated"]),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].event, renamed);
+    }
+
+    #[actix_rt::test]
+    async fn load_backward_newest_first() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let id = "1".to_string();
+
+        let events = ["Mike", "Mike2", "Mike3", "Mike4"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let name = UserName::try_from(*name).unwrap();
+                if i == 0 {
+                    UserEvent::UserAdded(User::new(1, name))
+                } else {
+                    UserEvent::UserNameUpdated(1, name)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+
+        let latest = event_repository
+            .load_backward(Some(&id), &RepositoryVersion::Any, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            latest.iter().map(|e| e.version).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        assert_eq!(latest[0].event, events[3]);
+
+        let earlier = event_repository
+            .load_backward(Some(&id), &RepositoryVersion::Exact(1), 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            earlier.into_iter().map(|e| e.event).collect::<Vec<_>>(),
+            vec![events[1].clone(), events[0].clone()]
+        );
+
+        let missing = event_repository
+            .load_backward(Some(&"2".to_string()), &RepositoryVersion::Any, 10)
+            .await
+            .unwrap();
+        assert!(missing.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn stream_info_without_loading() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let id = "1".to_string();
+
+        let info = event_repository.stream_info(Some(&id)).await.unwrap();
+        assert_eq!(info.version, RepositoryVersion::NoStream);
+        assert!(!info.exists());
+
+        let events = vec![
+            UserEvent::UserAdded(User::new(1, UserName::try_from("Mike").unwrap())),
+            UserEvent::UserNameUpdated(1, UserName::try_from("Mike2").unwrap()),
+        ];
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+
+        let info = event_repository.stream_info(Some(&id)).await.unwrap();
+        assert_eq!(info.version, RepositoryVersion::Exact(1));
+        assert_eq!(info.event_count, 2);
+        assert!(info.exists());
+        assert!(info.first_recorded_at <= info.last_recorded_at);
+        assert!(info.last_recorded_at.is_some());
+
+        let category = event_repository.stream_info(None).await.unwrap();
+        assert_eq!(category.event_count, 2);
+    }
+
+    #[actix_rt::test]
+    async fn delete_and_truncate_streams() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let (one, two) = ("1".to_string(), "2".to_string());
+
+        let events = vec![
+            UserEvent::UserAdded(User::new(1, UserName::try_from("Mike").unwrap())),
+            UserEvent::UserNameUpdated(1, UserName::try_from("Mike2").unwrap()),
+            UserEvent::UserNameUpdated(1, UserName::try_from("Mike3").unwrap()),
+        ];
+
+        for id in [&one, &two] {
+            event_repository
+                .append(&RepositoryVersion::NoStream, id, &events)
+                .await
+                .unwrap();
+        }
+
+        event_repository.truncate_before(&1, &one).await.unwrap();
+        let (evts, version) = event_repository.load(Some(&one)).await.unwrap();
+        assert_eq!(evts, events[1..].to_vec());
+        assert_eq!(version, RepositoryVersion::Exact(2));
+
+        assert_matches!(
+            event_repository
+                .soft_delete(&RepositoryVersion::Exact(0), &one)
+                .await,
+            Err(VersionedRepositoryError::VersionConflict(_))
+        );
+        event_repository
+            .soft_delete(&RepositoryVersion::Exact(2), &one)
+            .await
+            .unwrap();
+        let (evts, version) = event_repository.load(Some(&one)).await.unwrap();
+        assert!(evts.is_empty());
+        assert_eq!(version, RepositoryVersion::NoStream);
+
+        // Replacing the metadata keeps the soft delete
+        event_repository
+            .set_metadata(&one, &StreamMetadata::default())
+            .await
+            .unwrap();
+        let (evts, version) = event_repository.load(Some(&one)).await.unwrap();
+        assert!(evts.is_empty());
+        assert_eq!(version, RepositoryVersion::NoStream);
+
+        let (_, version) = event_repository
+            .append(&version, &one, &events[2..].to_vec())
+            .await
+            .unwrap();
+        assert_eq!(version, RepositoryVersion::Exact(3));
+        assert_eq!(
+            event_repository.load(Some(&one)).await.unwrap().0,
+            events[2..].to_vec()
+        );
+
+        event_repository
+            .hard_delete(&RepositoryVersion::Any, &two)
+            .await
+            .unwrap();
+        assert_matches!(
+            event_repository.load(Some(&two)).await,
+            Err(VersionedRepositoryError::RepoErr(Error::StreamDeleted(_)))
+        );
+        assert_matches!(
+            event_repository
+                .append(&RepositoryVersion::Any, &two, &events)
+                .await,
+            Err(VersionedRepositoryError::RepoErr(Error::StreamDeleted(_)))
+        );
+        assert!(
+            event_repository
+                .stream_info(Some(&two))
+                .await
+                .unwrap()
+                .deleted
+        );
+    }
+
+    #[actix_rt::test]
+    async fn stream_metadata_retention() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let id = "1".to_string();
+
+        let events = vec![
+            UserEvent::UserAdded(User::new(1, UserName::try_from("Mike").unwrap())),
+            UserEvent::UserNameUpdated(1, UserName::try_from("Mike2").unwrap()),
+            UserEvent::UserNameUpdated(1, UserName::try_from("Mike3").unwrap()),
+        ];
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            event_repository.get_metadata(&id).await.unwrap(),
+            StreamMetadata::default()
+        );
+
+        let metadata = StreamMetadata::new()
+            .with_max_count(2)
+            .with_custom("tier", serde_json::json!("telemetry"));
+        event_repository.set_metadata(&id, &metadata).await.unwrap();
+
+        assert_eq!(event_repository.get_metadata(&id).await.unwrap(), metadata);
+        assert_eq!(
+            event_repository.load(Some(&id)).await.unwrap().0,
+            events[1..].to_vec()
+        );
+
+        event_repository
+            .set_metadata(
+                &id,
+                &StreamMetadata::new().with_max_age(Duration::from_secs(0)),
+            )
+            .await
+            .unwrap();
+        assert!(event_repository.load(Some(&id)).await.unwrap().0.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn load_types_keeps_stream_version() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let id = "1".to_string();
+
+        let events = vec![
+            UserEvent::UserAdded(User::new(1, UserName::try_from("Mike").unwrap())),
+            UserEvent::UserNameUpdated(1, UserName::try_from("Mike2").unwrap()),
+            UserEvent::UserNameUpdated(1, UserName::try_from("Mike3").unwrap()),
+        ];
+        event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await
+            .unwrap();
+
+        let (loaded, version) = event_repository
+            .load_types_from_version(&RepositoryVersion::Any, Some(&id), &["UserAdded"])
+            .await
+            .unwrap();
+        assert_eq!(loaded, events[..1].to_vec());
+        assert_eq!(version, RepositoryVersion::Exact(2));
+
+        let (loaded, _) = event_repository
+            .load_types_from_version(&RepositoryVersion::Exact(2), None, &["UserNameUpdated"])
+            .await
+            .unwrap();
+        assert_eq!(loaded, events[2..].to_vec());
+    }
+
+    #[actix_rt::test]
+    async fn append_multi_is_all_or_nothing() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let (from, to) = ("1".to_string(), "2".to_string());
+
+        let added = |id| UserEvent::UserAdded(User::new(id, UserName::try_from("Mike").unwrap()));
+        let renamed = |id| UserEvent::UserNameUpdated(id, UserName::try_from("Mike2").unwrap());
+
+        let results = event_repository
+            .append_multi(&[
+                StreamAppend::new(from.clone(), RepositoryVersion::NoStream, vec![added(1)]),
+                StreamAppend::new(to.clone(), RepositoryVersion::NoStream, vec![added(2)]),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(results[0], (vec![added(1)], RepositoryVersion::Exact(0)));
+        assert_eq!(results[1], (vec![added(2)], RepositoryVersion::Exact(0)));
+
+        event_repository
+            .append(&RepositoryVersion::Exact(0), &to, &vec![renamed(2)])
+            .await
+            .unwrap();
+
+        let res = event_repository
+            .append_multi(&[
+                StreamAppend::new(from.clone(), RepositoryVersion::Exact(0), vec![renamed(1)]),
+                StreamAppend::new(to.clone(), RepositoryVersion::Exact(0), vec![renamed(2)]),
+            ])
+            .await;
+        assert_matches!(res, Err(VersionedRepositoryError::VersionConflict(_)));
+        assert_eq!(
+            event_repository.load(Some(&from)).await.unwrap(),
+            (vec![added(1)], RepositoryVersion::Exact(0))
+        );
+        assert_eq!(event_repository.load(None).await.unwrap().0.len(), 3);
+
+        let res = event_repository
+            .append_multi(&[
+                StreamAppend::new(from.clone(), RepositoryVersion::Exact(0), vec![renamed(1)]),
+                StreamAppend::new(from.clone(), RepositoryVersion::Exact(1), vec![renamed(1)]),
+            ])
+            .await;
+        assert_matches!(
+            res,
+            Err(VersionedRepositoryError::RepoErr(Error::DuplicateStream(_)))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn append_idempotent_appends_a_key_once() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let id = "1".to_string();
+
+        let added = vec![UserEvent::UserAdded(User::new(
+            1,
+            UserName::try_from("Mike").unwrap(),
+        ))];
+        let renamed = |name| {
+            vec![UserEvent::UserNameUpdated(
+                1,
+                UserName::try_from(name).unwrap(),
+            )]
+        };
+
+        event_repository
+            .append_idempotent(&RepositoryVersion::NoStream, &id, &added, "add")
+            .await
+            .unwrap();
+
+        // A retry racing the first attempt decided on the same version - the key wins over the conflict
+        let first = event_repository
+            .append_idempotent(
+                &RepositoryVersion::Exact(0),
+                &id,
+                &renamed("Mike2"),
+                "rename",
+            )
+            .await
+            .unwrap();
+        let retried = event_repository
+            .append_idempotent(
+                &RepositoryVersion::Exact(0),
+                &id,
+                &renamed("Mike3"),
+                "rename",
+            )
+            .await
+            .unwrap();
+        assert_eq!(retried, first);
+        assert_eq!(
+            event_repository.processed(&id, "rename").await.unwrap(),
+            Some(renamed("Mike2"))
+        );
+        assert_eq!(event_repository.load(Some(&id)).await.unwrap().0.len(), 2);
+
+        let res = event_repository
+            .append_idempotent(
+                &RepositoryVersion::Exact(0),
+                &id,
+                &renamed("Mike3"),
+                "other",
+            )
+            .await;
+        assert_matches!(res, Err(VersionedRepositoryError::VersionConflict(_)));
+
+        // Keys are scoped per stream
+        assert_eq!(
+            event_repository
+                .processed(&"2".to_string(), "rename")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[actix_rt::test]
+    async fn append_idempotent_after_hard_delete() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new(BASE_STREAM);
+        let id = "1".to_string();
+        let added = vec![UserEvent::UserAdded(User::new(
+            1,
+            UserName::try_from("Mike").unwrap(),
+        ))];
+
+        event_repository
+            .append_idempotent(&RepositoryVersion::NoStream, &id, &added, "add")
+            .await
+            .unwrap();
+        event_repository
+            .hard_delete(&RepositoryVersion::Exact(0), &id)
+            .await
+            .unwrap();
+
+        assert_eq!(event_repository.processed(&id, "add").await.unwrap(), None);
+
+        let res = event_repository
+            .append_idempotent(&RepositoryVersion::NoStream, &id, &added, "add")
+            .await;
+        assert_matches!(
+            res,
+            Err(VersionedRepositoryError::RepoErr(Error::StreamDeleted(_)))
+        );
+
+        // The shard is still usable
+        assert_eq!(
+            event_repository
+                .append(&RepositoryVersion::NoStream, &"2".to_string(), &added)
+                .await
+                .unwrap()
+                .1,
+            RepositoryVersion::Exact(0)
+        );
+    }
 }
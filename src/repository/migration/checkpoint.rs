@@ -0,0 +1,152 @@
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    fs::{self, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+
+use super::stream_hash;
+
+/// Streams a migration has finished, so an interrupted migration picks up where it stopped
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    type Error: std::fmt::Debug + Send + Sync;
+
+    async fn completed(&self) -> Result<HashSet<String>, Self::Error>;
+
+    async fn complete(&self, stream: &str) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryCheckpointStore {
+    completed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl InMemoryCheckpointStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for InMemoryCheckpointStore {
+    type Error = Infallible;
+
+    async fn completed(&self) -> Result<HashSet<String>, Infallible> {
+        Ok(self.completed.lock().unwrap().to_owned())
+    }
+
+    async fn complete(&self, stream: &str) -> Result<(), Infallible> {
+        self.completed.lock().unwrap().insert(stream.to_owned());
+        Ok(())
+    }
+}
+
+/// Completed streams appended to a file, one per line
+///
+/// A line is only written once the stream has been copied and verified, so a crash at worst copies and verifies
+/// the stream again. Each line holds the stream id as JSON followed by a checksum of it, and lines that don't
+/// match their checksum are ignored - a write torn by a crash then never marks a stream complete
+#[derive(Debug)]
+pub struct FileCheckpointStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn checksum(stream: &str) -> String {
+        format!("{:016x}", stream_hash(&[stream]).unwrap())
+    }
+
+    fn parse_line(line: &str) -> Option<String> {
+        let (stream, checksum) = line.rsplit_once('\t')?;
+
+        (checksum == Self::checksum(stream))
+            .then(|| serde_json::from_str(stream).ok())
+            .flatten()
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    type Error = io::Error;
+
+    async fn completed(&self) -> Result<HashSet<String>, io::Error> {
+        let _lock = self.lock.lock().unwrap();
+
+        match fs::read_to_string(&self.path) {
+            Ok(completed) => Ok(completed.lines().filter_map(Self::parse_line).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn complete(&self, stream: &str) -> Result<(), io::Error> {
+        let _lock = self.lock.lock().unwrap();
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+
+        // Ends a line torn by a crash, so it can't run into this one
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+
+            if last != *b"\n" {
+                file.write_all(b"\n")?;
+            }
+        }
+
+        let stream = serde_json::to_string(stream)?;
+        writeln!(file, "{}\t{}", stream, Self::checksum(&stream))?;
+
+        file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use super::{CheckpointStore, FileCheckpointStore};
+
+    #[actix_rt::test]
+    async fn torn_lines_are_ignored() {
+        let path = std::env::temp_dir().join(format!("epoch-checkpoints-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let checkpoints = FileCheckpointStore::new(&path);
+
+        checkpoints.complete("users-1").await.unwrap();
+
+        // A crash while checkpointing users-2 left part of its line
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"\"users-2\"\t3f").unwrap();
+        assert_eq!(
+            checkpoints.completed().await.unwrap(),
+            ["users-1".to_owned()].into()
+        );
+
+        checkpoints.complete("users-2").await.unwrap();
+        assert_eq!(
+            checkpoints.completed().await.unwrap(),
+            ["users-1".to_owned(), "users-2".to_owned()].into()
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,25 @@
+use std::fmt::Debug;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MigrationError<SourceErr: Debug, TargetErr: Debug, CheckpointErr: Debug> {
+    #[error("Source repository error {0:?}")]
+    Source(SourceErr),
+    #[error("Target repository error {0:?}")]
+    Target(TargetErr),
+    #[error("Checkpoint error {0:?}")]
+    Checkpoint(CheckpointErr),
+    #[error("Could not serialize event for hashing {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Target stream {stream} does not hold a copy of the source stream: {reason}")]
+    Diverged { stream: String, reason: String },
+    #[error("Stream {stream} failed verification, expected {expected_events} events hashing to {expected_hash:016x} but found {actual_events} hashing to {actual_hash:016x}")]
+    VerificationFailed {
+        stream: String,
+        expected_events: usize,
+        expected_hash: u64,
+        actual_events: usize,
+        actual_hash: u64,
+    },
+}
@@ -0,0 +1,413 @@
+use std::{convert::identity, fmt::Debug};
+
+use futures::TryStreamExt;
+use serde::Serialize;
+
+use crate::decider::Event;
+
+use super::{
+    event::{StreamListingRepository, StreamingEventRepository},
+    RepositoryVersion, VersionedRepositoryError,
+};
+
+use self::{checkpoint::CheckpointStore, error::MigrationError};
+
+pub mod checkpoint;
+pub mod error;
+
+/// Events are appended to the target in batches of this size by default
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a over the JSON of each event, chained in stream order
+///
+/// Events go through `serde_json::Value` first so object keys are hashed in a stable order. Meant to catch
+/// events lost or altered while copying, not deliberate tampering
+pub fn stream_hash<E: Serialize>(events: &[E]) -> Result<u64, serde_json::Error> {
+    let mut hasher = StreamHasher::new();
+
+    for event in events {
+        hasher.update(event)?;
+    }
+
+    Ok(hasher.finish())
+}
+
+/// `stream_hash` fed one event at a time, so a stream can be hashed while it is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamHasher {
+    hash: u64,
+    events: usize,
+}
+
+impl Default for StreamHasher {
+    fn default() -> Self {
+        Self {
+            hash: FNV_OFFSET,
+            events: 0,
+        }
+    }
+}
+
+impl StreamHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update<E: Serialize>(&mut self, event: &E) -> Result<(), serde_json::Error> {
+        let bytes = serde_json::to_vec(&serde_json::to_value(event)?)?;
+
+        // Newline separates events so moving bytes across an event boundary changes the hash
+        for byte in bytes.iter().chain(b"\n") {
+            self.hash ^= u64::from(*byte);
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+        self.events += 1;
+
+        Ok(())
+    }
+
+    /// Events hashed so far
+    pub fn events(&self) -> usize {
+        self.events
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// Reads a whole target stream through `hasher` and returns the version it ended at
+async fn hash_target<'a, R, E, Err, SErr, KErr>(
+    repository: &R,
+    stream: &R::StreamId,
+    hasher: &mut StreamHasher,
+) -> Result<
+    RepositoryVersion<R::Version>,
+    MigrationError<SErr, VersionedRepositoryError<Err, R::Version>, KErr>,
+>
+where
+    R: StreamingEventRepository<'a, E, Err>,
+    R::StreamId: Clone,
+    R::Version: Debug,
+    E: Event + Serialize + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+    SErr: Debug,
+    KErr: Debug,
+{
+    let mut events = repository.stream(Some(stream.to_owned()));
+    let mut version = RepositoryVersion::NoStream;
+
+    while let Some(recorded) = events.try_next().await.map_err(MigrationError::Target)? {
+        hasher.update(&recorded.event)?;
+        version = RepositoryVersion::Exact(recorded.version);
+    }
+
+    Ok(version)
+}
+
+/// A stream copied and verified by a migration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamVerification {
+    pub stream: String,
+    pub events: usize,
+    pub hash: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub migrated: Vec<StreamVerification>,
+    /// Streams a previous run already completed
+    pub skipped: usize,
+    /// Events appended to the target by this run
+    pub copied_events: usize,
+}
+
+/// Copies every stream of the source repository's category into the target repository, stream by stream
+///
+/// Source streams are read lazily and appended to the target a batch at a time as they arrive, so a stream is
+/// never held in memory as a whole. Each stream keeps its event order and is verified against the source by
+/// event count and `stream_hash` before it is checkpointed. A stream left half copied by an interrupted run is
+/// resumed after checking the events already in the target match the source
+pub struct Migrator<S, T, K, F> {
+    source: S,
+    target: T,
+    checkpoints: K,
+    transform: F,
+    batch_size: usize,
+}
+
+impl<S, T, K, E> Migrator<S, T, K, fn(E) -> E> {
+    /// Copies the events as they are
+    pub fn new(source: S, target: T, checkpoints: K) -> Self {
+        Self::with_transform(source, target, checkpoints, identity)
+    }
+}
+
+impl<S, T, K, F> Migrator<S, T, K, F> {
+    /// Rewrites each event on its way to the target, ie. to upcast old event versions
+    pub fn with_transform(source: S, target: T, checkpoints: K, transform: F) -> Self {
+        Self {
+            source,
+            target,
+            checkpoints,
+            transform,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn target(&self) -> &T {
+        &self.target
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub async fn run<'a, SE, TE, SErr, TErr>(
+        &mut self,
+    ) -> Result<
+        MigrationReport,
+        MigrationError<
+            VersionedRepositoryError<SErr, S::Version>,
+            VersionedRepositoryError<TErr, T::Version>,
+            K::Error,
+        >,
+    >
+    where
+        S: StreamingEventRepository<'a, SE, SErr> + StreamListingRepository<'a, SE, SErr>,
+        S::StreamId: ToString + Clone,
+        S::Version: Debug,
+        T: StreamingEventRepository<'a, TE, TErr, StreamId = S::StreamId>,
+        T::Version: Debug,
+        K: CheckpointStore,
+        F: Fn(SE) -> TE,
+        SE: Event + Sync + Send + Debug,
+        TE: Event + Serialize + Clone + Sync + Send + Debug,
+        SErr: Debug + Send + Sync,
+        TErr: Debug + Send + Sync,
+    {
+        let completed = self
+            .checkpoints
+            .completed()
+            .await
+            .map_err(MigrationError::Checkpoint)?;
+
+        let mut report = MigrationReport::default();
+
+        for stream in self
+            .source
+            .list_streams()
+            .await
+            .map_err(MigrationError::Source)?
+        {
+            let key = stream.to_string();
+
+            if completed.contains(&key) {
+                report.skipped += 1;
+                continue;
+            }
+
+            let mut copied = StreamHasher::new();
+            let mut version = hash_target(&self.target, &stream, &mut copied).await?;
+
+            let mut expected = StreamHasher::new();
+            let mut batch = Vec::with_capacity(self.batch_size);
+            let mut source_events = self.source.stream(Some(stream.to_owned()));
+
+            while let Some(recorded) = source_events
+                .try_next()
+                .await
+                .map_err(MigrationError::Source)?
+            {
+                let event = (self.transform)(recorded.event);
+                expected.update(&event)?;
+
+                // The events already in the target are checked before anything is appended after them
+                if expected.events() <= copied.events() {
+                    if expected.events() == copied.events() && expected != copied {
+                        return Err(MigrationError::Diverged {
+                            stream: key,
+                            reason: "the events already in the target differ from the source"
+                                .to_owned(),
+                        });
+                    }
+                    continue;
+                }
+
+                batch.push(event);
+
+                if batch.len() == self.batch_size {
+                    (_, version) = self
+                        .target
+                        .append(&version, &stream, &batch)
+                        .await
+                        .map_err(MigrationError::Target)?;
+                    report.copied_events += batch.len();
+                    batch.clear();
+                }
+            }
+
+            if expected.events() < copied.events() {
+                return Err(MigrationError::Diverged {
+                    stream: key,
+                    reason: format!(
+                        "the target holds {} events, the source {}",
+                        copied.events(),
+                        expected.events()
+                    ),
+                });
+            }
+
+            if !batch.is_empty() {
+                self.target
+                    .append(&version, &stream, &batch)
+                    .await
+                    .map_err(MigrationError::Target)?;
+                report.copied_events += batch.len();
+            }
+
+            let mut migrated = StreamHasher::new();
+            hash_target(&self.target, &stream, &mut migrated).await?;
+
+            if migrated != expected {
+                return Err(MigrationError::VerificationFailed {
+                    stream: key,
+                    expected_events: expected.events(),
+                    expected_hash: expected.finish(),
+                    actual_events: migrated.events(),
+                    actual_hash: migrated.finish(),
+                });
+            }
+
+            self.checkpoints
+                .complete(&key)
+                .await
+                .map_err(MigrationError::Checkpoint)?;
+
+            report.migrated.push(StreamVerification {
+                stream: key,
+                events: expected.events(),
+                hash: expected.finish(),
+            });
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::{
+        repository::{
+            archive::RawEvent, event::VersionedEventRepositoryWithStreams,
+            in_memory::versioned_with_streams::InMemoryEventRepository, RepositoryVersion,
+        },
+        test_helpers::deciders::user::{Guitar, User, UserEvent, UserName},
+    };
+
+    use super::{
+        checkpoint::{CheckpointStore, InMemoryCheckpointStore},
+        error::MigrationError,
+        Migrator,
+    };
+
+    async fn source() -> InMemoryEventRepository<UserEvent> {
+        let mut source = InMemoryEventRepository::<UserEvent>::new("users");
+
+        for id in [1, 2] {
+            source
+                .append(
+                    &RepositoryVersion::NoStream,
+                    &id.to_string(),
+                    &vec![
+                        UserEvent::UserAdded(User::new(id, UserName::try_from("Mike").unwrap())),
+                        UserEvent::UserGuitarAdded(
+                            id,
+                            Guitar {
+                                brand: "Gibson".to_string(),
+                            },
+                        ),
+                    ],
+                )
+                .await
+                .unwrap();
+        }
+
+        source
+    }
+
+    fn to_raw(event: UserEvent) -> RawEvent {
+        RawEvent(serde_json::to_value(event).unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn migration_resumes_half_copied_streams() {
+        let source = source().await;
+        let mut target = InMemoryEventRepository::<RawEvent>::new("copy");
+        let checkpoints = InMemoryCheckpointStore::new();
+
+        // An earlier run stopped after the first event of stream 1
+        let (events, _) = source.load(Some(&"1".to_string())).await.unwrap();
+        target
+            .append(
+                &RepositoryVersion::NoStream,
+                &"1".to_string(),
+                &vec![to_raw(events[0].to_owned())],
+            )
+            .await
+            .unwrap();
+
+        let mut migrator =
+            Migrator::with_transform(source, target.clone(), checkpoints.clone(), to_raw)
+                .with_batch_size(1);
+        let report = migrator.run().await.unwrap();
+
+        assert_eq!(report.migrated.len(), 2);
+        assert_eq!(report.copied_events, 3);
+        assert_eq!(checkpoints.completed().await.unwrap().len(), 2);
+        assert_eq!(
+            target.load(Some(&"1".to_string())).await.unwrap(),
+            (
+                events.into_iter().map(to_raw).collect(),
+                RepositoryVersion::Exact(1)
+            )
+        );
+
+        let report = migrator.run().await.unwrap();
+        assert_eq!(report.skipped, 2);
+        assert_eq!(report.copied_events, 0);
+    }
+
+    #[actix_rt::test]
+    async fn migration_refuses_diverged_targets() {
+        let mut target = InMemoryEventRepository::<UserEvent>::new("copy");
+        target
+            .append(
+                &RepositoryVersion::NoStream,
+                &"2".to_string(),
+                &vec![UserEvent::UserAdded(User::new(
+                    2,
+                    UserName::try_from("Joe").unwrap(),
+                ))],
+            )
+            .await
+            .unwrap();
+
+        let res = Migrator::new(source().await, target, InMemoryCheckpointStore::new())
+            .run()
+            .await;
+
+        assert_matches!(res, Err(MigrationError::Diverged { stream, .. }) if stream == "2");
+    }
+}
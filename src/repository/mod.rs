@@ -3,11 +3,18 @@ use thiserror::Error;
 
 use crate::decider::Event;
 
+pub mod archive;
+#[cfg(any(feature = "zstd", feature = "gzip"))]
+pub mod compression;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 #[cfg(feature = "esdb")]
 pub mod esdb;
 pub mod event;
+pub mod idempotency;
 #[cfg(feature = "in_memory")]
 pub mod in_memory;
+pub mod migration;
 #[cfg(feature = "redis")]
 pub mod redis;
 pub mod state;
@@ -28,6 +35,18 @@ pub enum VersionedRepositoryError<RepoErr, V> {
     RepoErr(RepoErr),
 }
 
+impl<RepoErr, V> VersionedRepositoryError<RepoErr, V> {
+    /// Maps the repository error and keeps version conflicts as they are - for repositories wrapping another one
+    pub fn map_repo_err<T>(self, f: impl FnOnce(RepoErr) -> T) -> VersionedRepositoryError<T, V> {
+        match self {
+            VersionedRepositoryError::VersionConflict(diff) => {
+                VersionedRepositoryError::VersionConflict(diff)
+            }
+            VersionedRepositoryError::RepoErr(e) => VersionedRepositoryError::RepoErr(f(e)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct VersionDiff<V> {
     expected: RepositoryVersion<V>,
@@ -55,6 +74,11 @@ pub trait WithFineGrainedStreamId {
     }
 }
 
+/// Event type of a stored representation, so filtered reads can skip events without converting them
+pub trait WithEventType {
+    fn event_type(&self) -> String;
+}
+
 pub trait StreamIdFromEvent<Evt: Event>: Sized {
     fn from(e: Evt) -> Self {
         Self::event_entity_id_into(e.get_id())
@@ -1,4 +1,8 @@
-use std::{error::Error, fmt::Debug};
+use std::{
+    error::Error,
+    fmt::Debug,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use redis_om::RedisError;
 
@@ -22,6 +26,10 @@ pub enum RedisRepositoryError<DTOErr: Error + Debug> {
     ParseDTO(RedisError),
     #[error("Could not convert DTO to Event: {0:?}")]
     FromDTO(DTOErr),
+    #[error("Stream {0} has been deleted")]
+    StreamDeleted(String),
+    #[error("Could not parse stream metadata field {0}")]
+    ParseMetadata(String),
 }
 
 #[derive(Error, Debug)]
@@ -36,6 +44,13 @@ pub struct RedisVersion {
     version: usize,
 }
 
+impl RedisVersion {
+    /// Entry ids start with the millisecond timestamp redis assigned when the entry was added
+    pub fn recorded_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp as u64)
+    }
+}
+
 impl Ord for RedisVersion {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         if self > other {
@@ -1,19 +1,137 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
 use redis_om::RedisError;
-use redis_om::{redis::aio::MultiplexedConnection, Client, StreamModel};
+use redis_om::{
+    redis::{self, aio::MultiplexedConnection, streams::StreamInfoStreamReply, ToRedisArgs},
+    Client, StreamModel,
+};
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::decider::Event;
 
-use crate::repository::{event::VersionedEventRepositoryWithStreams, RepositoryVersion};
-use crate::repository::{VersionDiff, VersionedRepositoryError, WithFineGrainedStreamId};
+use crate::repository::{
+    event::{
+        AllEventsFilter, AllEventsRepository, BackwardEventRepository, DeletableEventRepository,
+        EventStream, FilteredEventRepository, GlobalEvent, RecordedEvent, StreamInfo,
+        StreamInfoRepository, StreamListingRepository, StreamMetadata, StreamMetadataRepository,
+        StreamingEventRepository, VersionedEventRepositoryWithStreams,
+    },
+    RepositoryVersion,
+};
+use crate::repository::{
+    VersionDiff, VersionedRepositoryError, WithEventType, WithFineGrainedStreamId,
+};
 
 use super::{RedisRepositoryError, RedisVersion};
 
+/// Entries fetched per XRANGE when streaming
+const READ_PAGE_SIZE: usize = 500;
+
+/// Metadata hash field holding the id of the last entry hidden by `soft_delete`
+pub const SOFT_DELETED_THROUGH: &str = "soft_deleted_through";
+
+/// Hides a sub stream's entries up to `ARGV[3]`, unless it was hard deleted meanwhile or the redis stream's
+/// newest entry is no longer `ARGV[1]` - a later append could belong to the sub stream, so it has to be read again
+const SOFT_DELETE_SCRIPT: &str = r#"
+if redis.call('SISMEMBER', KEYS[3], ARGV[4]) == 1 then
+    return -1
+end
+local newest = redis.call('XREVRANGE', KEYS[1], '+', '-', 'COUNT', 1)
+local newest_id = ''
+if #newest > 0 then
+    newest_id = newest[1][1]
+end
+if newest_id ~= ARGV[1] then
+    return 0
+end
+redis.call('HSET', KEYS[2], ARGV[2], ARGV[3])
+return 1
+"#;
+
+/// Hard deletes a sub stream - records `ARGV[2]` in the tombstone set and XDELs its entries `ARGV[3..]`, unless it
+/// was hard deleted meanwhile or the redis stream's newest entry is no longer `ARGV[1]`
+const HARD_DELETE_SCRIPT: &str = r#"
+if redis.call('SISMEMBER', KEYS[2], ARGV[2]) == 1 then
+    return -1
+end
+local newest = redis.call('XREVRANGE', KEYS[1], '+', '-', 'COUNT', 1)
+local newest_id = ''
+if #newest > 0 then
+    newest_id = newest[1][1]
+end
+if newest_id ~= ARGV[1] then
+    return 0
+end
+redis.call('SADD', KEYS[2], ARGV[2])
+for i = 3, #ARGV, 1000 do
+    redis.call('XDEL', KEYS[1], unpack(ARGV, i, math.min(i + 999, #ARGV)))
+end
+return 1
+"#;
+
+/// XADDs entries to the redis stream, unless sub stream `ARGV[2]` was hard deleted meanwhile or the redis stream's
+/// newest entry is no longer `ARGV[1]`. `ARGV[3]` counts the trim arguments that follow, then each entry is its
+/// number of field and value arguments followed by them. Returns the new entry ids
+const APPEND_SCRIPT: &str = r#"
+if redis.call('SISMEMBER', KEYS[2], ARGV[2]) == 1 then
+    return -1
+end
+local newest = redis.call('XREVRANGE', KEYS[1], '+', '-', 'COUNT', 1)
+local newest_id = ''
+if #newest > 0 then
+    newest_id = newest[1][1]
+end
+if newest_id ~= ARGV[1] then
+    return 0
+end
+local trim_end = 3 + tonumber(ARGV[3])
+local ids = {}
+local i = trim_end + 1
+while i <= #ARGV do
+    local fields_end = i + tonumber(ARGV[i])
+    local args = {KEYS[1]}
+    for t = 4, trim_end do
+        args[#args + 1] = ARGV[t]
+    end
+    args[#args + 1] = '*'
+    for f = i + 1, fields_end do
+        args[#args + 1] = ARGV[f]
+    end
+    ids[#ids + 1] = redis.call('XADD', unpack(args))
+    i = fields_end + 1
+end
+return ids
+"#;
+
+/// XADD arguments trimming the redis stream on the way when a `StreamTrim` is set
+fn trim_args(trim: Option<StreamTrim>) -> Vec<String> {
+    match trim {
+        Some(StreamTrim::MaxLen(max_len)) => {
+            vec!["MAXLEN".to_owned(), "~".to_owned(), max_len.to_string()]
+        }
+        Some(StreamTrim::MaxAge(max_age)) => {
+            let min_id = SystemTime::now()
+                .checked_sub(max_age)
+                .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since_epoch| since_epoch.as_millis());
+
+            vec!["MINID".to_owned(), "~".to_owned(), min_id.to_string()]
+        }
+        None => vec![],
+    }
+}
+
+/// Field and value arguments of a stream entry
+fn entry_args<SM: StreamModel>(dto: &SM::Data) -> Vec<Vec<u8>> {
+    dto.to_redis_args()
+}
+
 pub trait StreamModelDTO<SM, DTOErr>
 where
     SM: StreamModel,
@@ -25,12 +143,22 @@ where
         Self: Sized;
 }
 
+/// Approximate trimming of the whole redis stream, applied by XADD on every append
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamTrim {
+    /// `MAXLEN ~` - keeps roughly the newest entries
+    MaxLen(usize),
+    /// `MINID ~` - drops entries older than the given age
+    MaxAge(Duration),
+}
+
 #[derive(Debug, Clone)]
 pub struct RedisStreamsEventRepository<SM, DTO>
 where
     SM: StreamModel<Data = DTO>,
 {
     client: Client,
+    trim: Option<StreamTrim>,
     _sm: PhantomData<SM>,
 }
 
@@ -41,15 +169,315 @@ where
     pub fn new(client: &Client) -> Self {
         Self {
             client: client.to_owned(),
+            trim: None,
             _sm: PhantomData::default(),
         }
     }
 
+    /// Trims the whole redis stream on append - every sub stream loses its old entries alike
+    pub fn with_trim(mut self, trim: StreamTrim) -> Self {
+        self.trim = Some(trim);
+        self
+    }
+
     pub async fn get_connection(&self) -> Result<MultiplexedConnection, RedisError> {
         self.client.get_multiplexed_async_connection().await
     }
 }
 
+impl<SM, DTO> RedisStreamsEventRepository<SM, DTO>
+where
+    SM: StreamModel<Data = DTO>,
+    DTO: WithFineGrainedStreamId + redis_om::FromRedisValue,
+{
+    /// Set of hard deleted sub stream ids, kept next to the redis stream
+    fn tombstones_key() -> String {
+        format!("{}:tombstones", SM::stream_key())
+    }
+
+    async fn is_deleted(
+        conn: &mut MultiplexedConnection,
+        stream: &str,
+    ) -> Result<bool, RedisError> {
+        redis::cmd("SISMEMBER")
+            .arg(Self::tombstones_key())
+            .arg(stream)
+            .query_async(conn)
+            .await
+    }
+
+    /// Entry ids of a sub stream, oldest first - redis cannot filter streams so the whole stream is read
+    async fn sub_stream_ids<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+        stream: &str,
+    ) -> Result<Vec<RedisVersion>, RedisRepositoryError<DTOErr>> {
+        let rv = <SM as StreamModel>::range("-".to_string(), "+".to_string(), conn)
+            .await
+            .map_err(RedisRepositoryError::ReadError)?;
+
+        let mut ids = vec![];
+
+        for raw_event in rv {
+            let dto = raw_event
+                .data::<DTO>()
+                .map_err(RedisRepositoryError::ParseDTO)?;
+
+            if dto.fine_grained_eq(stream) {
+                ids.push(
+                    RedisVersion::try_from(raw_event.id.as_ref())
+                        .map_err(RedisRepositoryError::Version)?,
+                );
+            }
+        }
+
+        Ok(ids)
+    }
+
+    async fn delete_entries<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+        ids: &[RedisVersion],
+    ) -> Result<(), RedisRepositoryError<DTOErr>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        redis::cmd("XDEL")
+            .arg(SM::stream_key().to_string())
+            .arg(ids.iter().map(|id| id.to_string()).collect::<Vec<_>>())
+            .query_async::<_, usize>(conn)
+            .await
+            .map_err(RedisRepositoryError::SaveError)?;
+
+        Ok(())
+    }
+
+    /// Reads the sub stream's entry ids, checking the ones not hidden by a soft delete against the expected
+    /// version
+    async fn expect_sub_stream<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+        version: &RepositoryVersion<RedisVersion>,
+        stream: &str,
+    ) -> Result<
+        Vec<RedisVersion>,
+        VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>,
+    > {
+        let hidden_through = Self::hidden_through(conn, stream)
+            .await
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let ids = Self::sub_stream_ids(conn, stream)
+            .await
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let visible = hidden_through.map_or(&ids[..], |through| {
+            &ids[ids.partition_point(|id| id <= &through)..]
+        });
+
+        let actual = visible.last().map_or(RepositoryVersion::NoStream, |v| {
+            RepositoryVersion::Exact(*v)
+        });
+
+        let matches = match version {
+            RepositoryVersion::Any => true,
+            RepositoryVersion::NoStream => visible.is_empty(),
+            RepositoryVersion::StreamExists => !visible.is_empty(),
+            RepositoryVersion::Exact(_) => actual == *version,
+        };
+
+        if matches {
+            Ok(ids)
+        } else {
+            Err(VersionedRepositoryError::VersionConflict(VersionDiff::new(
+                *version, actual,
+            )))
+        }
+    }
+
+    /// Hash holding a sub stream's metadata, kept next to the redis stream
+    fn metadata_key(stream: &str) -> String {
+        format!("{}:metadata:{}", SM::stream_key(), stream)
+    }
+
+    async fn read_metadata<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+        stream: &str,
+    ) -> Result<StreamMetadata<RedisVersion>, RedisRepositoryError<DTOErr>> {
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(Self::metadata_key(stream))
+            .query_async(conn)
+            .await
+            .map_err(RedisRepositoryError::ReadError)?;
+
+        let parse_err = |field: &str| RedisRepositoryError::ParseMetadata(field.to_owned());
+
+        Ok(StreamMetadata {
+            max_age: fields
+                .get("max_age_ms")
+                .map(|v| v.parse().map(Duration::from_millis))
+                .transpose()
+                .map_err(|_| parse_err("max_age_ms"))?,
+            max_count: fields
+                .get("max_count")
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|_| parse_err("max_count"))?,
+            truncate_before: fields
+                .get("truncate_before")
+                .map(|v| RedisVersion::try_from(v.as_str()))
+                .transpose()
+                .map_err(RedisRepositoryError::Version)?,
+            custom: fields
+                .get("custom")
+                .map(|v| serde_json::from_str(v))
+                .transpose()
+                .map_err(|_| parse_err("custom"))?
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn write_metadata<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+        stream: &str,
+        metadata: &StreamMetadata<RedisVersion>,
+    ) -> Result<(), RedisRepositoryError<DTOErr>> {
+        let key = Self::metadata_key(stream);
+        let mut fields = vec![(
+            "custom",
+            serde_json::to_string(&metadata.custom)
+                .map_err(|_| RedisRepositoryError::ParseMetadata("custom".to_owned()))?,
+        )];
+
+        if let Some(max_age) = metadata.max_age {
+            fields.push(("max_age_ms", max_age.as_millis().to_string()));
+        }
+        if let Some(max_count) = metadata.max_count {
+            fields.push(("max_count", max_count.to_string()));
+        }
+        if let Some(truncate_before) = metadata.truncate_before {
+            fields.push(("truncate_before", truncate_before.to_string()));
+        }
+
+        // Only the metadata fields are replaced, the soft delete marker shares the hash
+        redis::pipe()
+            .atomic()
+            .cmd("HDEL")
+            .arg(&key)
+            .arg(&["custom", "max_age_ms", "max_count", "truncate_before"])
+            .ignore()
+            .cmd("HSET")
+            .arg(&key)
+            .arg(fields)
+            .ignore()
+            .query_async::<_, ()>(conn)
+            .await
+            .map_err(RedisRepositoryError::SaveError)
+    }
+
+    async fn ensure_not_deleted<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+        stream: &str,
+    ) -> Result<(), RedisRepositoryError<DTOErr>> {
+        if Self::is_deleted(conn, stream)
+            .await
+            .map_err(RedisRepositoryError::ReadError)?
+        {
+            Err(RedisRepositoryError::StreamDeleted(stream.to_owned()))
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn soft_deleted_through<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+        stream: &str,
+    ) -> Result<Option<RedisVersion>, RedisRepositoryError<DTOErr>> {
+        redis::cmd("HGET")
+            .arg(Self::metadata_key(stream))
+            .arg(SOFT_DELETED_THROUGH)
+            .query_async::<_, Option<String>>(conn)
+            .await
+            .map_err(RedisRepositoryError::ReadError)?
+            .map(|v| RedisVersion::try_from(v.as_str()))
+            .transpose()
+            .map_err(RedisRepositoryError::Version)
+    }
+
+    /// Checks the sub stream was not hard deleted and returns the last entry id its soft deletes hid
+    async fn hidden_through<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+        stream: &str,
+    ) -> Result<Option<RedisVersion>, RedisRepositoryError<DTOErr>> {
+        Self::ensure_not_deleted(conn, stream).await?;
+        Self::soft_deleted_through(conn, stream).await
+    }
+
+    /// Id of the newest entry of the whole redis stream
+    async fn newest_entry_id<DTOErr: Error + Debug>(
+        conn: &mut MultiplexedConnection,
+    ) -> Result<Option<String>, RedisRepositoryError<DTOErr>> {
+        let newest: Vec<HashMap<String, redis::Value>> = redis::cmd("XREVRANGE")
+            .arg(SM::stream_key().to_string())
+            .arg("+")
+            .arg("-")
+            .arg("COUNT")
+            .arg(1)
+            .query_async(conn)
+            .await
+            .map_err(RedisRepositoryError::ReadError)?;
+
+        Ok(newest.into_iter().flat_map(HashMap::into_keys).next())
+    }
+}
+
+impl<SM, DTO> RedisStreamsEventRepository<SM, DTO>
+where
+    SM: StreamModel<Data = DTO>,
+    DTO: WithFineGrainedStreamId + redis_om::FromRedisValue,
+{
+    /// Applies a sub stream's metadata retention by deleting its expired entries
+    ///
+    /// Redis MAXLEN/MINID trimming works on the whole redis stream and finding a sub stream's entries reads all
+    /// of it, so appends and `set_metadata` leave retention alone - run this from a periodic maintenance job
+    pub async fn enforce_retention<DTOErr: Error + Debug>(
+        &self,
+        stream: &str,
+    ) -> Result<(), RedisRepositoryError<DTOErr>> {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)?;
+
+        Self::ensure_not_deleted(&mut conn, stream).await?;
+
+        let metadata = Self::read_metadata(&mut conn, stream).await?;
+
+        if metadata.max_age.is_none()
+            && metadata.max_count.is_none()
+            && metadata.truncate_before.is_none()
+        {
+            return Ok(());
+        }
+
+        let ids = Self::sub_stream_ids(&mut conn, stream).await?;
+        let mut start = metadata
+            .truncate_before
+            .map_or(0, |v| ids.partition_point(|id| id < &v));
+
+        if let Some(max_count) = metadata.max_count {
+            start = start.max(ids.len().saturating_sub(max_count));
+        }
+
+        if let Some(cutoff) = metadata
+            .max_age
+            .and_then(|max_age| SystemTime::now().checked_sub(max_age))
+        {
+            start = start.max(ids.partition_point(|id| id.recorded_at() < cutoff));
+        }
+
+        Self::delete_entries(&mut conn, &ids[..start]).await
+    }
+}
+
 #[async_trait]
 impl<'a, E, SM, DTO, DTOErr>
     VersionedEventRepositoryWithStreams<'a, E, RedisRepositoryError<DTOErr>>
@@ -83,6 +511,13 @@ where
             .map_err(RedisRepositoryError::ConnectionError)
             .map_err(VersionedRepositoryError::RepoErr)?;
 
+        let hidden_through = match id {
+            Some(stream_id) => Self::hidden_through(&mut conn, stream_id)
+                .await
+                .map_err(VersionedRepositoryError::RepoErr)?,
+            None => None,
+        };
+
         let rv = <SM as StreamModel>::range("-", "+", &mut conn)
             .await
             .map_err(RedisRepositoryError::ReadError)
@@ -106,14 +541,20 @@ where
                 }
             }
 
-            let ev = E::try_from_dto(dto)
-                .map_err(RedisRepositoryError::FromDTO)
+            let version = RedisVersion::try_from(raw_event.id.as_ref())
+                .map_err(RedisRepositoryError::Version)
                 .map_err(VersionedRepositoryError::RepoErr)?;
 
-            redis_version =
-                RepositoryVersion::Exact(RedisVersion::try_from(raw_event.id.as_ref()).unwrap());
+            if hidden_through.is_some_and(|through| version <= through) {
+                continue;
+            }
 
-            evts.push(ev);
+            evts.push(
+                E::try_from_dto(dto)
+                    .map_err(RedisRepositoryError::FromDTO)
+                    .map_err(VersionedRepositoryError::RepoErr)?,
+            );
+            redis_version = RepositoryVersion::Exact(version);
         }
 
         Ok((evts, redis_version))
@@ -133,6 +574,13 @@ where
             .map_err(RedisRepositoryError::ConnectionError)
             .map_err(VersionedRepositoryError::RepoErr)?;
 
+        let hidden_through = match id {
+            Some(stream_id) => Self::hidden_through(&mut conn, stream_id)
+                .await
+                .map_err(VersionedRepositoryError::RepoErr)?,
+            None => None,
+        };
+
         let start = if let RepositoryVersion::Exact(v) = version {
             v.to_string()
         } else {
@@ -162,23 +610,31 @@ where
                 }
             }
 
-            let ev = E::try_from_dto(dto)
-                .map_err(RedisRepositoryError::FromDTO)
+            let version = RedisVersion::try_from(raw_event.id.as_ref())
+                .map_err(RedisRepositoryError::Version)
                 .map_err(VersionedRepositoryError::RepoErr)?;
 
-            redis_version =
-                RepositoryVersion::Exact(RedisVersion::try_from(raw_event.id.as_ref()).unwrap());
+            if hidden_through.is_some_and(|through| version <= through) {
+                continue;
+            }
 
-            evts.push(ev);
+            evts.push(
+                E::try_from_dto(dto)
+                    .map_err(RedisRepositoryError::FromDTO)
+                    .map_err(VersionedRepositoryError::RepoErr)?,
+            );
+            redis_version = RepositoryVersion::Exact(version);
         }
 
         Ok((evts, redis_version))
     }
 
+    /// The version is checked against the sub stream's visible entries, and the entries are only added while the
+    /// redis stream's newest entry is still the one seen by that check - otherwise the check runs again
     async fn append(
         &mut self,
         version: &RepositoryVersion<RedisVersion>,
-        _stream: &Self::StreamId,
+        stream: &Self::StreamId,
         events: &Vec<E>,
     ) -> Result<
         (Vec<E>, RepositoryVersion<RedisVersion>),
@@ -194,70 +650,882 @@ where
             .map_err(RedisRepositoryError::ConnectionError)
             .map_err(VersionedRepositoryError::RepoErr)?;
 
-        match version {
-            RepositoryVersion::Any => {}
-            RepositoryVersion::Exact(v) => {
-                let res = <SM as StreamModel>::range(v.to_string(), "-".to_string(), &mut conn)
-                    .await
-                    .map_err(RedisRepositoryError::ReadError)
-                    .map_err(VersionedRepositoryError::RepoErr)?;
+        let dtos = events
+            .iter()
+            .map(|e| entry_args::<SM>(&e.clone().into_dto()))
+            .collect::<Vec<_>>();
+        let trim = trim_args(self.trim);
+        let script = redis::Script::new(APPEND_SCRIPT);
 
-                if res.len() > 1 {
-                    let last_message_id = res.last().unwrap().id.to_owned();
+        let ids = loop {
+            let newest = Self::newest_entry_id(&mut conn)
+                .await
+                .map_err(VersionedRepositoryError::RepoErr)?;
+            Self::expect_sub_stream(&mut conn, version, stream).await?;
 
-                    return Err(VersionedRepositoryError::VersionConflict(VersionDiff::new(
-                        *version,
-                        RepositoryVersion::Exact(
-                            RedisVersion::try_from(last_message_id.as_ref()).unwrap(),
-                        ),
-                    )));
-                }
+            if dtos.is_empty() {
+                break vec![];
+            }
+
+            let mut invocation = script.prepare_invoke();
+            invocation
+                .key(SM::stream_key().to_string())
+                .key(Self::tombstones_key())
+                .arg(newest.unwrap_or_default())
+                .arg(stream)
+                .arg(trim.len())
+                .arg(&trim);
+
+            for fields in &dtos {
+                invocation.arg(fields.len()).arg(fields);
             }
-            RepositoryVersion::NoStream => {
-                let len = <SM as StreamModel>::len(&mut conn)
-                    .await
-                    .map_err(RedisRepositoryError::ReadError)
-                    .map_err(VersionedRepositoryError::RepoErr)?;
 
-                if len > 0 {
-                    return Err(VersionedRepositoryError::VersionConflict(VersionDiff::new(
-                        *version,
-                        RepositoryVersion::StreamExists,
-                    )));
+            match invocation
+                .invoke_async::<_, redis::Value>(&mut conn)
+                .await
+                .map_err(RedisRepositoryError::SaveError)
+                .map_err(VersionedRepositoryError::RepoErr)?
+            {
+                redis::Value::Int(-1) => {
+                    return Err(VersionedRepositoryError::RepoErr(
+                        RedisRepositoryError::StreamDeleted(stream.to_owned()),
+                    ))
+                }
+                // Appended to since the read, check the version again
+                redis::Value::Int(_) => continue,
+                added => {
+                    break redis::from_redis_value::<Vec<String>>(&added)
+                        .map_err(RedisRepositoryError::SaveError)
+                        .map_err(VersionedRepositoryError::RepoErr)?
                 }
             }
-            RepositoryVersion::StreamExists => {
-                let len = <SM as StreamModel>::len(&mut conn)
-                    .await
-                    .map_err(RedisRepositoryError::ReadError)
-                    .map_err(VersionedRepositoryError::RepoErr)?;
+        };
 
-                if len == 0 {
-                    return Err(VersionedRepositoryError::VersionConflict(VersionDiff::new(
-                        *version,
-                        RepositoryVersion::NoStream,
-                    )));
+        let version = match ids.last() {
+            Some(id) => RepositoryVersion::Exact(
+                RedisVersion::try_from(id.as_str())
+                    .map_err(RedisRepositoryError::Version)
+                    .map_err(VersionedRepositoryError::RepoErr)?,
+            ),
+            None => RepositoryVersion::NoStream,
+        };
+
+        Ok((events.to_owned(), version))
+    }
+}
+
+/// A client side filter - redis cannot select stream entries by field, so every entry from `version` on is still
+/// read and parsed into its DTO, and only the ones of a kept type are converted into events
+#[async_trait]
+impl<'a, E, SM, DTO, DTOErr> FilteredEventRepository<'a, E, RedisRepositoryError<DTOErr>>
+    for RedisStreamsEventRepository<SM, DTO>
+where
+    E: Event
+        + Sync
+        + Send
+        + Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + StreamModelDTO<SM, DTOErr>,
+    SM: StreamModel<Data = DTO> + Send + Sync,
+    DTO: WithFineGrainedStreamId + WithEventType + Clone + Send + Sync + redis_om::FromRedisValue,
+    DTOErr: Debug + Error + Send + Sync + Clone,
+{
+    async fn load_types_from_version(
+        &self,
+        version: &RepositoryVersion<RedisVersion>,
+        id: Option<&String>,
+        event_types: &[&str],
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<RedisVersion>),
+        VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>,
+    > {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let hidden_through = match id {
+            Some(stream_id) => Self::hidden_through(&mut conn, stream_id)
+                .await
+                .map_err(VersionedRepositoryError::RepoErr)?,
+            None => None,
+        };
+
+        let start = if let RepositoryVersion::Exact(v) = version {
+            v.to_string()
+        } else {
+            "-".to_string()
+        };
+
+        let rv = <SM as StreamModel>::range(start, "+".to_string(), &mut conn)
+            .await
+            .map_err(RedisRepositoryError::ReadError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let mut evts: Vec<E> = vec![];
+        let mut redis_version = RepositoryVersion::NoStream;
+
+        for raw_event in rv {
+            let dto = raw_event
+                .data::<DTO>()
+                .map_err(RedisRepositoryError::ParseDTO)
+                .map_err(VersionedRepositoryError::RepoErr)?;
+
+            if let Some(stream_id) = id {
+                if !dto.fine_grained_eq(stream_id) {
+                    continue;
                 }
             }
-        }
 
-        let evts = events
-            .iter()
-            .map(|e| e.clone().into_dto())
-            .collect::<Vec<DTO>>();
+            let version = RedisVersion::try_from(raw_event.id.as_ref())
+                .map_err(RedisRepositoryError::Version)
+                .map_err(VersionedRepositoryError::RepoErr)?;
+
+            if hidden_through.is_some_and(|through| version <= through) {
+                continue;
+            }
 
-        let mut version = RepositoryVersion::NoStream;
+            // Skipped events still move the version on so it stays usable for OCC
+            redis_version = RepositoryVersion::Exact(version);
 
-        for e in evts {
-            let version_str = SM::publish(&e, &mut conn).await.map_err(|e| {
-                VersionedRepositoryError::RepoErr(RedisRepositoryError::ConnectionError(e))
-            })?;
+            if !event_types.contains(&dto.event_type().as_str()) {
+                continue;
+            }
 
-            version =
-                RepositoryVersion::Exact(RedisVersion::try_from(version_str.as_str()).unwrap());
+            evts.push(
+                E::try_from_dto(dto)
+                    .map_err(RedisRepositoryError::FromDTO)
+                    .map_err(VersionedRepositoryError::RepoErr)?,
+            );
         }
 
-        Ok((events.to_owned(), version))
+        Ok((evts, redis_version))
+    }
+}
+
+/// Entries are read a page of `READ_PAGE_SIZE` at a time and only parsed and converted into events as the
+/// stream is polled
+impl<'a, E, SM, DTO, DTOErr> StreamingEventRepository<'a, E, RedisRepositoryError<DTOErr>>
+    for RedisStreamsEventRepository<SM, DTO>
+where
+    E: Event
+        + Sync
+        + Send
+        + Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + StreamModelDTO<SM, DTOErr>,
+    SM: StreamModel<Data = DTO> + Send + Sync,
+    DTO: WithFineGrainedStreamId + Clone + Send + Sync + redis_om::FromRedisValue,
+    DTOErr: Debug + Error + Send + Sync + Clone + 'static,
+{
+    fn stream_from_version(
+        &self,
+        version: RepositoryVersion<RedisVersion>,
+        id: Option<String>,
+    ) -> EventStream<'_, E, RedisVersion, RedisRepositoryError<DTOErr>> {
+        let start = if let RepositoryVersion::Exact(v) = version {
+            v.to_string()
+        } else {
+            "-".to_string()
+        };
+
+        let sub_stream = id.clone();
+
+        stream::try_unfold(
+            (
+                None::<(MultiplexedConnection, Option<RedisVersion>)>,
+                Some(start),
+            ),
+            move |(conn, start)| {
+                let id = sub_stream.clone();
+
+                async move {
+                    let start = match start {
+                        Some(start) => start,
+                        None => return Ok(None),
+                    };
+
+                    let (mut conn, hidden_through) = match conn {
+                        Some(opened) => opened,
+                        None => {
+                            let mut conn = self
+                                .get_connection()
+                                .await
+                                .map_err(RedisRepositoryError::ConnectionError)?;
+
+                            let hidden_through = match &id {
+                                Some(stream_id) => {
+                                    Self::hidden_through(&mut conn, stream_id).await?
+                                }
+                                None => None,
+                            };
+
+                            (conn, hidden_through)
+                        }
+                    };
+
+                    let page = <SM as StreamModel>::range_count(
+                        start,
+                        "+".to_string(),
+                        READ_PAGE_SIZE,
+                        &mut conn,
+                    )
+                    .await
+                    .map_err(RedisRepositoryError::ReadError)?;
+
+                    // Exclusive range start - requires redis 6.2+
+                    let next = match page.last() {
+                        Some(last) if page.len() == READ_PAGE_SIZE => Some(format!("({}", last.id)),
+                        _ => None,
+                    };
+
+                    Ok::<_, RedisRepositoryError<DTOErr>>(Some((
+                        (page, hidden_through),
+                        (Some((conn, hidden_through)), next),
+                    )))
+                }
+            },
+        )
+        .map_ok(|(page, hidden_through)| {
+            stream::iter(
+                page.into_iter()
+                    .map(move |raw_event| Ok((raw_event, hidden_through))),
+            )
+        })
+        .try_flatten()
+        .try_filter_map(move |(raw_event, hidden_through)| {
+            let id = id.clone();
+
+            async move {
+                let dto = raw_event
+                    .data::<DTO>()
+                    .map_err(RedisRepositoryError::ParseDTO)?;
+
+                // Sub streams are filtered client side, same as load
+                if let Some(stream_id) = id {
+                    if !dto.fine_grained_eq(&stream_id) {
+                        return Ok(None);
+                    }
+                }
+
+                let version = RedisVersion::try_from(raw_event.id.as_ref())
+                    .map_err(RedisRepositoryError::Version)?;
+
+                if hidden_through.is_some_and(|through| version <= through) {
+                    return Ok(None);
+                }
+
+                Ok(Some(RecordedEvent {
+                    event: E::try_from_dto(dto).map_err(RedisRepositoryError::FromDTO)?,
+                    recorded_at: Some(version.recorded_at()),
+                    version,
+                    metadata: HashMap::new(),
+                }))
+            }
+        })
+        .map_err(VersionedRepositoryError::RepoErr)
+        .boxed()
+    }
+}
+
+/// Pages through XREVRANGE from `from` towards the start of the redis stream - sub streams share the redis
+/// stream and are filtered client side, so paging goes on until `max_count` of the sub stream's entries are found
+#[async_trait]
+impl<'a, E, SM, DTO, DTOErr> BackwardEventRepository<'a, E, RedisRepositoryError<DTOErr>>
+    for RedisStreamsEventRepository<SM, DTO>
+where
+    E: Event
+        + Sync
+        + Send
+        + Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + StreamModelDTO<SM, DTOErr>,
+    SM: StreamModel<Data = DTO> + Send + Sync,
+    DTO: WithFineGrainedStreamId + Clone + Send + Sync + redis_om::FromRedisValue,
+    DTOErr: Debug + Error + Send + Sync + Clone,
+{
+    async fn load_backward(
+        &self,
+        id: Option<&String>,
+        from: &RepositoryVersion<RedisVersion>,
+        max_count: usize,
+    ) -> Result<
+        Vec<RecordedEvent<E, RedisVersion>>,
+        VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>,
+    > {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let hidden_through = match id {
+            Some(stream_id) => Self::hidden_through(&mut conn, stream_id)
+                .await
+                .map_err(VersionedRepositoryError::RepoErr)?,
+            None => None,
+        };
+
+        let mut end = if let RepositoryVersion::Exact(v) = from {
+            v.to_string()
+        } else {
+            "+".to_string()
+        };
+
+        // A sub stream's entries may be spread out, so its pages are not bounded by max_count
+        let page_size = if id.is_some() {
+            READ_PAGE_SIZE
+        } else {
+            max_count.min(READ_PAGE_SIZE)
+        };
+
+        let mut evts = vec![];
+
+        while evts.len() < max_count {
+            let page: Vec<HashMap<String, redis::Value>> = redis::cmd("XREVRANGE")
+                .arg(SM::stream_key().to_string())
+                .arg(&end)
+                .arg("-")
+                .arg("COUNT")
+                .arg(page_size)
+                .query_async(&mut conn)
+                .await
+                .map_err(RedisRepositoryError::ReadError)
+                .map_err(VersionedRepositoryError::RepoErr)?;
+            let full = page.len() == page_size;
+
+            for (entry_id, fields) in page.into_iter().flatten() {
+                // Exclusive range end - requires redis 6.2+
+                end = format!("({}", entry_id);
+
+                if evts.len() >= max_count {
+                    break;
+                }
+
+                let dto = DTO::from_redis_value(&fields)
+                    .map_err(RedisRepositoryError::ParseDTO)
+                    .map_err(VersionedRepositoryError::RepoErr)?;
+
+                if let Some(stream_id) = id {
+                    if !dto.fine_grained_eq(stream_id) {
+                        continue;
+                    }
+                }
+
+                let version = RedisVersion::try_from(entry_id.as_str())
+                    .map_err(RedisRepositoryError::Version)
+                    .map_err(VersionedRepositoryError::RepoErr)?;
+
+                // Older entries are all hidden too
+                if hidden_through.is_some_and(|through| version <= through) {
+                    return Ok(evts);
+                }
+
+                evts.push(RecordedEvent {
+                    event: E::try_from_dto(dto)
+                        .map_err(RedisRepositoryError::FromDTO)
+                        .map_err(VersionedRepositoryError::RepoErr)?,
+                    recorded_at: Some(version.recorded_at()),
+                    version,
+                    metadata: HashMap::new(),
+                });
+            }
+
+            if !full {
+                break;
+            }
+        }
+
+        Ok(evts)
+    }
+}
+
+#[async_trait]
+impl<'a, E, SM, DTO, DTOErr> StreamInfoRepository<'a, E, RedisRepositoryError<DTOErr>>
+    for RedisStreamsEventRepository<SM, DTO>
+where
+    E: Event
+        + Sync
+        + Send
+        + Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + StreamModelDTO<SM, DTOErr>,
+    SM: StreamModel<Data = DTO> + Send + Sync,
+    DTO: WithFineGrainedStreamId + Clone + Send + Sync + redis_om::FromRedisValue,
+    DTOErr: Debug + Error + Send + Sync + Clone,
+{
+    async fn stream_info(
+        &self,
+        id: Option<&String>,
+    ) -> Result<
+        StreamInfo<RedisVersion>,
+        VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>,
+    > {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        if let Some(stream_id) = id {
+            if Self::is_deleted(&mut conn, stream_id)
+                .await
+                .map_err(RedisRepositoryError::ReadError)
+                .map_err(VersionedRepositoryError::RepoErr)?
+            {
+                return Ok(StreamInfo {
+                    deleted: true,
+                    ..StreamInfo::no_stream()
+                });
+            }
+        }
+
+        let (event_count, first, last) = match id {
+            None => {
+                let len = <SM as StreamModel>::len(&mut conn)
+                    .await
+                    .map_err(RedisRepositoryError::ReadError)
+                    .map_err(VersionedRepositoryError::RepoErr)?;
+
+                if len == 0 {
+                    return Ok(StreamInfo::no_stream());
+                }
+
+                let info: StreamInfoStreamReply = redis::cmd("XINFO")
+                    .arg("STREAM")
+                    .arg(SM::stream_key().to_string())
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(RedisRepositoryError::ReadError)
+                    .map_err(VersionedRepositoryError::RepoErr)?;
+
+                (info.length, info.first_entry.id, info.last_entry.id)
+            }
+            Some(stream_id) => {
+                let hidden_through = Self::soft_deleted_through(&mut conn, stream_id)
+                    .await
+                    .map_err(VersionedRepositoryError::RepoErr)?;
+
+                // Sub streams are filtered client side so the whole shared stream has to be scanned
+                let rv = <SM as StreamModel>::range("-".to_string(), "+".to_string(), &mut conn)
+                    .await
+                    .map_err(RedisRepositoryError::ReadError)
+                    .map_err(VersionedRepositoryError::RepoErr)?;
+
+                let mut ids = vec![];
+
+                for raw_event in rv {
+                    let dto = raw_event
+                        .data::<DTO>()
+                        .map_err(RedisRepositoryError::ParseDTO)
+                        .map_err(VersionedRepositoryError::RepoErr)?;
+
+                    if !dto.fine_grained_eq(stream_id) {
+                        continue;
+                    }
+
+                    let version = RedisVersion::try_from(raw_event.id.as_ref())
+                        .map_err(RedisRepositoryError::Version)
+                        .map_err(VersionedRepositoryError::RepoErr)?;
+
+                    if hidden_through.is_none_or(|through| version > through) {
+                        ids.push(raw_event.id);
+                    }
+                }
+
+                match (ids.first(), ids.last()) {
+                    (Some(first), Some(last)) => (ids.len(), first.to_owned(), last.to_owned()),
+                    _ => return Ok(StreamInfo::no_stream()),
+                }
+            }
+        };
+
+        let first = RedisVersion::try_from(first.as_str())
+            .map_err(RedisRepositoryError::Version)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+        let last = RedisVersion::try_from(last.as_str())
+            .map_err(RedisRepositoryError::Version)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        Ok(StreamInfo {
+            version: RepositoryVersion::Exact(last),
+            event_count,
+            first_recorded_at: Some(first.recorded_at()),
+            last_recorded_at: Some(last.recorded_at()),
+            deleted: false,
+        })
+    }
+}
+
+/// Sub streams share one redis stream, so a soft delete records the last entry it hides in the sub stream's
+/// metadata hash for reads to skip, while hard deletes remove the entries with XDEL and record the id in a
+/// tombstone set checked on every read and append
+#[async_trait]
+impl<'a, E, SM, DTO, DTOErr> DeletableEventRepository<'a, E, RedisRepositoryError<DTOErr>>
+    for RedisStreamsEventRepository<SM, DTO>
+where
+    E: Event
+        + Sync
+        + Send
+        + Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + StreamModelDTO<SM, DTOErr>,
+    SM: StreamModel<Data = DTO> + Send + Sync,
+    DTO: WithFineGrainedStreamId + Clone + Send + Sync + redis_om::FromRedisValue,
+    DTOErr: Debug + Error + Send + Sync + Clone,
+{
+    async fn soft_delete(
+        &mut self,
+        version: &RepositoryVersion<RedisVersion>,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>> {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let script = redis::Script::new(SOFT_DELETE_SCRIPT);
+
+        loop {
+            let newest = Self::newest_entry_id(&mut conn)
+                .await
+                .map_err(VersionedRepositoryError::RepoErr)?;
+            let ids = Self::expect_sub_stream(&mut conn, version, stream).await?;
+
+            let hide_through = match ids.last() {
+                Some(last) => last.to_string(),
+                None => return Ok(()),
+            };
+
+            let written: i64 = script
+                .key(SM::stream_key().to_string())
+                .key(Self::metadata_key(stream))
+                .key(Self::tombstones_key())
+                .arg(newest.unwrap_or_default())
+                .arg(SOFT_DELETED_THROUGH)
+                .arg(hide_through)
+                .arg(stream)
+                .invoke_async(&mut conn)
+                .await
+                .map_err(RedisRepositoryError::SaveError)
+                .map_err(VersionedRepositoryError::RepoErr)?;
+
+            match written {
+                1 => return Ok(()),
+                -1 => {
+                    return Err(VersionedRepositoryError::RepoErr(
+                        RedisRepositoryError::StreamDeleted(stream.to_owned()),
+                    ))
+                }
+                // Appended to since the read, check the version again
+                _ => continue,
+            }
+        }
+    }
+
+    async fn hard_delete(
+        &mut self,
+        version: &RepositoryVersion<RedisVersion>,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>> {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let script = redis::Script::new(HARD_DELETE_SCRIPT);
+
+        loop {
+            let newest = Self::newest_entry_id(&mut conn)
+                .await
+                .map_err(VersionedRepositoryError::RepoErr)?;
+            let ids = Self::expect_sub_stream(&mut conn, version, stream).await?;
+
+            let mut invocation = script.prepare_invoke();
+            invocation
+                .key(SM::stream_key().to_string())
+                .key(Self::tombstones_key())
+                .arg(newest.unwrap_or_default())
+                .arg(stream)
+                .arg(ids.iter().map(|id| id.to_string()).collect::<Vec<_>>());
+
+            let written: i64 = invocation
+                .invoke_async(&mut conn)
+                .await
+                .map_err(RedisRepositoryError::SaveError)
+                .map_err(VersionedRepositoryError::RepoErr)?;
+
+            match written {
+                1 => return Ok(()),
+                -1 => {
+                    return Err(VersionedRepositoryError::RepoErr(
+                        RedisRepositoryError::StreamDeleted(stream.to_owned()),
+                    ))
+                }
+                // Appended to since the read, check the version again
+                _ => continue,
+            }
+        }
+    }
+
+    async fn truncate_before(
+        &mut self,
+        version: &RedisVersion,
+        stream: &String,
+    ) -> Result<(), VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>> {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let ids = Self::expect_sub_stream(&mut conn, &RepositoryVersion::Any, stream).await?;
+        let before = ids
+            .into_iter()
+            .filter(|id| id < version)
+            .collect::<Vec<_>>();
+
+        Self::delete_entries(&mut conn, &before)
+            .await
+            .map_err(VersionedRepositoryError::RepoErr)
+    }
+}
+
+/// Metadata lives in a hash next to the redis stream - its retention only takes effect once `enforce_retention`
+/// runs for the sub stream
+#[async_trait]
+impl<'a, E, SM, DTO, DTOErr> StreamMetadataRepository<'a, E, RedisRepositoryError<DTOErr>>
+    for RedisStreamsEventRepository<SM, DTO>
+where
+    E: Event
+        + Sync
+        + Send
+        + Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + StreamModelDTO<SM, DTOErr>,
+    SM: StreamModel<Data = DTO> + Send + Sync,
+    DTO: WithFineGrainedStreamId + Clone + Send + Sync + redis_om::FromRedisValue,
+    DTOErr: Debug + Error + Send + Sync + Clone,
+{
+    async fn get_metadata(
+        &self,
+        stream: &String,
+    ) -> Result<
+        StreamMetadata<RedisVersion>,
+        VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>,
+    > {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        Self::ensure_not_deleted(&mut conn, stream)
+            .await
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        Self::read_metadata(&mut conn, stream)
+            .await
+            .map_err(VersionedRepositoryError::RepoErr)
+    }
+
+    async fn set_metadata(
+        &mut self,
+        stream: &String,
+        metadata: &StreamMetadata<RedisVersion>,
+    ) -> Result<(), VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>> {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        Self::ensure_not_deleted(&mut conn, stream)
+            .await
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        Self::write_metadata(&mut conn, stream, metadata)
+            .await
+            .map_err(VersionedRepositoryError::RepoErr)
+    }
+}
+
+/// Sub streams only exist as ids on the entries of the shared stream, so listing them reads the whole stream
+#[async_trait]
+impl<'a, E, SM, DTO, DTOErr> StreamListingRepository<'a, E, RedisRepositoryError<DTOErr>>
+    for RedisStreamsEventRepository<SM, DTO>
+where
+    E: Event
+        + Sync
+        + Send
+        + Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + StreamModelDTO<SM, DTOErr>,
+    SM: StreamModel<Data = DTO> + Send + Sync,
+    DTO: WithFineGrainedStreamId + Clone + Send + Sync + redis_om::FromRedisValue,
+    DTOErr: Debug + Error + Send + Sync + Clone,
+{
+    async fn list_streams(
+        &self,
+    ) -> Result<Vec<String>, VersionedRepositoryError<RedisRepositoryError<DTOErr>, RedisVersion>>
+    {
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let rv = <SM as StreamModel>::range("-".to_string(), "+".to_string(), &mut conn)
+            .await
+            .map_err(RedisRepositoryError::ReadError)
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        let mut newest = HashMap::new();
+        let mut ids = vec![];
+
+        for raw_event in rv {
+            let id = raw_event
+                .data::<DTO>()
+                .map_err(RedisRepositoryError::ParseDTO)
+                .map_err(VersionedRepositoryError::RepoErr)?
+                .to_fine_grained_id();
+            let version = RedisVersion::try_from(raw_event.id.as_ref())
+                .map_err(RedisRepositoryError::Version)
+                .map_err(VersionedRepositoryError::RepoErr)?;
+
+            if newest.insert(id.to_owned(), version).is_none() {
+                ids.push(id);
+            }
+        }
+
+        // Hard deleted and fully soft deleted sub streams hold no events anymore
+        let mut listed = vec![];
+        for id in ids {
+            if Self::is_deleted(&mut conn, &id)
+                .await
+                .map_err(RedisRepositoryError::ReadError)
+                .map_err(VersionedRepositoryError::RepoErr)?
+            {
+                continue;
+            }
+
+            let hidden_through = Self::soft_deleted_through(&mut conn, &id)
+                .await
+                .map_err(VersionedRepositoryError::RepoErr)?;
+
+            if hidden_through.is_none_or(|through| newest[&id] > through) {
+                listed.push(id);
+            }
+        }
+
+        Ok(listed)
+    }
+}
+
+/// A redis repository is backed by a single stream, so the stream key is its only category and entry ids
+/// give the global order
+#[async_trait]
+impl<E, SM, DTO, DTOErr> AllEventsRepository<E, RedisRepositoryError<DTOErr>>
+    for RedisStreamsEventRepository<SM, DTO>
+where
+    E: Event
+        + Sync
+        + Send
+        + Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + StreamModelDTO<SM, DTOErr>,
+    SM: StreamModel<Data = DTO> + Send + Sync,
+    DTO: WithFineGrainedStreamId + Clone + Send + Sync + redis_om::FromRedisValue,
+    DTOErr: Debug + Error + Send + Sync + Clone,
+{
+    type Position = RedisVersion;
+
+    async fn load_all(
+        &self,
+        from: Option<&RedisVersion>,
+        filter: &AllEventsFilter,
+        max_count: Option<usize>,
+    ) -> Result<Vec<GlobalEvent<E, RedisVersion>>, RedisRepositoryError<DTOErr>> {
+        let category = SM::stream_key().to_string();
+
+        if !filter.matches_category(&category) {
+            return Ok(vec![]);
+        }
+
+        let mut conn = self
+            .get_connection()
+            .await
+            .map_err(RedisRepositoryError::ConnectionError)?;
+
+        let max_count = max_count.unwrap_or(usize::MAX);
+        let page_size = max_count.min(READ_PAGE_SIZE);
+
+        // Exclusive range start - requires redis 6.2+
+        let mut start = from.map_or("-".to_string(), |v| format!("({}", v.to_string()));
+        let mut evts = vec![];
+
+        // Type filtering happens client side, so pages are read until enough events matched
+        while evts.len() < max_count {
+            let page = <SM as StreamModel>::range_count(start, "+", page_size, &mut conn)
+                .await
+                .map_err(RedisRepositoryError::ReadError)?;
+            let full = page.len() == page_size;
+
+            start = match page.last() {
+                Some(last) => format!("({}", last.id),
+                None => break,
+            };
+
+            for raw_event in page {
+                if evts.len() >= max_count {
+                    break;
+                }
+
+                let dto = raw_event
+                    .data::<DTO>()
+                    .map_err(RedisRepositoryError::ParseDTO)?;
+                let stream = dto.to_fine_grained_id();
+
+                let event = E::try_from_dto(dto).map_err(RedisRepositoryError::FromDTO)?;
+
+                if !filter.matches_event_type(&event.event_type()) {
+                    continue;
+                }
+
+                evts.push(GlobalEvent {
+                    position: RedisVersion::try_from(raw_event.id.as_ref())
+                        .map_err(RedisRepositoryError::Version)?,
+                    stream,
+                    category: category.to_owned(),
+                    event,
+                });
+            }
+
+            if !full {
+                break;
+            }
+        }
+
+        Ok(evts)
     }
 }
 
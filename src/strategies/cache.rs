@@ -0,0 +1,127 @@
+use std::{
+    fmt::Debug,
+    hash::Hash,
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+
+use crate::{
+    decider::Evolver,
+    repository::{event::StreamingEventRepository, RepositoryVersion, VersionedRepositoryError},
+};
+
+use super::catch_up;
+
+#[derive(Debug)]
+struct CachedState<State, V> {
+    state: State,
+    version: RepositoryVersion<V>,
+    touched: Instant,
+}
+
+/// Rehydrated states by stream id, so hot aggregates only fold the events appended since they were cached
+///
+/// Holds at most `capacity` streams, evicting the least recently used one when full. With `time_to_idle` set,
+/// streams that were not used for that long are dropped as well
+#[derive(Debug)]
+pub struct StateCache<StreamId: Hash + Eq, State, V> {
+    entries: Mutex<LruCache<StreamId, CachedState<State, V>>>,
+    time_to_idle: Option<Duration>,
+}
+
+impl<StreamId, State, V> StateCache<StreamId, State, V>
+where
+    StreamId: Hash + Eq,
+    State: Clone,
+    V: Clone,
+{
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            time_to_idle: None,
+        }
+    }
+
+    pub fn with_time_to_idle(mut self, time_to_idle: Duration) -> Self {
+        self.time_to_idle = Some(time_to_idle);
+        self
+    }
+
+    pub fn get(&self, stream_id: &StreamId) -> Option<(State, RepositoryVersion<V>)> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let idle = match (entries.peek(stream_id), self.time_to_idle) {
+            (Some(cached), Some(time_to_idle)) => cached.touched.elapsed() > time_to_idle,
+            _ => false,
+        };
+
+        if idle {
+            entries.pop(stream_id);
+            return None;
+        }
+
+        entries.get_mut(stream_id).map(|cached| {
+            cached.touched = Instant::now();
+            (cached.state.to_owned(), cached.version.to_owned())
+        })
+    }
+
+    pub fn put(&self, stream_id: StreamId, state: State, version: RepositoryVersion<V>) {
+        self.entries.lock().unwrap().put(
+            stream_id,
+            CachedState {
+                state,
+                version,
+                touched: Instant::now(),
+            },
+        );
+    }
+
+    pub fn invalidate(&self, stream_id: &StreamId) {
+        self.entries.lock().unwrap().pop(stream_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Loads a stream's state, folding only the events after the cached version on a hit
+pub(crate) async fn hydrate<'a, Ev, RepoErr, StreamId, V>(
+    initial: Ev::State,
+    event_repository: &(impl StreamingEventRepository<'a, Ev::Evt, RepoErr, StreamId = StreamId, Version = V>
+          + Send
+          + Sync),
+    cache: &StateCache<StreamId, Ev::State, V>,
+    stream_id: &StreamId,
+) -> Result<(Ev::State, RepositoryVersion<V>), VersionedRepositoryError<RepoErr, V>>
+where
+    Ev: Evolver,
+    Ev::Evt: Send + Sync + Debug,
+    Ev::State: Clone,
+    RepoErr: Debug + Send + Sync,
+    StreamId: Hash + Eq + Clone + Send + Sync,
+    V: Clone + Ord + Send + Sync,
+{
+    match cache.get(stream_id) {
+        Some((state, version @ RepositoryVersion::Exact(_))) => {
+            catch_up::<Ev, _, _, _>(state, version, event_repository, stream_id).await
+        }
+        _ => {
+            catch_up::<Ev, _, _, _>(
+                initial,
+                RepositoryVersion::NoStream,
+                event_repository,
+                stream_id,
+            )
+            .await
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use std::{
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::{
+    future::{self, BoxFuture, Either},
+    FutureExt,
+};
+
+#[derive(Debug)]
+pub enum MiddlewareError<E> {
+    Invalid(String),
+    Unauthorized(String),
+    TimedOut(Duration),
+    Strategy(E),
+}
+
+pub type MiddlewareResult<T, E> = Result<T, MiddlewareError<E>>;
+
+/// A cross-cutting step wrapped around strategy execution, in the spirit of tower layers
+///
+/// Implementations receive ownership of the context and command and either short-circuit with a
+/// `MiddlewareError` or hand them (possibly modified) to `next`
+#[async_trait]
+pub trait Middleware<Ctx, Cmd, T, E>: Send + Sync
+where
+    Ctx: Send,
+    Cmd: Send,
+    T: Send,
+    E: Send,
+{
+    async fn call(
+        &self,
+        ctx: Ctx,
+        cmd: Cmd,
+        next: Next<'_, Ctx, Cmd, T, E>,
+    ) -> MiddlewareResult<T, E>;
+}
+
+type Endpoint<'a, Ctx, Cmd, T, E> =
+    Box<dyn FnOnce(Ctx, Cmd) -> BoxFuture<'a, Result<T, E>> + Send + 'a>;
+
+pub struct Next<'a, Ctx, Cmd, T, E> {
+    middleware: &'a [Box<dyn Middleware<Ctx, Cmd, T, E>>],
+    endpoint: Endpoint<'a, Ctx, Cmd, T, E>,
+}
+
+impl<'a, Ctx, Cmd, T, E> Next<'a, Ctx, Cmd, T, E>
+where
+    Ctx: Send,
+    Cmd: Send,
+    T: Send,
+    E: Send,
+{
+    pub async fn run(self, ctx: Ctx, cmd: Cmd) -> MiddlewareResult<T, E> {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => {
+                middleware
+                    .call(
+                        ctx,
+                        cmd,
+                        Next {
+                            middleware: rest,
+                            endpoint: self.endpoint,
+                        },
+                    )
+                    .await
+            }
+            None => (self.endpoint)(ctx, cmd)
+                .await
+                .map_err(MiddlewareError::Strategy),
+        }
+    }
+}
+
+/// Ordered middleware stack - the first layer added is the outermost
+///
+/// The endpoint is usually a strategy call such as `LoadDecideAppend::execute` or
+/// `ReifyDecideSave::execute_reify_decide`
+pub struct Pipeline<Ctx, Cmd, T, E> {
+    middleware: Vec<Box<dyn Middleware<Ctx, Cmd, T, E>>>,
+}
+
+impl<Ctx, Cmd, T, E> Default for Pipeline<Ctx, Cmd, T, E> {
+    fn default() -> Self {
+        Self { middleware: vec![] }
+    }
+}
+
+impl<Ctx, Cmd, T, E> Pipeline<Ctx, Cmd, T, E>
+where
+    Ctx: Send,
+    Cmd: Send,
+    T: Send,
+    E: Send,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn layer(mut self, middleware: impl Middleware<Ctx, Cmd, T, E> + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    pub async fn run<'a>(
+        &'a self,
+        ctx: Ctx,
+        cmd: Cmd,
+        endpoint: impl FnOnce(Ctx, Cmd) -> BoxFuture<'a, Result<T, E>> + Send + 'a,
+    ) -> MiddlewareResult<T, E> {
+        Next {
+            middleware: &self.middleware,
+            endpoint: Box::new(endpoint),
+        }
+        .run(ctx, cmd)
+        .await
+    }
+}
+
+/// Rejects commands with `MiddlewareError::Invalid` before they reach the decider
+pub struct Validate<F>(pub F);
+
+#[async_trait]
+impl<Ctx, Cmd, T, E, F> Middleware<Ctx, Cmd, T, E> for Validate<F>
+where
+    Ctx: Send + Sync,
+    Cmd: Send + Sync,
+    T: Send,
+    E: Send,
+    F: Fn(&Ctx, &Cmd) -> Result<(), String> + Send + Sync,
+{
+    async fn call(
+        &self,
+        ctx: Ctx,
+        cmd: Cmd,
+        next: Next<'_, Ctx, Cmd, T, E>,
+    ) -> MiddlewareResult<T, E> {
+        (self.0)(&ctx, &cmd).map_err(MiddlewareError::Invalid)?;
+        next.run(ctx, cmd).await
+    }
+}
+
+/// Rejects commands with `MiddlewareError::Unauthorized` based on the `Ctx` (ie. the calling principal)
+pub struct Authorize<F>(pub F);
+
+#[async_trait]
+impl<Ctx, Cmd, T, E, F> Middleware<Ctx, Cmd, T, E> for Authorize<F>
+where
+    Ctx: Send + Sync,
+    Cmd: Send + Sync,
+    T: Send,
+    E: Send,
+    F: Fn(&Ctx, &Cmd) -> Result<(), String> + Send + Sync,
+{
+    async fn call(
+        &self,
+        ctx: Ctx,
+        cmd: Cmd,
+        next: Next<'_, Ctx, Cmd, T, E>,
+    ) -> MiddlewareResult<T, E> {
+        (self.0)(&ctx, &cmd).map_err(MiddlewareError::Unauthorized)?;
+        next.run(ctx, cmd).await
+    }
+}
+
+/// Rewrites the context before it reaches inner layers - ie. to inject a correlation id
+pub struct MapContext<F>(pub F);
+
+#[async_trait]
+impl<Ctx, Cmd, T, E, F> Middleware<Ctx, Cmd, T, E> for MapContext<F>
+where
+    Ctx: Send,
+    Cmd: Send + Sync,
+    T: Send,
+    E: Send,
+    F: Fn(Ctx, &Cmd) -> Ctx + Send + Sync,
+{
+    async fn call(
+        &self,
+        ctx: Ctx,
+        cmd: Cmd,
+        next: Next<'_, Ctx, Cmd, T, E>,
+    ) -> MiddlewareResult<T, E> {
+        let ctx = (self.0)(ctx, &cmd);
+        next.run(ctx, cmd).await
+    }
+}
+
+/// Fails with `MiddlewareError::TimedOut` if inner layers take longer than `duration`
+///
+/// Epoch is runtime agnostic so the caller supplies the sleep future, ie. `actix_rt::time::sleep`
+pub struct Timeout {
+    duration: Duration,
+    sleep: Box<dyn Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync>,
+}
+
+impl Timeout {
+    pub fn new(
+        duration: Duration,
+        sleep: impl Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            duration,
+            sleep: Box::new(sleep),
+        }
+    }
+}
+
+#[async_trait]
+impl<Ctx, Cmd, T, E> Middleware<Ctx, Cmd, T, E> for Timeout
+where
+    Ctx: Send,
+    Cmd: Send,
+    T: Send,
+    E: Send,
+{
+    async fn call(
+        &self,
+        ctx: Ctx,
+        cmd: Cmd,
+        next: Next<'_, Ctx, Cmd, T, E>,
+    ) -> MiddlewareResult<T, E> {
+        match future::select(next.run(ctx, cmd).boxed(), (self.sleep)(self.duration)).await {
+            Either::Left((res, _)) => res,
+            Either::Right(_) => Err(MiddlewareError::TimedOut(self.duration)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditRecord<'a, T, E> {
+    pub command: String,
+    pub elapsed: Duration,
+    pub outcome: &'a MiddlewareResult<T, E>,
+}
+
+/// Reports every command with its outcome and timing once inner layers complete
+pub struct Audit<F>(pub F);
+
+#[async_trait]
+impl<Ctx, Cmd, T, E, F> Middleware<Ctx, Cmd, T, E> for Audit<F>
+where
+    Ctx: Send,
+    Cmd: Send + Debug,
+    T: Send + Sync,
+    E: Send + Sync,
+    F: Fn(&AuditRecord<'_, T, E>) + Send + Sync,
+{
+    async fn call(
+        &self,
+        ctx: Ctx,
+        cmd: Cmd,
+        next: Next<'_, Ctx, Cmd, T, E>,
+    ) -> MiddlewareResult<T, E> {
+        let command = format!("{:?}", &cmd);
+        let started = Instant::now();
+
+        let outcome = next.run(ctx, cmd).await;
+
+        (self.0)(&AuditRecord {
+            command,
+            elapsed: started.elapsed(),
+            outcome: &outcome,
+        });
+
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    use assert_matches::assert_matches;
+
+    use crate::{
+        repository::{
+            event::VersionedEventRepositoryWithStreams,
+            in_memory::{
+                self, state::versioned::InMemoryStateRepository,
+                versioned_with_streams::InMemoryEventRepository,
+            },
+        },
+        strategies::{LoadDecideAppend, LoadDecideAppendError, ReifyDecideSave, StreamState},
+        test_helpers::deciders::user::{
+            UserCommand, UserDecider, UserDeciderCtx, UserDeciderError, UserDeciderState, UserEvent,
+        },
+    };
+
+    use super::*;
+
+    fn non_empty_name(_ctx: &UserDeciderCtx, cmd: &UserCommand) -> Result<(), String> {
+        match cmd {
+            UserCommand::AddUser(name) | UserCommand::UpdateUserName(_, name)
+                if name.is_empty() =>
+            {
+                Err("Name is required".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn pipeline_around_load_decide_append() {
+        let audited = Arc::new(Mutex::new(vec![]));
+        let audit_log = audited.clone();
+
+        let pipeline = Pipeline::new()
+            .layer(Audit(move |record: &AuditRecord<'_, _, _>| {
+                audit_log
+                    .lock()
+                    .unwrap()
+                    .push((record.command.clone(), record.outcome.is_ok()))
+            }))
+            .layer(Validate(non_empty_name));
+
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new("test");
+        let repository = &mut event_repository;
+
+        let res = pipeline
+            .run(
+                UserDeciderCtx::new(),
+                UserCommand::AddUser("".to_string()),
+                |ctx, cmd| {
+                    async move {
+                        UserDecider::execute(
+                            UserDeciderState::default(),
+                            repository,
+                            &StreamState::New,
+                            &ctx,
+                            &cmd,
+                            None,
+                        )
+                        .await
+                    }
+                    .boxed()
+                },
+            )
+            .await;
+
+        assert_matches!(res, Err(MiddlewareError::Invalid(_)));
+
+        let (evts, _) = event_repository.load(None).await.unwrap();
+        assert!(evts.is_empty());
+
+        let repository = &mut event_repository;
+        let res = pipeline
+            .run(
+                UserDeciderCtx::new(),
+                UserCommand::AddUser("Mike".to_string()),
+                |ctx, cmd| {
+                    async move {
+                        UserDecider::execute(
+                            UserDeciderState::default(),
+                            repository,
+                            &StreamState::New,
+                            &ctx,
+                            &cmd,
+                            None,
+                        )
+                        .await
+                    }
+                    .boxed()
+                },
+            )
+            .await;
+
+        assert_matches!(res, Ok(evts) if evts.len() == 1);
+        assert_eq!(
+            *audited.lock().unwrap(),
+            vec![
+                (format!("{:?}", UserCommand::AddUser("".to_string())), false),
+                (
+                    format!("{:?}", UserCommand::AddUser("Mike".to_string())),
+                    true
+                )
+            ]
+        );
+    }
+
+    /// Calling principal next to the decider context
+    type PrincipalCtx = (String, UserDeciderCtx);
+    type AddUserError =
+        LoadDecideAppendError<UserDeciderError, in_memory::versioned_with_streams::error::Error>;
+
+    fn admins_only(ctx: &PrincipalCtx, _cmd: &UserCommand) -> Result<(), String> {
+        match ctx.0.as_str() {
+            "admin" => Ok(()),
+            principal => Err(format!("{} is not an admin", principal)),
+        }
+    }
+
+    async fn add_user_as(
+        pipeline: &Pipeline<PrincipalCtx, UserCommand, Vec<UserEvent>, AddUserError>,
+        principal: &str,
+        event_repository: &mut InMemoryEventRepository<UserEvent>,
+        seen: &Mutex<Vec<String>>,
+    ) -> MiddlewareResult<Vec<UserEvent>, AddUserError> {
+        pipeline
+            .run(
+                (principal.to_string(), UserDeciderCtx::new()),
+                UserCommand::AddUser("Mike".to_string()),
+                |ctx, cmd| {
+                    async move {
+                        seen.lock().unwrap().push(ctx.0.to_owned());
+
+                        UserDecider::execute(
+                            UserDeciderState::default(),
+                            event_repository,
+                            &StreamState::New,
+                            &ctx.1,
+                            &cmd,
+                            None,
+                        )
+                        .await
+                    }
+                    .boxed()
+                },
+            )
+            .await
+    }
+
+    #[actix_rt::test]
+    async fn authorize_short_circuits_before_the_decider() {
+        let pipeline = Pipeline::new().layer(Authorize(admins_only));
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new("test");
+        let seen = Mutex::new(vec![]);
+
+        let res = add_user_as(&pipeline, "guest", &mut event_repository, &seen).await;
+
+        assert_matches!(res, Err(MiddlewareError::Unauthorized(reason)) if reason == "guest is not an admin");
+        assert!(seen.lock().unwrap().is_empty());
+        let (evts, _) = event_repository.load(None).await.unwrap();
+        assert!(evts.is_empty());
+
+        let res = add_user_as(&pipeline, "admin", &mut event_repository, &seen).await;
+
+        assert_matches!(res, Ok(evts) if evts.len() == 1);
+        assert_eq!(*seen.lock().unwrap(), vec!["admin".to_string()]);
+        let (evts, _) = event_repository.load(None).await.unwrap();
+        assert_eq!(evts.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn map_context_rewrites_the_context_of_inner_layers() {
+        let mapped = Arc::new(AtomicBool::new(false));
+        let mapped_in_layer = mapped.clone();
+
+        let pipeline = Pipeline::new()
+            .layer(MapContext(move |ctx: PrincipalCtx, _cmd: &UserCommand| {
+                mapped_in_layer.store(true, Ordering::SeqCst);
+                ("admin".to_string(), ctx.1)
+            }))
+            .layer(Authorize(admins_only));
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new("test");
+        let seen = Mutex::new(vec![]);
+
+        let res = add_user_as(&pipeline, "guest", &mut event_repository, &seen).await;
+
+        assert_matches!(res, Ok(evts) if evts.len() == 1);
+        assert!(mapped.load(Ordering::SeqCst));
+        assert_eq!(*seen.lock().unwrap(), vec!["admin".to_string()]);
+    }
+
+    #[actix_rt::test]
+    async fn pipeline_around_reify_decide_save() {
+        let pipeline = Pipeline::new()
+            .layer(MapContext(|_ctx, _cmd: &UserCommand| UserDeciderCtx::new()))
+            .layer(Authorize(
+                |_ctx: &UserDeciderCtx, cmd: &UserCommand| match cmd {
+                    UserCommand::AddGuitar(_, _) => Err("Guitars are admin only".to_string()),
+                    _ => Ok(()),
+                },
+            ));
+
+        let mut state_repository =
+            InMemoryStateRepository::<UserDeciderState>::new(UserDeciderState::default());
+        let repository = &mut state_repository;
+
+        let res = pipeline
+            .run(
+                UserDeciderCtx::new(),
+                UserCommand::AddUser("Mike".to_string()),
+                |ctx, cmd| {
+                    async move {
+                            UserDecider::execute_reify_decide(repository, &ctx, &cmd, None).await
+                        }
+                        .boxed()
+                },
+            )
+            .await;
+
+        assert_matches!(res, Ok(state) if state.users.len() == 1);
+    }
+
+    #[actix_rt::test]
+    async fn timeout_short_circuits_slow_strategies() {
+        let pipeline: Pipeline<(), (), (), ()> = Pipeline::new()
+            .layer(Timeout::new(Duration::from_millis(10), |d| {
+                actix_rt::time::sleep(d).boxed()
+            }));
+
+        let res = pipeline
+            .run((), (), |_, _| {
+                actix_rt::time::sleep(Duration::from_secs(1))
+                    .map(Ok)
+                    .boxed()
+            })
+            .await;
+
+        assert_matches!(res, Err(MiddlewareError::TimedOut(_)));
+    }
+}
@@ -1,15 +1,30 @@
-use core::time;
-use std::{fmt::Debug, thread};
+use std::{
+    fmt::Debug,
+    hash::Hash,
+    time::{Duration, SystemTime},
+};
 
 use crate::{
-    decider::{DeciderWithContext, Evolver},
+    decider::{AsyncDeciderWithContext, DeciderWithContext, Event, Evolver, IdempotentCommand},
     repository::{
-        self, state::VersionedStateRepository, RepositoryVersion, StreamIdFromEvent,
-        VersionedRepositoryError,
+        self, idempotency::IdempotentEventRepository, state::VersionedStateRepository,
+        RepositoryVersion, StreamIdFromEvent, VersionedRepositoryError,
     },
 };
 use async_trait::async_trait;
-use repository::event::VersionedEventRepositoryWithStreams;
+use cache::StateCache;
+use futures::{
+    future::{ready, BoxFuture},
+    stream::BoxStream,
+    FutureExt, StreamExt, TryStreamExt,
+};
+use futures_timer::Delay;
+use repository::event::{
+    EventStream, RecordedEvent, StreamingEventRepository, VersionedEventRepositoryWithStreams,
+};
+
+pub mod cache;
+pub mod middleware;
 
 #[async_trait]
 pub trait StateFromEventRepository
@@ -19,26 +34,28 @@ where
 {
     type Ev: Evolver + Send + Sync;
 
+    /// Folds the category stream as it is read so the events are never all held in memory
     async fn load<'a, Err, V>(
         initial: <Self::Ev as Evolver>::State,
-        event_repository: &(impl VersionedEventRepositoryWithStreams<'a, <Self::Ev as Evolver>::Evt, Err, Version = V>
+        event_repository: &(impl StreamingEventRepository<'a, <Self::Ev as Evolver>::Evt, Err, Version = V>
               + Send
               + Sync),
     ) -> Result<<Self::Ev as Evolver>::State, VersionedRepositoryError<Err, V>>
     where
         Err: Debug + Send + Sync,
+        V: Send + Sync,
     {
-        Ok(event_repository
-            .load(None)
-            .await?
-            .0
-            .iter()
-            .fold(initial, Self::Ev::evolve))
+        event_repository
+            .stream(None)
+            .try_fold(initial, |state, recorded| {
+                ready(Ok(Self::Ev::evolve(state, &recorded.event)))
+            })
+            .await
     }
 
     async fn load_by_id<'a, Err, StreamId, Version>(
         initial: <Self::Ev as Evolver>::State,
-        event_repository: &(impl VersionedEventRepositoryWithStreams<
+        event_repository: &(impl StreamingEventRepository<
             'a,
             <Self::Ev as Evolver>::Evt,
             Err,
@@ -50,15 +67,134 @@ where
     ) -> Result<<Self::Ev as Evolver>::State, VersionedRepositoryError<Err, Version>>
     where
         Err: Debug + Send + Sync,
-        StreamId: Send + Sync,
+        StreamId: Clone + Send + Sync,
+        Version: Send + Sync,
     {
-        Ok(event_repository
-            .load(Some(stream_id))
-            .await?
-            .0
-            .iter()
-            .fold(initial, Self::Ev::evolve))
+        event_repository
+            .stream(Some(stream_id.to_owned()))
+            .try_fold(initial, |state, recorded| {
+                ready(Ok(Self::Ev::evolve(state, &recorded.event)))
+            })
+            .await
+    }
+
+    /// Rebuilds state as it was at a point in the stream's history, with the version it was rebuilt to
+    ///
+    /// Reading stops at the first event past `as_of`, so older points cost less to rebuild. `None` rebuilds
+    /// the whole category
+    async fn load_as_of<'a, Err, StreamId, V>(
+        initial: <Self::Ev as Evolver>::State,
+        event_repository: &(impl StreamingEventRepository<
+            'a,
+            <Self::Ev as Evolver>::Evt,
+            Err,
+            StreamId = StreamId,
+            Version = V,
+        > + Send
+              + Sync),
+        stream_id: Option<&StreamId>,
+        as_of: &AsOf<V>,
+    ) -> Result<
+        (<Self::Ev as Evolver>::State, RepositoryVersion<V>),
+        VersionedRepositoryError<Err, V>,
+    >
+    where
+        Err: Debug + Send + Sync,
+        StreamId: Clone + Send + Sync,
+        V: Clone + Ord + Send + Sync,
+    {
+        if let AsOf::Version(RepositoryVersion::NoStream) = as_of {
+            return Ok((initial, RepositoryVersion::NoStream));
+        }
+
+        event_repository
+            .stream(stream_id.cloned())
+            .try_take_while(|recorded| ready(Ok(as_of.includes(recorded))))
+            .try_fold(
+                (initial, RepositoryVersion::NoStream),
+                |(state, _), recorded| {
+                    ready(Ok((
+                        Self::Ev::evolve(state, &recorded.event),
+                        RepositoryVersion::Exact(recorded.version),
+                    )))
+                },
+            )
+            .await
     }
+
+    /// Steps through a stream one event at a time, yielding each event with the state right after it
+    #[allow(clippy::type_complexity)]
+    fn steps<'s, 'a, Err, StreamId, V>(
+        initial: <Self::Ev as Evolver>::State,
+        event_repository: &'s (impl StreamingEventRepository<
+            'a,
+            <Self::Ev as Evolver>::Evt,
+            Err,
+            StreamId = StreamId,
+            Version = V,
+        > + Send
+                 + Sync),
+        stream_id: Option<StreamId>,
+    ) -> BoxStream<'s, Result<Step<Self::Ev, V>, VersionedRepositoryError<Err, V>>>
+    where
+        Self::Ev: 's,
+        <Self::Ev as Evolver>::State: Clone + 's,
+        <Self::Ev as Evolver>::Evt: 's,
+        Err: Debug + Send + Sync + 's,
+        V: Send + Sync + 's,
+    {
+        event_repository
+            .stream(stream_id)
+            .scan(initial, |state, recorded| {
+                ready(Some(recorded.map(|recorded| {
+                    *state = Self::Ev::evolve(state.to_owned(), &recorded.event);
+
+                    Step {
+                        event: recorded.event,
+                        version: recorded.version,
+                        recorded_at: recorded.recorded_at,
+                        state: state.to_owned(),
+                    }
+                })))
+            })
+            .boxed()
+    }
+}
+
+/// Point in a stream's history to rebuild state at
+///
+/// Any version other than `Exact` and `NoStream` means the latest state. Events a backend recorded no time for
+/// are taken to be older than any `Time`
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsOf<V> {
+    /// Up to and including the event at this version
+    Version(RepositoryVersion<V>),
+    /// Events recorded at or before this time
+    Time(SystemTime),
+}
+
+impl<V: Ord> AsOf<V> {
+    fn includes<E>(&self, recorded: &RecordedEvent<E, V>) -> bool {
+        match self {
+            AsOf::Version(RepositoryVersion::Exact(version)) => &recorded.version <= version,
+            AsOf::Version(RepositoryVersion::NoStream) => false,
+            AsOf::Version(_) => true,
+            AsOf::Time(time) => match &recorded.recorded_at {
+                Some(at) => at <= time,
+                // Backends without timestamps can't tell, so the event counts as already recorded
+                None => true,
+            },
+        }
+    }
+}
+
+/// One event of a stream and the state right after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step<Ev: Evolver, V> {
+    pub event: Ev::Evt,
+    pub version: V,
+    pub recorded_at: Option<SystemTime>,
+    pub state: Ev::State,
 }
 
 #[async_trait]
@@ -75,19 +211,17 @@ where
     fn to_lda_error<DecErr: Send + Sync, RepoErr: Send + Sync, Version: Send + Sync>(
         err: VersionedRepositoryError<RepoErr, Version>,
     ) -> LoadDecideAppendError<DecErr, RepoErr> {
-        match err {
-            VersionedRepositoryError::VersionConflict(_) => LoadDecideAppendError::VersionError,
-            VersionedRepositoryError::RepoErr(e) => LoadDecideAppendError::RepositoryErr(e),
-        }
+        to_lda_error(err)
     }
 
-    async fn execute<'a, RepoErr, StreamId>(
+    async fn execute<'a, RepoErr, StreamId, V>(
         initial: <Self::Decide as Evolver>::State,
-        event_repository: &mut (impl VersionedEventRepositoryWithStreams<
+        event_repository: &mut (impl StreamingEventRepository<
             'a,
             <Self::Decide as Evolver>::Evt,
             RepoErr,
             StreamId = StreamId,
+            Version = V,
         > + Send
                   + Sync),
         stream_id: &StreamState<StreamId>,
@@ -104,56 +238,175 @@ where
             + Sync
             + Clone
             + StreamIdFromEvent<<<Self as LoadDecideAppend>::Decide as Evolver>::Evt>,
+        V: Clone + Ord + Send + Sync,
     {
-        let (mut decider_evts, mut version) = match stream_id {
-            StreamState::New => (vec![], RepositoryVersion::NoStream),
-            StreamState::Existing(sid) => event_repository
-                .load(Some(sid))
-                .await
-                .map_err(Self::to_lda_error)?,
+        let hydrated = match stream_id {
+            StreamState::New => (initial, RepositoryVersion::NoStream),
+            StreamState::Existing(sid) => catch_up::<Self::Decide, _, _, _>(
+                initial,
+                RepositoryVersion::NoStream,
+                event_repository,
+                sid,
+            )
+            .await
+            .map_err(Self::to_lda_error)?,
         };
 
-        let mut state = initial;
+        let (evts, _, _) = decide_append::<Self::Decide, _, _, _, _>(
+            hydrated,
+            event_repository,
+            stream_id,
+            |state| {
+                let evts = <Self::Decide as DeciderWithContext>::decide(ctx, &state, cmd);
+                ready((state, evts)).boxed()
+            },
+            retrys,
+        )
+        .await?;
+
+        Ok(evts)
+    }
 
-        for r in 1..retrys.unwrap_or(20) {
-            state = decider_evts
-                .iter()
-                .fold(state, <Self::Decide as Evolver>::evolve);
+    /// Same as `execute` but the command's idempotency key is checked by the append itself, so a command
+    /// retried with a key already used on its stream returns the events appended under it and nothing is
+    /// appended twice - not even when the retries race each other
+    ///
+    /// Keys are scoped per stream, so a retried command creating a new stream is only recognised when the
+    /// decider derives the stream id from the command
+    async fn execute_idempotent<'a, RepoErr, StreamId, V>(
+        initial: <Self::Decide as Evolver>::State,
+        event_repository: &mut (impl IdempotentEventRepository<
+            'a,
+            <Self::Decide as Evolver>::Evt,
+            RepoErr,
+            StreamId = StreamId,
+            Version = V,
+        > + Send
+                  + Sync),
+        stream_id: &StreamState<StreamId>,
+        ctx: &<<Self as LoadDecideAppend>::Decide as DeciderWithContext>::Ctx,
+        cmd: &<<Self as LoadDecideAppend>::Decide as DeciderWithContext>::Cmd,
+        retrys: Option<u32>,
+    ) -> Result<
+        Vec<<Self::Decide as Evolver>::Evt>,
+        LoadDecideAppendError<<Self::Decide as DeciderWithContext>::Err, RepoErr>,
+    >
+    where
+        RepoErr: Debug + Send + Sync,
+        StreamId: Send
+            + Sync
+            + Clone
+            + StreamIdFromEvent<<<Self as LoadDecideAppend>::Decide as Evolver>::Evt>,
+        V: Clone + Ord + Send + Sync,
+        <<Self as LoadDecideAppend>::Decide as DeciderWithContext>::Cmd: IdempotentCommand,
+    {
+        let key = match cmd.idempotency_key() {
+            Some(key) => key,
+            None => {
+                return Self::execute(initial, event_repository, stream_id, ctx, cmd, retrys).await
+            }
+        };
 
-            let new_evts = <Self::Decide as DeciderWithContext>::decide(ctx, &state, cmd)
-                .map_err(LoadDecideAppendError::DecideErr)?;
+        // Saves loading and deciding for plain retries, racing ones are caught by the append
+        if let StreamState::Existing(sid) = stream_id {
+            if let Some(evts) = event_repository
+                .processed(sid, &key)
+                .await
+                .map_err(Self::to_lda_error)?
+            {
+                return Ok(evts);
+            }
+        }
 
-            let stream = match stream_id {
-                StreamState::New => match new_evts.first() {
-                    None => {
-                        return Ok(vec![]);
-                    }
-                    Some(evt) => StreamId::from(evt.clone()),
-                },
-                StreamState::Existing(sid) => sid.clone(),
-            };
-
-            match event_repository.append(&version, &stream, &new_evts).await {
-                Ok((appended_evts, _)) => return Ok(appended_evts),
-                Err(VersionedRepositoryError::RepoErr(e)) => {
-                    println!("Max Retries for {:?}!!", &cmd);
-                    return Err(LoadDecideAppendError::RepositoryErr(e));
-                }
-                Err(VersionedRepositoryError::VersionConflict(_)) => {
-                    println!("RETRY #{} for {:?}!!", &r, &cmd);
-                    thread::sleep(time::Duration::new(0, 100000000 * r));
-                    let (mut catchup_evts, new_version) = event_repository
-                        .load_from_version(&version, Some(&stream))
-                        .await
-                        .map_err(Self::to_lda_error)?;
+        Self::execute(
+            initial,
+            &mut KeyedAppend {
+                inner: event_repository,
+                key: &key,
+            },
+            stream_id,
+            ctx,
+            cmd,
+            retrys,
+        )
+        .await
+    }
 
-                    version = new_version;
-                    decider_evts.append(&mut catchup_evts);
-                }
-            };
+    /// Same as `execute` but hydrates existing streams through `cache`, so only the events appended since the
+    /// state was cached are loaded and folded
+    ///
+    /// The state after a successful append is put back in the cache. A version conflict catches up on the events
+    /// appended since, and any error drops the stream from the cache so the next command loads it in full
+    async fn execute_with_cache<'a, RepoErr, StreamId, V>(
+        initial: <Self::Decide as Evolver>::State,
+        event_repository: &mut (impl StreamingEventRepository<
+            'a,
+            <Self::Decide as Evolver>::Evt,
+            RepoErr,
+            StreamId = StreamId,
+            Version = V,
+        > + Send
+                  + Sync),
+        cache: &StateCache<StreamId, <Self::Decide as Evolver>::State, V>,
+        stream_id: &StreamState<StreamId>,
+        ctx: &<<Self as LoadDecideAppend>::Decide as DeciderWithContext>::Ctx,
+        cmd: &<<Self as LoadDecideAppend>::Decide as DeciderWithContext>::Cmd,
+        retrys: Option<u32>,
+    ) -> Result<
+        Vec<<Self::Decide as Evolver>::Evt>,
+        LoadDecideAppendError<<Self::Decide as DeciderWithContext>::Err, RepoErr>,
+    >
+    where
+        RepoErr: Debug + Send + Sync,
+        StreamId: Send
+            + Sync
+            + Clone
+            + Hash
+            + Eq
+            + StreamIdFromEvent<<<Self as LoadDecideAppend>::Decide as Evolver>::Evt>,
+        V: Clone + Ord + Send + Sync,
+        <Self::Decide as Evolver>::State: Clone,
+    {
+        let hydrated = match stream_id {
+            StreamState::New => (initial, RepositoryVersion::NoStream),
+            StreamState::Existing(sid) => {
+                cache::hydrate::<Self::Decide, _, _, _>(initial, event_repository, cache, sid)
+                    .await
+                    .map_err(Self::to_lda_error)?
+            }
+        };
+
+        let res = decide_append::<Self::Decide, _, _, _, _>(
+            hydrated,
+            event_repository,
+            stream_id,
+            |state| {
+                let evts = <Self::Decide as DeciderWithContext>::decide(ctx, &state, cmd);
+                ready((state, evts)).boxed()
+            },
+            retrys,
+        )
+        .await;
+
+        let (evts, state, version) = match (res, stream_id) {
+            (Ok(appended), _) => appended,
+            (Err(e), StreamState::Existing(sid)) => {
+                cache.invalidate(sid);
+                return Err(e);
+            }
+            (Err(e), StreamState::New) => return Err(e),
+        };
+
+        let stream = match stream_id {
+            StreamState::New => evts.first().map(|evt| StreamId::from(evt.clone())),
+            StreamState::Existing(sid) => Some(sid.clone()),
+        };
+
+        if let Some(stream) = stream {
+            cache.put(stream, state, version);
         }
 
-        Err(LoadDecideAppendError::OccMaxRetries)
+        Ok(evts)
     }
 }
 
@@ -194,36 +447,125 @@ where
     where
         RepoErr: Send + Sync,
     {
-        let (mut state, mut version) = state_repository
-            .reify()
-            .await
-            .map_err(ReifyDecideSaveError::RepositoryErr)?;
+        reify_decide_save::<Self::Decide, _, _>(
+            state_repository,
+            |state| {
+                let evts = <Self::Decide as DeciderWithContext>::decide(ctx, &state, cmd);
+                ready((state, evts)).boxed()
+            },
+            retrys,
+        )
+        .await
+    }
+}
 
-        for r in 1..retrys.unwrap_or(20) {
-            let local_state = state.clone();
-            let evts = <Self::Decide as DeciderWithContext>::decide(ctx, &local_state, cmd)
-                .map_err(ReifyDecideSaveError::DecideErr)?;
+#[async_trait]
+pub trait AsyncLoadDecideAppend
+where
+    <Self::Decide as Evolver>::State: Send + Sync + Debug,
+    <Self::Decide as AsyncDeciderWithContext>::Ctx: Send + Sync + Debug,
+    <Self::Decide as AsyncDeciderWithContext>::Cmd: Send + Sync + Debug,
+    <Self::Decide as Evolver>::Evt: Clone + Send + Sync + Debug,
+    <Self::Decide as AsyncDeciderWithContext>::Err: Send + Sync + Debug,
+{
+    type Decide: AsyncDeciderWithContext + Send + Sync;
 
-            let new_state = evts
-                .iter()
-                .fold(local_state, <Self::Decide as Evolver>::evolve);
+    async fn execute_async<'a, RepoErr, StreamId, V>(
+        initial: <Self::Decide as Evolver>::State,
+        event_repository: &mut (impl StreamingEventRepository<
+            'a,
+            <Self::Decide as Evolver>::Evt,
+            RepoErr,
+            StreamId = StreamId,
+            Version = V,
+        > + Send
+                  + Sync),
+        stream_id: &StreamState<StreamId>,
+        ctx: &<<Self as AsyncLoadDecideAppend>::Decide as AsyncDeciderWithContext>::Ctx,
+        cmd: &<<Self as AsyncLoadDecideAppend>::Decide as AsyncDeciderWithContext>::Cmd,
+        retrys: Option<u32>,
+    ) -> Result<
+        Vec<<Self::Decide as Evolver>::Evt>,
+        LoadDecideAppendError<<Self::Decide as AsyncDeciderWithContext>::Err, RepoErr>,
+    >
+    where
+        RepoErr: Debug + Send + Sync,
+        StreamId: Send
+            + Sync
+            + Clone
+            + StreamIdFromEvent<<<Self as AsyncLoadDecideAppend>::Decide as Evolver>::Evt>,
+        V: Clone + Ord + Send + Sync,
+    {
+        let hydrated = match stream_id {
+            StreamState::New => (initial, RepositoryVersion::NoStream),
+            StreamState::Existing(sid) => catch_up::<Self::Decide, _, _, _>(
+                initial,
+                RepositoryVersion::NoStream,
+                event_repository,
+                sid,
+            )
+            .await
+            .map_err(to_lda_error)?,
+        };
 
-            match state_repository.save(&version, &new_state).await {
-                Ok(s) => return Ok(s),
-                Err(VersionedRepositoryError::RepoErr(e)) => {
-                    return Err(ReifyDecideSaveError::RepositoryErr(e))
-                }
-                Err(VersionedRepositoryError::VersionConflict(_)) => {
-                    println!("Retry #{} for {:?} - Reload State", &r, &cmd);
-                    (state, version) = state_repository
-                        .reify()
-                        .await
-                        .map_err(ReifyDecideSaveError::RepositoryErr)?;
+        let (evts, _, _) = decide_append::<Self::Decide, _, _, _, _>(
+            hydrated,
+            event_repository,
+            stream_id,
+            |state| {
+                async move {
+                    let evts =
+                        <Self::Decide as AsyncDeciderWithContext>::decide(ctx, &state, cmd).await;
+                    (state, evts)
                 }
-            }
-        }
+                .boxed()
+            },
+            retrys,
+        )
+        .await?;
 
-        Err(ReifyDecideSaveError::OccMaxRetries)
+        Ok(evts)
+    }
+}
+
+#[async_trait]
+pub trait AsyncReifyDecideSave
+where
+    <<Self as AsyncReifyDecideSave>::Decide as AsyncDeciderWithContext>::Ctx: Send + Sync,
+    <<Self as AsyncReifyDecideSave>::Decide as AsyncDeciderWithContext>::Cmd: Send + Sync + Debug,
+    <<Self as AsyncReifyDecideSave>::Decide as AsyncDeciderWithContext>::Err: Send + Sync,
+    <<Self as AsyncReifyDecideSave>::Decide as Evolver>::Evt: Send + Sync,
+    <<Self as AsyncReifyDecideSave>::Decide as Evolver>::State: Send + Sync + Clone,
+{
+    type Decide: AsyncDeciderWithContext + Send + Sync;
+
+    async fn execute_reify_decide_async<'a, RepoErr>(
+        state_repository: &mut (impl VersionedStateRepository<'a, <Self::Decide as Evolver>::State, RepoErr>
+                  + Send
+                  + Sync),
+        ctx: &<<Self as AsyncReifyDecideSave>::Decide as AsyncDeciderWithContext>::Ctx,
+        cmd: &<<Self as AsyncReifyDecideSave>::Decide as AsyncDeciderWithContext>::Cmd,
+        retrys: Option<u32>,
+    ) -> Result<
+        <Self::Decide as Evolver>::State,
+        ReifyDecideSaveError<<Self::Decide as AsyncDeciderWithContext>::Err, RepoErr>,
+    >
+    where
+        RepoErr: Send + Sync,
+    {
+        reify_decide_save::<Self::Decide, _, _>(
+            state_repository,
+            |state| {
+                async move {
+                    let evts =
+                        <Self::Decide as AsyncDeciderWithContext>::decide(ctx, &state, cmd).await;
+                    (state, evts)
+                }
+                .boxed()
+            },
+            retrys,
+        )
+        .await
     }
 }
 
@@ -266,6 +608,236 @@ where
     }
 }
 
+/// Routes the appends of the retry loop through `append_idempotent` under one key
+struct KeyedAppend<'r, R> {
+    inner: &'r mut R,
+    key: &'r str,
+}
+
+#[async_trait]
+impl<'a, 'r, R, E, Err> VersionedEventRepositoryWithStreams<'a, E, Err> for KeyedAppend<'r, R>
+where
+    R: IdempotentEventRepository<'a, E, Err> + Send + Sync,
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    type StreamId = R::StreamId;
+    type Version = R::Version;
+
+    async fn load(
+        &self,
+        id: Option<&Self::StreamId>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<Err, Self::Version>,
+    > {
+        self.inner.load(id).await
+    }
+
+    async fn load_from_version(
+        &self,
+        version: &RepositoryVersion<Self::Version>,
+        id: Option<&Self::StreamId>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<Err, Self::Version>,
+    > {
+        self.inner.load_from_version(version, id).await
+    }
+
+    async fn append(
+        &mut self,
+        version: &RepositoryVersion<Self::Version>,
+        stream: &Self::StreamId,
+        events: &Vec<E>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<Err, Self::Version>,
+    >
+    where
+        'a: 'async_trait,
+        E: 'async_trait,
+    {
+        self.inner
+            .append_idempotent(version, stream, events, self.key)
+            .await
+    }
+}
+
+impl<'a, 'r, R, E, Err> StreamingEventRepository<'a, E, Err> for KeyedAppend<'r, R>
+where
+    R: IdempotentEventRepository<'a, E, Err> + Send + Sync,
+    E: Event + Sync + Send + Debug,
+    Err: Debug + Send + Sync,
+{
+    fn stream_from_version(
+        &self,
+        version: RepositoryVersion<Self::Version>,
+        id: Option<Self::StreamId>,
+    ) -> EventStream<'_, E, Self::Version, Err> {
+        self.inner.stream_from_version(version, id)
+    }
+}
+
+/// Decides on the hydrated state and appends the events, catching up on the stream and deciding again after
+/// every version conflict
+///
+/// `decide` hands the state back with its events so it can be folded further without a clone. Returns the
+/// appended events with the state and version they lead to
+async fn decide_append<'a, 'c, Ev, DecErr, RepoErr, StreamId, V>(
+    (mut state, mut version): (Ev::State, RepositoryVersion<V>),
+    event_repository: &mut (impl StreamingEventRepository<'a, Ev::Evt, RepoErr, StreamId = StreamId, Version = V>
+              + Send
+              + Sync),
+    stream_id: &StreamState<StreamId>,
+    decide: impl Fn(Ev::State) -> BoxFuture<'c, (Ev::State, Result<Vec<Ev::Evt>, DecErr>)>,
+    retrys: Option<u32>,
+) -> Result<(Vec<Ev::Evt>, Ev::State, RepositoryVersion<V>), LoadDecideAppendError<DecErr, RepoErr>>
+where
+    Ev: Evolver,
+    Ev::Evt: Clone + Send + Sync + Debug,
+    Ev::State: Send,
+    DecErr: Send + Sync,
+    RepoErr: Debug + Send + Sync,
+    StreamId: Clone + Send + Sync + StreamIdFromEvent<Ev::Evt>,
+    V: Clone + Ord + Send + Sync,
+{
+    for r in 1..retrys.unwrap_or(20) {
+        let (decided_on, new_evts) = decide(state).await;
+        state = decided_on;
+        let new_evts = new_evts.map_err(LoadDecideAppendError::DecideErr)?;
+
+        let stream = match stream_id {
+            StreamState::New => match new_evts.first() {
+                None => {
+                    return Ok((vec![], state, version));
+                }
+                Some(evt) => StreamId::from(evt.clone()),
+            },
+            StreamState::Existing(sid) => sid.clone(),
+        };
+
+        match event_repository.append(&version, &stream, &new_evts).await {
+            Ok((appended_evts, new_version)) => {
+                let state = appended_evts.iter().fold(state, Ev::evolve);
+                return Ok((appended_evts, state, new_version));
+            }
+            Err(VersionedRepositoryError::RepoErr(e)) => {
+                return Err(LoadDecideAppendError::RepositoryErr(e));
+            }
+            Err(VersionedRepositoryError::VersionConflict(diff)) => {
+                // A stream behind the version the state was built at will never catch up to it
+                if let (RepositoryVersion::Exact(actual), RepositoryVersion::Exact(held)) =
+                    (diff.actual(), &version)
+                {
+                    if &actual < held {
+                        return Err(LoadDecideAppendError::VersionError);
+                    }
+                }
+
+                backoff(r).await;
+                (state, version) =
+                    catch_up::<Ev, _, _, _>(state, version, event_repository, &stream)
+                        .await
+                        .map_err(to_lda_error)?;
+            }
+        };
+    }
+
+    Err(LoadDecideAppendError::OccMaxRetries)
+}
+
+/// Reifies the state, decides and saves the evolved state, reifying again after every version conflict
+async fn reify_decide_save<'a, 'c, Ev, DecErr, RepoErr>(
+    state_repository: &mut (impl VersionedStateRepository<'a, Ev::State, RepoErr> + Send + Sync),
+    decide: impl Fn(Ev::State) -> BoxFuture<'c, (Ev::State, Result<Vec<Ev::Evt>, DecErr>)>,
+    retrys: Option<u32>,
+) -> Result<Ev::State, ReifyDecideSaveError<DecErr, RepoErr>>
+where
+    Ev: Evolver,
+    Ev::State: Send + Sync,
+    DecErr: Send + Sync,
+    RepoErr: Send + Sync,
+{
+    let (mut state, mut version) = state_repository
+        .reify()
+        .await
+        .map_err(ReifyDecideSaveError::RepositoryErr)?;
+
+    for _ in 1..retrys.unwrap_or(20) {
+        let (decided_on, evts) = decide(state).await;
+        let new_state = evts
+            .map_err(ReifyDecideSaveError::DecideErr)?
+            .iter()
+            .fold(decided_on, Ev::evolve);
+
+        match state_repository.save(&version, &new_state).await {
+            Ok(s) => return Ok(s),
+            Err(VersionedRepositoryError::RepoErr(e)) => {
+                return Err(ReifyDecideSaveError::RepositoryErr(e))
+            }
+            Err(VersionedRepositoryError::VersionConflict(_)) => {
+                (state, version) = state_repository
+                    .reify()
+                    .await
+                    .map_err(ReifyDecideSaveError::RepositoryErr)?;
+            }
+        }
+    }
+
+    Err(ReifyDecideSaveError::OccMaxRetries)
+}
+
+/// Waits a little longer after every conflicting attempt so competing writers spread out
+async fn backoff(attempt: u32) {
+    Delay::new(Duration::from_millis(100 * u64::from(attempt))).await
+}
+
+/// Folds the events of a stream recorded after `version` onto `state`, returning the version it caught up to
+///
+/// Any version other than `Exact` folds the whole stream
+pub(crate) async fn catch_up<'a, Ev, RepoErr, StreamId, V>(
+    state: Ev::State,
+    version: RepositoryVersion<V>,
+    event_repository: &(impl StreamingEventRepository<'a, Ev::Evt, RepoErr, StreamId = StreamId, Version = V>
+          + Send
+          + Sync),
+    stream_id: &StreamId,
+) -> Result<(Ev::State, RepositoryVersion<V>), VersionedRepositoryError<RepoErr, V>>
+where
+    Ev: Evolver,
+    Ev::Evt: Send + Sync + Debug,
+    RepoErr: Debug + Send + Sync,
+    StreamId: Clone + Send + Sync,
+    V: Clone + Ord + Send + Sync,
+{
+    let since = match &version {
+        RepositoryVersion::Exact(v) => Some(v.to_owned()),
+        _ => None,
+    };
+
+    // Reads start at `version` itself, which the state already includes
+    event_repository
+        .stream_from_version(version.to_owned(), Some(stream_id.to_owned()))
+        .try_filter(|recorded| ready(since.as_ref().is_none_or(|since| &recorded.version > since)))
+        .try_fold((state, version), |(state, _), recorded| {
+            ready(Ok((
+                Ev::evolve(state, &recorded.event),
+                RepositoryVersion::Exact(recorded.version),
+            )))
+        })
+        .await
+}
+
+fn to_lda_error<DecErr: Send + Sync, RepoErr: Send + Sync, Version: Send + Sync>(
+    err: VersionedRepositoryError<RepoErr, Version>,
+) -> LoadDecideAppendError<DecErr, RepoErr> {
+    match err {
+        VersionedRepositoryError::VersionConflict(_) => LoadDecideAppendError::VersionError,
+        VersionedRepositoryError::RepoErr(e) => LoadDecideAppendError::RepositoryErr(e),
+    }
+}
+
 pub enum StreamState<T> {
     New,
     Existing(T),
@@ -288,7 +860,7 @@ pub enum ReifyDecideSaveError<DecideErr: Send + Sync, RepoErr> {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::{collections::HashMap, num::NonZeroUsize};
 
     use assert_matches::assert_matches;
 
@@ -300,8 +872,8 @@ mod tests {
         },
         test_helpers::{
             deciders::user::{
-                User, UserCommand, UserDecider, UserDeciderCtx, UserDeciderError, UserDeciderState,
-                UserEvent, UserFieldError, UserName,
+                Guitar, User, UserCommand, UserDecider, UserDeciderCtx, UserDeciderError,
+                UserDeciderState, UserEvent, UserFieldError, UserName,
             },
             ValueType,
         },
@@ -454,6 +1026,92 @@ mod tests {
         );
     }
 
+    #[actix_rt::test]
+    async fn execute_with_cache_folds_only_the_tail() {
+        let ctx = UserDeciderCtx::new();
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new("test");
+        let cache = StateCache::new(NonZeroUsize::new(8).unwrap());
+
+        let evts = UserDecider::execute_with_cache(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &cache,
+            &StreamState::New,
+            &ctx,
+            &UserCommand::AddUser("Mike".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+        let user_id = evts.first().unwrap().get_id();
+        let id = user_id.to_string();
+        assert_eq!(cache.len(), 1);
+
+        // Appended behind the cache's back - picked up by the tail load of the next command
+        event_repository
+            .append(
+                &RepositoryVersion::Exact(0),
+                &id,
+                &vec![UserEvent::UserNameUpdated(
+                    user_id,
+                    UserName::try_from("Mike2").unwrap(),
+                )],
+            )
+            .await
+            .unwrap();
+
+        UserDecider::execute_with_cache(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &cache,
+            &StreamState::Existing(id.clone()),
+            &ctx,
+            &UserCommand::UpdateUserName(user_id, "Mike3".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let loaded =
+            UserDeciderState::load_by_id(UserDeciderState::default(), &event_repository, &id)
+                .await
+                .unwrap();
+        assert_eq!(
+            cache.get(&id).unwrap(),
+            (loaded.clone(), RepositoryVersion::Exact(2))
+        );
+
+        // A cached version ahead of the stream can't be caught up on and is dropped
+        cache.put(id.clone(), loaded, RepositoryVersion::Exact(99));
+
+        let res = UserDecider::execute_with_cache(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &cache,
+            &StreamState::Existing(id.clone()),
+            &ctx,
+            &UserCommand::UpdateUserName(user_id, "Mike4".to_string()),
+            None,
+        )
+        .await;
+        assert_matches!(res, Err(LoadDecideAppendError::VersionError));
+        assert!(cache.get(&id).is_none());
+
+        // So the next command loads the stream in full
+        UserDecider::execute_with_cache(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &cache,
+            &StreamState::Existing(id.clone()),
+            &ctx,
+            &UserCommand::UpdateUserName(user_id, "Mike4".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(cache.get(&id).unwrap().1, RepositoryVersion::Exact(3));
+    }
+
     #[actix_rt::test]
     async fn decide_evolve_with_command_response() {
         let ctx = UserDeciderCtx::new();
@@ -480,4 +1138,247 @@ mod tests {
 
         assert_eq!(res.users.len(), 1);
     }
+
+    #[actix_rt::test]
+    async fn load_decide_append_idempotent() {
+        let ctx = UserDeciderCtx::new();
+
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new("test");
+
+        let evts = UserDecider::execute(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &StreamState::New,
+            &ctx,
+            &UserCommand::AddUser("Mike".to_string()),
+            None,
+        )
+        .await
+        .expect("command_succeeds");
+        let user_id = evts.first().unwrap().get_id();
+        let id = user_id.to_string();
+
+        let cmd = UserCommand::UpdateUserName(user_id, "Mike2".to_string());
+
+        let first = UserDecider::execute_idempotent(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &StreamState::Existing(id.clone()),
+            &ctx,
+            &cmd,
+            None,
+        )
+        .await
+        .expect("command_succeeds");
+
+        let retried = UserDecider::execute_idempotent(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &StreamState::Existing(id.clone()),
+            &ctx,
+            &cmd,
+            None,
+        )
+        .await
+        .expect("command_succeeds");
+
+        assert_eq!(first, retried);
+
+        let (evts, _) = event_repository.load(Some(&id)).await.expect("loaded");
+        assert_eq!(evts.len(), 2);
+        assert_eq!(evts[1..], first);
+
+        // The same key on two new streams does not collide
+        let cmd = UserCommand::AddUser("Dmitiry".to_string());
+
+        for _ in 0..2 {
+            UserDecider::execute_idempotent(
+                UserDeciderState::default(),
+                &mut event_repository,
+                &StreamState::New,
+                &ctx,
+                &cmd,
+                None,
+            )
+            .await
+            .expect("command_succeeds");
+        }
+
+        let (evts, _) = event_repository.load(None).await.expect("loaded");
+        assert_eq!(evts.len(), 4);
+    }
+
+    #[actix_rt::test]
+    async fn async_load_decide_append_basic_function() {
+        let ctx = UserDeciderCtx::new();
+
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new("test");
+
+        let evts = UserDecider::execute_async(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &StreamState::New,
+            &ctx,
+            &UserCommand::AddUser("Mike".to_string()),
+            None,
+        )
+        .await
+        .expect("command_succeeds");
+
+        let id = evts.first().unwrap().get_id();
+
+        let evts = UserDecider::execute_async(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &StreamState::Existing(id.to_string()),
+            &ctx,
+            &UserCommand::AddGuitar(
+                id,
+                Guitar {
+                    brand: "Ibanez".to_string(),
+                },
+            ),
+            None,
+        )
+        .await
+        .expect("command_succeeds");
+
+        assert_matches!(evts.first(), Some(UserEvent::UserGuitarAdded(user_id, _)) if user_id == &id);
+
+        let res = UserDecider::execute_async(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &StreamState::Existing(id.to_string()),
+            &ctx,
+            &UserCommand::AddGuitar(
+                id,
+                Guitar {
+                    brand: "Ibanez".to_string(),
+                },
+            ),
+            None,
+        )
+        .await;
+
+        assert_matches!(
+            res,
+            Err(LoadDecideAppendError::DecideErr(
+                UserDeciderError::AlreadyHasGuitar(_)
+            ))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn async_reify_decide_save_basic_functionality() {
+        let ctx = UserDeciderCtx::new();
+
+        let mut state_repository =
+            InMemoryStateRepository::<UserDeciderState>::new(UserDeciderState::default());
+
+        let res = UserDecider::execute_reify_decide_async(
+            &mut state_repository,
+            &ctx,
+            &UserCommand::AddUser("Mike".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(res.users.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn load_as_of_rebuilds_past_states() {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new("test");
+        let id = "1".to_string();
+
+        event_repository
+            .append(
+                &RepositoryVersion::NoStream,
+                &id,
+                &vec![UserEvent::UserAdded(User::new(
+                    1,
+                    UserName::try_from("Mike").unwrap(),
+                ))],
+            )
+            .await
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let before_rename = std::time::SystemTime::now();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+
+        event_repository
+            .append(
+                &RepositoryVersion::Exact(0),
+                &id,
+                &vec![UserEvent::UserNameUpdated(
+                    1,
+                    UserName::try_from("Mike2").unwrap(),
+                )],
+            )
+            .await
+            .unwrap();
+
+        let name_at = |as_of| {
+            let event_repository = &event_repository;
+            let id = &id;
+            async move {
+                let (state, version) = UserDeciderState::load_as_of(
+                    UserDeciderState::default(),
+                    event_repository,
+                    Some(id),
+                    &as_of,
+                )
+                .await
+                .unwrap();
+
+                (
+                    state.users.get(&1).map(|user| user.name.to_owned()),
+                    version,
+                )
+            }
+        };
+
+        assert_eq!(
+            name_at(AsOf::Version(RepositoryVersion::Exact(0))).await,
+            (
+                Some(UserName::try_from("Mike").unwrap()),
+                RepositoryVersion::Exact(0)
+            )
+        );
+        assert_eq!(
+            name_at(AsOf::Time(before_rename)).await,
+            (
+                Some(UserName::try_from("Mike").unwrap()),
+                RepositoryVersion::Exact(0)
+            )
+        );
+        assert_eq!(
+            name_at(AsOf::Version(RepositoryVersion::Any)).await,
+            (
+                Some(UserName::try_from("Mike2").unwrap()),
+                RepositoryVersion::Exact(1)
+            )
+        );
+        assert_eq!(
+            name_at(AsOf::Version(RepositoryVersion::NoStream)).await,
+            (None, RepositoryVersion::NoStream)
+        );
+
+        let steps: Vec<_> =
+            UserDeciderState::steps(UserDeciderState::default(), &event_repository, Some(id))
+                .try_collect()
+                .await
+                .unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(
+            steps[0].state.users[&1].name,
+            UserName::try_from("Mike").unwrap()
+        );
+        assert_eq!(steps[1].version, 1);
+        assert_eq!(
+            steps[1].state.users[&1].name,
+            UserName::try_from("Mike2").unwrap()
+        );
+    }
 }
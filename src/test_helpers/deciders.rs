@@ -4,16 +4,20 @@ pub(crate) mod user {
         sync::{Arc, Mutex},
     };
 
+    use async_trait::async_trait;
     use autoincrement::{AsyncIncrement, AsyncIncremental};
     use serde::{Deserialize, Serialize};
     use thiserror::Error;
 
     use crate::{
-        decider::{Decider, DeciderWithContext, Event, Evolver},
+        command_bus::Command,
+        decider::{
+            AsyncDeciderWithContext, Decider, DeciderWithContext, Event, Evolver, IdempotentCommand,
+        },
         repository::StreamIdFromEvent,
         strategies::{
-            DecideEvolveWithCommandResponse, LoadDecideAppend, ReifyDecideSave,
-            StateFromEventRepository,
+            AsyncLoadDecideAppend, AsyncReifyDecideSave, DecideEvolveWithCommandResponse,
+            LoadDecideAppend, ReifyDecideSave, StateFromEventRepository,
         },
         test_helpers::ValueType,
     };
@@ -232,10 +236,31 @@ pub(crate) mod user {
         }
     }
 
+    #[async_trait]
+    impl AsyncDeciderWithContext for UserDecider {
+        type Ctx = UserDeciderCtx;
+
+        type Cmd = UserCommand;
+
+        type Err = UserDeciderError;
+
+        async fn decide(
+            ctx: &UserDeciderCtx,
+            state: &UserDeciderState,
+            cmd: &UserCommand,
+        ) -> Result<Vec<UserEvent>, UserDeciderError> {
+            <Self as DeciderWithContext>::decide(ctx, state, cmd)
+        }
+    }
+
     impl LoadDecideAppend for UserDecider {
         type Decide = Self;
     }
 
+    impl AsyncLoadDecideAppend for UserDecider {
+        type Decide = Self;
+    }
+
     impl DecideEvolveWithCommandResponse for UserDecider {
         type Decide = Self;
     }
@@ -244,6 +269,10 @@ pub(crate) mod user {
         type Decide = Self;
     }
 
+    impl AsyncReifyDecideSave for UserDecider {
+        type Decide = Self;
+    }
+
     #[derive(Debug, Clone, PartialEq, Eq)]
     pub(crate) struct UserDeciderState {
         pub(crate) users: HashMap<UserId, User>,
@@ -296,6 +325,23 @@ pub(crate) mod user {
         AddGuitar(UserId, Guitar),
     }
 
+    impl Command for UserCommand {
+        type Decide = UserDecider;
+    }
+
+    // Derived from the command content so repeating a command reuses its key
+    impl IdempotentCommand for UserCommand {
+        fn idempotency_key(&self) -> Option<String> {
+            match self {
+                UserCommand::AddUser(name) => Some(format!("AddUser-{}", name)),
+                UserCommand::UpdateUserName(id, name) => {
+                    Some(format!("UpdateUserName-{}-{}", id, name))
+                }
+                UserCommand::AddGuitar(_, _) => None,
+            }
+        }
+    }
+
     #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
     pub(crate) enum UserEvent {
         UserAdded(User),
@@ -5,7 +5,7 @@ use thiserror::Error;
 
 use crate::repository::{
     redis::{versioned_event::StreamModelDTO, RedisRepositoryError},
-    WithFineGrainedStreamId,
+    WithEventType, WithFineGrainedStreamId,
 };
 
 use super::{
@@ -47,6 +47,12 @@ impl WithFineGrainedStreamId for TestUserEventDTO {
     }
 }
 
+impl WithEventType for TestUserEventDTO {
+    fn event_type(&self) -> String {
+        format!("{:?}", self.event_type)
+    }
+}
+
 impl From<User> for UserDTO {
     fn from(value: User) -> Self {
         Self {
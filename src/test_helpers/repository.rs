@@ -14,7 +14,8 @@ use futures::{
 use crate::{
     decider::Event,
     repository::{
-        event::VersionedEventRepositoryWithStreams, state::VersionedStateRepository,
+        event::{StreamingEventRepository, VersionedEventRepositoryWithStreams},
+        state::VersionedStateRepository,
         RepositoryVersion,
     },
     strategies::{LoadDecideAppend, StateFromEventRepository, StreamState},
@@ -146,9 +147,9 @@ pub(crate) async fn vesioned_state_repository_spec<'a, Err: Debug + Send + Sync>
 pub(crate) async fn versioned_event_repository_with_streams_occ_spec<
     'a,
     Err: Debug + Send + Sync + Debug,
-    V: Eq + PartialEq + Debug + Send + Sync,
+    V: Clone + Ord + Debug + Send + Sync,
 >(
-    mut event_repository: impl VersionedEventRepositoryWithStreams<'a, UserEvent, Err, Version = V, StreamId = String>
+    mut event_repository: impl StreamingEventRepository<'a, UserEvent, Err, Version = V, StreamId = String>
         + Send
         + Sync
         + Clone,
@@ -242,12 +243,8 @@ pub(crate) async fn versioned_event_repository_with_streams_occ_spec<
     );
 }
 
-async fn add_guitar<
-    'a,
-    Err: Debug + Send + Sync + Debug,
-    V: Eq + PartialEq + Debug + Send + Sync,
->(
-    mut event_repository: impl VersionedEventRepositoryWithStreams<'a, UserEvent, Err, Version = V, StreamId = String>
+async fn add_guitar<'a, Err: Debug + Send + Sync + Debug, V: Clone + Ord + Debug + Send + Sync>(
+    mut event_repository: impl StreamingEventRepository<'a, UserEvent, Err, Version = V, StreamId = String>
         + Send
         + Sync,
     user_id: UserId,
@@ -0,0 +1,541 @@
+use std::{
+    fmt::Debug,
+    ops::Range,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{future::ready, stream, FutureExt, StreamExt, TryStreamExt};
+use futures_timer::Delay;
+use thiserror::Error;
+
+use crate::{
+    decider::Event,
+    repository::{
+        event::{EventStream, StreamingEventRepository, VersionedEventRepositoryWithStreams},
+        state::VersionedStateRepository,
+        RepositoryVersion, VersionDiff, VersionedRepositoryError,
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum FaultError<RepoErr: Debug> {
+    #[error("Injected transient failure")]
+    Transient,
+    #[error("Injected partial failure, the write was applied but reported as failed")]
+    Partial,
+    #[error("Repository error {0:?}")]
+    Repository(RepoErr),
+}
+
+/// Which faults to inject and how often, each probability between `0.0` and `1.0`
+///
+/// The same seed against the same sequence of calls injects the same faults, so a failing run can be replayed
+#[derive(Debug, Clone)]
+pub struct FaultConfig {
+    seed: u64,
+    version_conflicts: f64,
+    transient_failures: f64,
+    partial_failures: f64,
+    latency: f64,
+    latency_range: Range<Duration>,
+}
+
+impl FaultConfig {
+    /// Injects nothing until probabilities are set
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            version_conflicts: 0.0,
+            transient_failures: 0.0,
+            partial_failures: 0.0,
+            latency: 0.0,
+            latency_range: Duration::ZERO..Duration::ZERO,
+        }
+    }
+
+    /// Writes fail with a `VersionConflict` before reaching the wrapped repository
+    pub fn with_version_conflicts(mut self, probability: f64) -> Self {
+        self.version_conflicts = probability;
+        self
+    }
+
+    /// Reads and writes fail with `FaultError::Transient` before reaching the wrapped repository
+    pub fn with_transient_failures(mut self, probability: f64) -> Self {
+        self.transient_failures = probability;
+        self
+    }
+
+    /// Writes reach the wrapped repository but fail with `FaultError::Partial`, like a lost acknowledgement
+    pub fn with_partial_failures(mut self, probability: f64) -> Self {
+        self.partial_failures = probability;
+        self
+    }
+
+    /// Calls are delayed by a duration picked from `range`, without blocking the executor
+    pub fn with_latency(mut self, probability: f64, range: Range<Duration>) -> Self {
+        self.latency = probability;
+        self.latency_range = range;
+        self
+    }
+}
+
+/// How many faults of each kind were injected so far
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    pub version_conflicts: usize,
+    pub transient_failures: usize,
+    pub partial_failures: usize,
+    pub delays: usize,
+}
+
+#[derive(Debug)]
+struct FaultInjector {
+    config: FaultConfig,
+    // splitmix64 state
+    state: AtomicU64,
+    version_conflicts: AtomicUsize,
+    transient_failures: AtomicUsize,
+    partial_failures: AtomicUsize,
+    delays: AtomicUsize,
+}
+
+impl FaultInjector {
+    fn new(config: FaultConfig) -> Self {
+        Self {
+            state: AtomicU64::new(config.seed),
+            config,
+            version_conflicts: AtomicUsize::new(0),
+            transient_failures: AtomicUsize::new(0),
+            partial_failures: AtomicUsize::new(0),
+            delays: AtomicUsize::new(0),
+        }
+    }
+
+    fn next_u64(&self) -> u64 {
+        const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+        let mut z = self
+            .state
+            .fetch_add(GAMMA, Ordering::Relaxed)
+            .wrapping_add(GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn roll(&self, probability: f64, counter: &AtomicUsize) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+
+        let sample = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        let hit = sample < probability;
+        if hit {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+
+        hit
+    }
+
+    /// Duration to delay the current call by, if latency is injected into it
+    fn latency(&self) -> Option<Duration> {
+        if !self.roll(self.config.latency, &self.delays) {
+            return None;
+        }
+
+        let Range { start, end } = self.config.latency_range;
+        let spread = end.saturating_sub(start).as_nanos() as u64;
+        let jitter = if spread == 0 {
+            0
+        } else {
+            self.next_u64() % spread
+        };
+
+        Some(start + Duration::from_nanos(jitter))
+    }
+
+    async fn delay(&self) {
+        if let Some(latency) = self.latency() {
+            Delay::new(latency).await;
+        }
+    }
+
+    fn transient<Err: Debug>(&self) -> Result<(), FaultError<Err>> {
+        if self.roll(self.config.transient_failures, &self.transient_failures) {
+            Err(FaultError::Transient)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn before_write<Err: Debug, V: Clone>(
+        &self,
+        version: &RepositoryVersion<V>,
+    ) -> Result<(), VersionedRepositoryError<FaultError<Err>, V>> {
+        self.delay().await;
+
+        if self.roll(self.config.version_conflicts, &self.version_conflicts) {
+            return Err(VersionedRepositoryError::VersionConflict(VersionDiff::new(
+                version.to_owned(),
+                RepositoryVersion::StreamExists,
+            )));
+        }
+
+        self.transient().map_err(VersionedRepositoryError::RepoErr)
+    }
+
+    fn after_write<Err: Debug, V>(
+        &self,
+    ) -> Result<(), VersionedRepositoryError<FaultError<Err>, V>> {
+        if self.roll(self.config.partial_failures, &self.partial_failures) {
+            Err(VersionedRepositoryError::RepoErr(FaultError::Partial))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn stats(&self) -> FaultStats {
+        FaultStats {
+            version_conflicts: self.version_conflicts.load(Ordering::Relaxed),
+            transient_failures: self.transient_failures.load(Ordering::Relaxed),
+            partial_failures: self.partial_failures.load(Ordering::Relaxed),
+            delays: self.delays.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Wraps an event repository and injects the faults described by a `FaultConfig`
+///
+/// Meant for exercising the retry loops of `LoadDecideAppend` without a flaky database
+#[derive(Debug)]
+pub struct FaultyEventRepository<R> {
+    inner: R,
+    faults: FaultInjector,
+}
+
+impl<R> FaultyEventRepository<R> {
+    pub fn new(inner: R, config: FaultConfig) -> Self {
+        Self {
+            inner,
+            faults: FaultInjector::new(config),
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.faults.stats()
+    }
+}
+
+#[async_trait]
+impl<'a, R, E, Err> VersionedEventRepositoryWithStreams<'a, E, FaultError<Err>>
+    for FaultyEventRepository<R>
+where
+    R: VersionedEventRepositoryWithStreams<'a, E, Err> + Send + Sync,
+    R::Version: Clone,
+    E: Event + Clone + Debug + Send + Sync,
+    Err: Debug + Send + Sync,
+{
+    type StreamId = R::StreamId;
+    type Version = R::Version;
+
+    async fn load(
+        &self,
+        id: Option<&Self::StreamId>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<FaultError<Err>, Self::Version>,
+    > {
+        self.faults.delay().await;
+        self.faults
+            .transient()
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        self.inner
+            .load(id)
+            .await
+            .map_err(|e| e.map_repo_err(FaultError::Repository))
+    }
+
+    async fn load_from_version(
+        &self,
+        version: &RepositoryVersion<Self::Version>,
+        id: Option<&Self::StreamId>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<FaultError<Err>, Self::Version>,
+    > {
+        self.faults.delay().await;
+        self.faults
+            .transient()
+            .map_err(VersionedRepositoryError::RepoErr)?;
+
+        self.inner
+            .load_from_version(version, id)
+            .await
+            .map_err(|e| e.map_repo_err(FaultError::Repository))
+    }
+
+    async fn append(
+        &mut self,
+        version: &RepositoryVersion<Self::Version>,
+        stream: &Self::StreamId,
+        events: &Vec<E>,
+    ) -> Result<
+        (Vec<E>, RepositoryVersion<Self::Version>),
+        VersionedRepositoryError<FaultError<Err>, Self::Version>,
+    >
+    where
+        'a: 'async_trait,
+        E: 'async_trait,
+    {
+        self.faults.before_write(version).await?;
+
+        let appended = self
+            .inner
+            .append(version, stream, events)
+            .await
+            .map_err(|e| e.map_repo_err(FaultError::Repository))?;
+
+        self.faults.after_write()?;
+
+        Ok(appended)
+    }
+}
+
+/// Faults are rolled once when the stream is opened, like a failed read request
+impl<'a, R, E, Err> StreamingEventRepository<'a, E, FaultError<Err>> for FaultyEventRepository<R>
+where
+    R: StreamingEventRepository<'a, E, Err> + Send + Sync,
+    R::Version: Clone + 'static,
+    E: Event + Clone + Debug + Send + Sync + 'static,
+    Err: Debug + Send + Sync + 'static,
+{
+    fn stream_from_version(
+        &self,
+        version: RepositoryVersion<Self::Version>,
+        id: Option<Self::StreamId>,
+    ) -> EventStream<'_, E, Self::Version, FaultError<Err>> {
+        let latency = self.faults.latency();
+
+        let events = match self.faults.transient() {
+            Err(e) => stream::once(ready(Err(VersionedRepositoryError::RepoErr(e)))).boxed(),
+            Ok(()) => self
+                .inner
+                .stream_from_version(version, id)
+                .map_err(|e| e.map_repo_err(FaultError::Repository))
+                .boxed(),
+        };
+
+        match latency {
+            Some(latency) => Delay::new(latency)
+                .map(move |()| events)
+                .flatten_stream()
+                .boxed(),
+            None => events,
+        }
+    }
+}
+
+/// Wraps a state repository and injects the faults described by a `FaultConfig`
+///
+/// Meant for exercising the retry loop of `ReifyDecideSave` without a flaky database
+#[derive(Debug)]
+pub struct FaultyStateRepository<R> {
+    inner: R,
+    faults: FaultInjector,
+}
+
+impl<R> FaultyStateRepository<R> {
+    pub fn new(inner: R, config: FaultConfig) -> Self {
+        Self {
+            inner,
+            faults: FaultInjector::new(config),
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.faults.stats()
+    }
+}
+
+#[async_trait]
+impl<'a, R, State, Err> VersionedStateRepository<'a, State, FaultError<Err>>
+    for FaultyStateRepository<R>
+where
+    R: VersionedStateRepository<'a, State, Err> + Send + Sync,
+    R::Version: Clone,
+    State: Send + Sync,
+    Err: Debug + Send + Sync,
+{
+    type Version = R::Version;
+
+    async fn reify(&self) -> Result<(State, RepositoryVersion<Self::Version>), FaultError<Err>> {
+        self.faults.delay().await;
+        self.faults.transient()?;
+
+        self.inner.reify().await.map_err(FaultError::Repository)
+    }
+
+    async fn save(
+        &mut self,
+        version: &RepositoryVersion<Self::Version>,
+        state: &State,
+    ) -> Result<State, VersionedRepositoryError<FaultError<Err>, Self::Version>>
+    where
+        'a: 'async_trait,
+        State: 'async_trait,
+        FaultError<Err>: 'async_trait,
+    {
+        self.faults.before_write(version).await?;
+
+        let saved = self
+            .inner
+            .save(version, state)
+            .await
+            .map_err(|e| e.map_repo_err(FaultError::Repository))?;
+
+        self.faults.after_write()?;
+
+        Ok(saved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use assert_matches::assert_matches;
+    use futures::future::join;
+
+    use crate::{
+        repository::{
+            event::VersionedEventRepositoryWithStreams,
+            in_memory::{
+                state::versioned::InMemoryStateRepository,
+                versioned_with_streams::InMemoryEventRepository,
+            },
+            RepositoryVersion, VersionedRepositoryError,
+        },
+        strategies::{LoadDecideAppend, ReifyDecideSave, StreamState},
+        test_helpers::deciders::user::{
+            User, UserCommand, UserDecider, UserDeciderCtx, UserDeciderState, UserEvent, UserName,
+        },
+    };
+
+    use super::{
+        FaultConfig, FaultError, FaultInjector, FaultyEventRepository, FaultyStateRepository,
+    };
+
+    #[test]
+    fn same_seed_injects_same_faults() {
+        let config = FaultConfig::new(42);
+        let rolls = |injector: FaultInjector| {
+            (0..64)
+                .map(|_| injector.roll(0.5, &injector.version_conflicts))
+                .collect::<Vec<_>>()
+        };
+
+        let first = rolls(FaultInjector::new(config.to_owned()));
+        assert_eq!(first, rolls(FaultInjector::new(config)));
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    #[actix_rt::test]
+    async fn load_decide_append_retries_through_conflicts() {
+        let mut event_repository = FaultyEventRepository::new(
+            InMemoryEventRepository::<UserEvent>::new("test"),
+            FaultConfig::new(7).with_version_conflicts(0.5),
+        );
+
+        let evts = UserDecider::execute(
+            UserDeciderState::default(),
+            &mut event_repository,
+            &StreamState::New,
+            &UserDeciderCtx::new(),
+            &UserCommand::AddUser("Mike".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(evts.len(), 1);
+        assert!(event_repository.stats().version_conflicts > 0);
+    }
+
+    #[actix_rt::test]
+    async fn partial_failures_apply_the_write() {
+        let mut event_repository = FaultyEventRepository::new(
+            InMemoryEventRepository::<UserEvent>::new("test"),
+            FaultConfig::new(1).with_partial_failures(1.0),
+        );
+        let id = "1".to_string();
+        let events = vec![UserEvent::UserAdded(User::new(
+            1,
+            UserName::try_from("Mike").unwrap(),
+        ))];
+
+        let res = event_repository
+            .append(&RepositoryVersion::NoStream, &id, &events)
+            .await;
+        assert_matches!(
+            res,
+            Err(VersionedRepositoryError::RepoErr(FaultError::Partial))
+        );
+
+        assert_eq!(
+            event_repository.inner().load(Some(&id)).await.unwrap(),
+            (events, RepositoryVersion::Exact(0))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn reify_decide_save_surfaces_transient_failures() {
+        let mut state_repository = FaultyStateRepository::new(
+            InMemoryStateRepository::new(UserDeciderState::default()),
+            FaultConfig::new(3).with_transient_failures(1.0),
+        );
+
+        let res = UserDecider::execute_reify_decide(
+            &mut state_repository,
+            &UserDeciderCtx::new(),
+            &UserCommand::AddUser("Mike".to_string()),
+            None,
+        )
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(state_repository.stats().transient_failures, 1);
+    }
+
+    #[actix_rt::test]
+    async fn latency_does_not_block_the_executor() {
+        let latency = Duration::from_millis(50);
+        let event_repository = FaultyEventRepository::new(
+            InMemoryEventRepository::<UserEvent>::new("test"),
+            FaultConfig::new(5).with_latency(1.0, latency..latency),
+        );
+        let id = "1".to_string();
+
+        // Both delays run at once on the single threaded test runtime
+        let started = Instant::now();
+        let (first, second) = join(
+            event_repository.load(Some(&id)),
+            event_repository.load(Some(&id)),
+        )
+        .await;
+
+        assert!(first.is_ok() && second.is_ok());
+        assert!(started.elapsed() < latency * 2);
+        assert_eq!(event_repository.stats().delays, 2);
+    }
+}
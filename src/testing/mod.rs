@@ -0,0 +1,204 @@
+use std::fmt::Debug;
+
+use crate::decider::{Decider, DeciderWithContext, Evolver};
+
+pub mod fault;
+#[cfg(any(test, feature = "proptest"))]
+pub mod property;
+pub mod replay;
+
+/// Starts a decider spec from the state produced by folding `events` over `State::default()`
+pub fn given<D>(events: Vec<D::Evt>) -> Given<D>
+where
+    D: Evolver,
+    D::State: Default,
+{
+    given_state(events.iter().fold(D::State::default(), D::evolve))
+}
+
+/// Starts a decider spec from an explicit state - useful for state stored deciders
+pub fn given_state<D: Evolver>(state: D::State) -> Given<D> {
+    Given { state }
+}
+
+pub struct Given<D: Evolver> {
+    state: D::State,
+}
+
+impl<D: Evolver> Given<D> {
+    pub fn when(self, cmd: <D as Decider>::Cmd) -> Then<D, <D as Decider>::Err>
+    where
+        D: Decider,
+    {
+        let result = <D as Decider>::decide(&self.state, &cmd);
+
+        Then {
+            state: self.state,
+            result,
+        }
+    }
+
+    pub fn when_with_ctx(
+        self,
+        ctx: &<D as DeciderWithContext>::Ctx,
+        cmd: <D as DeciderWithContext>::Cmd,
+    ) -> Then<D, <D as DeciderWithContext>::Err>
+    where
+        D: DeciderWithContext,
+    {
+        let result = <D as DeciderWithContext>::decide(ctx, &self.state, &cmd);
+
+        Then {
+            state: self.state,
+            result,
+        }
+    }
+}
+
+pub struct Then<D: Evolver, Err> {
+    state: D::State,
+    result: Result<Vec<D::Evt>, Err>,
+}
+
+impl<D, Err> Then<D, Err>
+where
+    D: Evolver,
+    D::Evt: Debug + PartialEq,
+    Err: Debug,
+{
+    pub fn then_expect(self, expected: Vec<D::Evt>) -> Self {
+        match &self.result {
+            Ok(actual) if actual == &expected => {}
+            Ok(actual) => panic!(
+                "Decided events did not match expected events\n{}",
+                diff(&format!("{:#?}", expected), &format!("{:#?}", actual))
+            ),
+            Err(e) => panic!(
+                "Expected events but decider failed\n{}",
+                diff(&format!("{:#?}", expected), &format!("{:#?}", e))
+            ),
+        }
+
+        self
+    }
+
+    pub fn then_error(self, matcher: impl FnOnce(&Err) -> bool) -> Self {
+        match &self.result {
+            Err(e) if matcher(e) => {}
+            Err(e) => panic!("Decider error did not match: {:#?}", e),
+            Ok(evts) => panic!("Expected an error but decider produced {:#?}", evts),
+        }
+
+        self
+    }
+
+    /// Compares the state after evolving the decided events - fails if the decider errored
+    pub fn then_expect_state(self, expected: D::State) -> Self
+    where
+        D::State: Clone + Debug + PartialEq,
+    {
+        let actual = match &self.result {
+            Ok(evts) => evts.iter().fold(self.state.clone(), D::evolve),
+            Err(e) => panic!("Expected state but decider failed: {:#?}", e),
+        };
+
+        if actual != expected {
+            panic!(
+                "Evolved state did not match expected state\n{}",
+                diff(&format!("{:#?}", expected), &format!("{:#?}", actual))
+            );
+        }
+
+        self
+    }
+}
+
+/// Line diff of two debug representations, `-` lines are expected and `+` lines actual
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // Longest common subsequence table, filled from the end of both inputs
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = vec![];
+    let (mut i, mut j) = (0, 0);
+
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            out.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            out.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::test_helpers::deciders::user::{
+        Guitar, User, UserCommand, UserDecider, UserDeciderCtx, UserDeciderError, UserDeciderState,
+        UserEvent, UserName,
+    };
+
+    use super::*;
+
+    fn mike() -> User {
+        User::new(1, UserName::try_from("Mike").unwrap())
+    }
+
+    #[test]
+    fn given_events_when_command_then_events() {
+        let ibanez = Guitar {
+            brand: "Ibanez".to_string(),
+        };
+
+        given::<UserDecider>(vec![UserEvent::UserAdded(mike())])
+            .when(UserCommand::AddGuitar(1, ibanez.clone()))
+            .then_expect(vec![UserEvent::UserGuitarAdded(1, ibanez.clone())]);
+
+        given::<UserDecider>(vec![
+            UserEvent::UserAdded(mike()),
+            UserEvent::UserGuitarAdded(1, ibanez.clone()),
+        ])
+        .when_with_ctx(&UserDeciderCtx::new(), UserCommand::AddGuitar(1, ibanez))
+        .then_error(|e| matches!(e, UserDeciderError::AlreadyHasGuitar(_)));
+    }
+
+    #[test]
+    fn given_state_when_command_then_state() {
+        given_state::<UserDecider>(UserDeciderState::default())
+            .when(UserCommand::AddUser("Mike".to_string()))
+            .then_expect(vec![UserEvent::UserAdded(mike())])
+            .then_expect_state(UserDeciderState::new(HashMap::from([(1, mike())])));
+    }
+
+    #[test]
+    #[should_panic(expected = "-             \"Mike3\",\n+             \"Mike2\",")]
+    fn mismatched_events_print_diff() {
+        given::<UserDecider>(vec![UserEvent::UserAdded(mike())])
+            .when(UserCommand::UpdateUserName(1, "Mike2".to_string()))
+            .then_expect(vec![UserEvent::UserNameUpdated(
+                1,
+                UserName::try_from("Mike3").unwrap(),
+            )]);
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt::Debug;
+
+use proptest::{
+    collection,
+    strategy::Strategy,
+    test_runner::{Config, TestCaseError, TestError, TestRunner},
+};
+
+use crate::decider::Decider;
+
+type Invariant<State> = (String, Box<dyn Fn(&State) -> bool>);
+
+/// Runs randomly generated command sequences through a `Decider`, shrinking any failing sequence
+///
+/// Every sequence checks that `decide` is deterministic, that each supplied invariant holds after
+/// every command and that replaying the produced events from the initial state gives the same state as
+/// evolving them one command at a time
+pub struct DeciderProperties<D: Decider> {
+    config: Config,
+    max_commands: usize,
+    invariants: Vec<Invariant<D::State>>,
+}
+
+impl<D: Decider> Default for DeciderProperties<D> {
+    fn default() -> Self {
+        Self {
+            config: Config {
+                failure_persistence: None,
+                ..Config::default()
+            },
+            max_commands: 20,
+            invariants: vec![],
+        }
+    }
+}
+
+impl<D> DeciderProperties<D>
+where
+    D: Decider,
+    D::State: Clone + Debug + PartialEq,
+    D::Evt: Debug + PartialEq,
+    D::Cmd: Debug,
+    D::Err: Debug,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_max_commands(mut self, max_commands: usize) -> Self {
+        self.max_commands = max_commands;
+        self
+    }
+
+    pub fn invariant(mut self, name: &str, check: impl Fn(&D::State) -> bool + 'static) -> Self {
+        self.invariants.push((name.to_owned(), Box::new(check)));
+        self
+    }
+
+    /// On failure the error holds the minimal command sequence proptest could shrink to
+    pub fn check(
+        &self,
+        initial: D::State,
+        commands: impl Strategy<Value = D::Cmd>,
+    ) -> Result<(), TestError<Vec<D::Cmd>>> {
+        let mut runner = TestRunner::new(self.config.clone());
+
+        runner.run(&collection::vec(commands, 0..=self.max_commands), |cmds| {
+            self.check_sequence(initial.clone(), &cmds)
+        })
+    }
+
+    fn check_sequence(&self, initial: D::State, cmds: &[D::Cmd]) -> Result<(), TestCaseError> {
+        let mut state = initial.clone();
+        let mut history = vec![];
+
+        for cmd in cmds {
+            match (D::decide(&state, cmd), D::decide(&state, cmd)) {
+                (Ok(evts), Ok(again)) if evts == again => {
+                    state = evts.iter().fold(state, D::evolve);
+                    history.extend(evts);
+                }
+                (Err(_), Err(_)) => {}
+                (first, second) => {
+                    return Err(TestCaseError::fail(format!(
+                        "decide is not deterministic for {:?}: {:?} then {:?}",
+                        cmd, first, second
+                    )))
+                }
+            }
+
+            for (name, check) in &self.invariants {
+                if !check(&state) {
+                    return Err(TestCaseError::fail(format!(
+                        "invariant \"{}\" violated after {:?} with state {:?}",
+                        name, cmd, state
+                    )));
+                }
+            }
+        }
+
+        let replayed = history.iter().fold(initial, D::evolve);
+
+        if replayed != state {
+            return Err(TestCaseError::fail(format!(
+                "replayed state {:?} differs from incrementally evolved state {:?}",
+                replayed, state
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use assert_matches::assert_matches;
+    use proptest::{prop_oneof, sample, strategy::Just, test_runner::RngSeed};
+
+    use crate::test_helpers::{
+        deciders::user::{Guitar, User, UserCommand, UserDecider, UserDeciderState, UserName},
+        ValueType,
+    };
+
+    use super::*;
+
+    const SEED: u64 = 0;
+
+    /// `UserDecider` adds every user as user 1 and only checks guitar commands against the state, so the
+    /// sequences start with user 1 added and only ever rename it
+    fn initial() -> UserDeciderState {
+        UserDeciderState::new(HashMap::from([(
+            1,
+            User::new(1, UserName::try_from("Mike").unwrap()),
+        )]))
+    }
+
+    fn user_commands() -> impl Strategy<Value = UserCommand> {
+        let guitar = sample::select(vec!["Ibanez", "Gibson", "Fender"]).prop_map(|brand| Guitar {
+            brand: brand.to_string(),
+        });
+
+        prop_oneof![
+            "[a-zA-Z]{0,12}".prop_map(UserCommand::AddUser),
+            (Just(1usize), "[a-zA-Z]{0,12}")
+                .prop_map(|(id, name)| UserCommand::UpdateUserName(id, name)),
+            (0..3usize, guitar).prop_map(|(id, guitar)| UserCommand::AddGuitar(id, guitar)),
+        ]
+    }
+
+    #[test]
+    fn user_decider_properties() {
+        let res = DeciderProperties::<UserDecider>::new()
+            .invariant("user names are valid", |state: &UserDeciderState| {
+                state
+                    .users
+                    .values()
+                    .all(|user| (1..=10).contains(&user.name.value().len()))
+            })
+            .check(initial(), user_commands());
+
+        assert!(res.is_ok(), "{:?}", res);
+    }
+
+    #[test]
+    fn failing_sequences_are_shrunk() {
+        let res = DeciderProperties::<UserDecider>::new()
+            .with_config(Config {
+                failure_persistence: None,
+                rng_seed: RngSeed::Fixed(SEED),
+                ..Config::default()
+            })
+            .invariant("at most one guitar", |state: &UserDeciderState| {
+                state.users.values().all(|user| user.guitars.len() <= 1)
+            })
+            .check(initial(), user_commands());
+
+        // Shrinking stops at a local minimum, so the seed is fixed to pin down which one
+        match res {
+            Err(TestError::Fail(_, cmds)) => assert_matches!(
+                cmds.as_slice(),
+                [UserCommand::AddGuitar(1, first), UserCommand::AddGuitar(1, second)]
+                    if first.brand == "Gibson" && second.brand == "Ibanez",
+                "{:?}",
+                cmds
+            ),
+            res => panic!("Expected a shrunk failure, got {:?}", res),
+        }
+    }
+}
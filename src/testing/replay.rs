@@ -0,0 +1,242 @@
+use std::{fmt::Debug, io::BufRead};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    decider::Evolver,
+    repository::archive::{error::ArchiveError, ArchiveReader},
+};
+
+use super::diff;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("Archive error {0}")]
+    Archive(#[from] ArchiveError),
+    #[error(
+        "Could not decode event {sequence} of stream {stream} in category {category}: {source}"
+    )]
+    Decode {
+        category: String,
+        stream: Value,
+        sequence: usize,
+        source: serde_json::Error,
+    },
+}
+
+/// A stream the old and the new evolver folded into states the comparison rejected
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence<OldState, NewState> {
+    pub category: String,
+    pub stream: Value,
+    pub events: usize,
+    pub old: OldState,
+    pub new: NewState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport<OldState, NewState> {
+    pub streams: usize,
+    pub events: usize,
+    pub divergences: Vec<Divergence<OldState, NewState>>,
+}
+
+impl<OldState: Debug, NewState: Debug> ReplayReport<OldState, NewState> {
+    /// Fails with a diff of every divergent stream
+    pub fn assert_unchanged(&self) {
+        if self.divergences.is_empty() {
+            return;
+        }
+
+        let diffs: Vec<String> = self
+            .divergences
+            .iter()
+            .map(|divergence| {
+                format!(
+                    "{} {} after {} events\n{}",
+                    divergence.category,
+                    divergence.stream,
+                    divergence.events,
+                    diff(
+                        &format!("{:#?}", divergence.old),
+                        &format!("{:#?}", divergence.new)
+                    )
+                )
+            })
+            .collect();
+
+        panic!(
+            "{} of {} replayed streams diverged\n{}",
+            self.divergences.len(),
+            self.streams,
+            diffs.join("\n\n")
+        );
+    }
+}
+
+/// Replays every stream of an exported archive through the `Old` and the `New` evolver from their default states
+///
+/// `unchanged` decides whether the two final states of a stream are equivalent - the states may be of different
+/// types when the new evolver changes the state shape. Only streams of `category` are replayed when given
+pub fn replay_archive<Old, New, R>(
+    archive: ArchiveReader<R>,
+    category: Option<&str>,
+    unchanged: impl Fn(&Old::State, &New::State) -> bool,
+) -> Result<ReplayReport<Old::State, New::State>, ReplayError>
+where
+    Old: Evolver,
+    Old::State: Default,
+    Old::Evt: DeserializeOwned,
+    New: Evolver,
+    New::State: Default,
+    New::Evt: DeserializeOwned,
+    R: BufRead,
+{
+    let mut report = ReplayReport {
+        streams: 0,
+        events: 0,
+        divergences: vec![],
+    };
+
+    for stream in archive {
+        let stream = stream?;
+
+        if category.is_some_and(|category| category != stream.category) {
+            continue;
+        }
+
+        let mut old = Old::State::default();
+        let mut new = New::State::default();
+
+        for (sequence, event) in stream.events.iter().enumerate() {
+            let decode_error = |source| ReplayError::Decode {
+                category: stream.category.to_owned(),
+                stream: stream.stream.to_owned(),
+                sequence,
+                source,
+            };
+
+            // Decoded once per evolver, so the new evolver may read the payload into a new event type
+            let old_event: Old::Evt =
+                serde_json::from_value(event.payload.to_owned()).map_err(decode_error)?;
+            let new_event: New::Evt =
+                serde_json::from_value(event.payload.to_owned()).map_err(decode_error)?;
+
+            old = Old::evolve(old, &old_event);
+            new = New::evolve(new, &new_event);
+        }
+
+        report.streams += 1;
+        report.events += stream.events.len();
+
+        if !unchanged(&old, &new) {
+            report.divergences.push(Divergence {
+                category: stream.category,
+                stream: stream.stream,
+                events: stream.events.len(),
+                old,
+                new,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        decider::Evolver,
+        repository::{
+            archive::{export_category, ArchiveReader, ArchiveWriter},
+            event::VersionedEventRepositoryWithStreams,
+            in_memory::versioned_with_streams::InMemoryEventRepository,
+            RepositoryVersion,
+        },
+        test_helpers::deciders::user::{User, UserDecider, UserDeciderState, UserEvent, UserName},
+    };
+
+    use super::replay_archive;
+
+    /// Evolver with a regression - renames are dropped
+    struct ForgetfulUserDecider;
+
+    impl Evolver for ForgetfulUserDecider {
+        type State = UserDeciderState;
+        type Evt = UserEvent;
+
+        fn evolve(state: Self::State, event: &Self::Evt) -> Self::State {
+            match event {
+                UserEvent::UserNameUpdated(..) => state,
+                event => UserDecider::evolve(state, event),
+            }
+        }
+    }
+
+    async fn archive() -> Vec<u8> {
+        let mut event_repository = InMemoryEventRepository::<UserEvent>::new("users");
+
+        for (id, renamed) in [(1, false), (2, true)] {
+            let mut events = vec![UserEvent::UserAdded(User::new(
+                id,
+                UserName::try_from("Mike").unwrap(),
+            ))];
+            if renamed {
+                events.push(UserEvent::UserNameUpdated(
+                    id,
+                    UserName::try_from("Mike2").unwrap(),
+                ));
+            }
+
+            event_repository
+                .append(&RepositoryVersion::NoStream, &id.to_string(), &events)
+                .await
+                .unwrap();
+        }
+
+        let mut archive = ArchiveWriter::new(vec![]).unwrap();
+        export_category(&event_repository, "users", &mut archive)
+            .await
+            .unwrap();
+        archive.finish().unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn replay_reports_divergent_streams() {
+        let archive = archive().await;
+
+        let report = replay_archive::<UserDecider, UserDecider, _>(
+            ArchiveReader::new(&archive[..]).unwrap(),
+            None,
+            PartialEq::eq,
+        )
+        .unwrap();
+        assert_eq!((report.streams, report.events), (2, 3));
+        report.assert_unchanged();
+
+        let report = replay_archive::<UserDecider, ForgetfulUserDecider, _>(
+            ArchiveReader::new(&archive[..]).unwrap(),
+            Some("users"),
+            PartialEq::eq,
+        )
+        .unwrap();
+        assert_eq!(report.divergences.len(), 1);
+        assert_eq!(report.divergences[0].stream, json!("2"));
+        assert_eq!(
+            report.divergences[0].new.users[&2].name,
+            UserName::try_from("Mike").unwrap()
+        );
+
+        let report = replay_archive::<UserDecider, ForgetfulUserDecider, _>(
+            ArchiveReader::new(&archive[..]).unwrap(),
+            Some("orders"),
+            PartialEq::eq,
+        )
+        .unwrap();
+        assert_eq!(report.streams, 0);
+    }
+}